@@ -0,0 +1,148 @@
+use crate::{
+    book::OpeningBook,
+    pieces::*,
+    position::Position,
+    tablebase::Tablebases,
+    variant::{Variant, VariantKind},
+};
+
+// A move from one square to another.
+pub type Move = ((u8, u8), (u8, u8));
+
+// Search depth of the computer player, in plies.
+pub const SEARCH_DEPTH: u32 = 3;
+
+// Score for winning the game, e.g. by capturing the king.
+pub const KING_VALUE: i32 = 100_000;
+
+pub fn piece_value(piece_type: PieceType) -> i32 {
+    match piece_type {
+        PieceType::King => KING_VALUE,
+        PieceType::Queen => 900,
+        PieceType::Rook => 500,
+        PieceType::Bishop => 330,
+        PieceType::Knight => 320,
+        PieceType::Pawn => 100,
+        PieceType::Archbishop => 850,
+        PieceType::Chancellor => 900,
+        PieceType::Amazon => 1200,
+        PieceType::Camel => 250,
+    }
+}
+
+// Material balance from `color`'s point of view, with a small bonus for
+// pieces near the centre so the computer doesn't shuffle aimlessly.
+pub fn evaluate(position: &Position, color: PieceColor) -> i32 {
+    let (centre_x, centre_y) = position.state.size.centre();
+    position
+        .pieces
+        .iter()
+        .map(|piece| {
+            let centre_distance =
+                (piece.x as f32 - centre_x).abs() + (piece.y as f32 - centre_y).abs();
+            let score = piece_value(piece.piece_type) + (7. - centre_distance) as i32 * 2;
+            if piece.color == color { score } else { -score }
+        })
+        .sum()
+}
+
+// The computer player's move, and what it promotes to if that's not a
+// queen. Standard games are opened from the book for as long as it has
+// moves, and endings with few enough pieces are played from the
+// tablebases; anything else is searched.
+pub fn computer_move(
+    variant: VariantKind,
+    book: &OpeningBook,
+    tablebases: &Tablebases,
+    position: &Position,
+) -> Option<(Move, Option<PieceType>)> {
+    let known_move = match variant {
+        VariantKind::Standard => book.pick(position).or_else(|| {
+            tablebases
+                .best_move(variant.rules(), position)
+                .map(|mv| (mv, None))
+        }),
+        _ => None,
+    };
+    known_move.or_else(|| {
+        best_move(position, variant.rules(), SEARCH_DEPTH).map(|mv| (mv, None))
+    })
+}
+
+// Best move for the side to move, searching `depth` plies ahead.
+pub fn best_move(position: &Position, variant: &dyn Variant, depth: u32) -> Option<Move> {
+    search(position, variant, depth).map(|(mv, _)| mv)
+}
+
+// Best move for the side to move and its score from that side's point of
+// view, searching `depth` plies ahead. Winning scores are `KING_VALUE` plus
+// the depth left when the game ended, so sooner wins score higher.
+pub fn search(position: &Position, variant: &dyn Variant, depth: u32) -> Option<(Move, i32)> {
+    search_moves(position, variant, depth, ordered_moves(position, variant))
+}
+
+// Like `search`, but only choosing between `moves`.
+pub fn search_moves(
+    position: &Position,
+    variant: &dyn Variant,
+    depth: u32,
+    moves: Vec<Move>,
+) -> Option<(Move, i32)> {
+    let mut best = None;
+    let mut alpha = -i32::MAX;
+
+    for mv in moves {
+        let mut next = position.clone();
+        variant.make_move(&mut next, mv.0, mv.1);
+        let score = -negamax(&next, variant, depth - 1, -i32::MAX, -alpha);
+        if best.is_none() || score > alpha {
+            alpha = score;
+            best = Some(mv);
+        }
+    }
+
+    best.map(|mv| (mv, alpha))
+}
+
+fn negamax(position: &Position, variant: &dyn Variant, depth: u32, mut alpha: i32, beta: i32) -> i32 {
+    let color = position.side_to_move;
+    // The game is over; prefer winning sooner and losing later.
+    if let Some(result) = variant.outcome(position) {
+        return match result.winner {
+            Some(winner) if winner == color => KING_VALUE + depth as i32,
+            Some(_) => -KING_VALUE - depth as i32,
+            None => 0,
+        };
+    }
+    if depth == 0 {
+        return variant.evaluate(position, color);
+    }
+
+    let moves = ordered_moves(position, variant);
+    if moves.is_empty() {
+        return variant.evaluate(position, color);
+    }
+
+    for mv in moves {
+        let mut next = position.clone();
+        variant.make_move(&mut next, mv.0, mv.1);
+        let score = -negamax(&next, variant, depth - 1, -beta, -alpha);
+        if score >= beta {
+            return beta;
+        }
+        alpha = alpha.max(score);
+    }
+
+    alpha
+}
+
+// Moves with the most valuable captures first, so alpha-beta prunes more.
+fn ordered_moves(position: &Position, variant: &dyn Variant) -> Vec<Move> {
+    let mut moves = variant.legal_moves(position);
+    moves.sort_by_key(|(_, to)| {
+        -position
+            .piece_at(*to)
+            .map_or(0, |piece| piece_value(piece.piece_type))
+    });
+    moves
+}
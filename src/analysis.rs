@@ -0,0 +1,743 @@
+use crate::{
+    ai::{search, KING_VALUE},
+    board::{MoveTextInput, PlayerTurn},
+    game::*,
+    history::MoveHistory,
+    notation::{parse_uci, square_name, to_fen, to_san},
+    pieces::*,
+    position::*,
+    tablebase::Tablebases,
+    ui::despawn_screen,
+    variant::{Variant, VariantKind},
+};
+use bevy::prelude::*;
+use shakmaty_syzygy::{Dtz, Wdl};
+use std::{
+    env,
+    io::{BufRead, BufReader, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+};
+
+// Deepest the built-in search goes when analysing; each ply takes a good
+// deal longer than the last.
+const MAX_ANALYSIS_DEPTH: u32 = 4;
+// Moves of the principal variation shown.
+const PV_LENGTH: usize = 8;
+
+pub struct AnalysisPlugin;
+impl Plugin for AnalysisPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Analysis>()
+            .on_state_enter(STATE_STAGE, AppState::Playing, start_analysis.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, toggle_analysis.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, analyse_position.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, eval_panel_update.system())
+            .on_state_exit(STATE_STAGE, AppState::Playing, stop_analysis.system())
+            .on_state_exit(STATE_STAGE, AppState::GameOver, despawn_screen::<EvalPanel>.system());
+    }
+}
+
+// An evaluation from white's point of view.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Score {
+    Centipawns(i32),
+    // Mate in this many moves; negative when black mates.
+    Mate(i32),
+    // Known from the tablebases: 1 when white wins, -1 when black does and
+    // 0 for a draw.
+    Tablebase(i32),
+}
+
+impl Score {
+    pub fn describe(&self) -> String {
+        match self {
+            Score::Centipawns(cp) => format!("{:+.2}", *cp as f32 / 100.),
+            Score::Mate(moves) => format!("#{}", moves),
+            Score::Tablebase(1) => "1-0".to_string(),
+            Score::Tablebase(-1) => "0-1".to_string(),
+            Score::Tablebase(_) => "=".to_string(),
+        }
+    }
+
+    // How much of the evaluation bar is white's, from 0 to 1; a pawn up
+    // fills about three fifths of it.
+    pub fn white_share(&self) -> f32 {
+        match *self {
+            Score::Centipawns(cp) => 1. / (1. + (-0.004 * cp as f32).exp()),
+            Score::Mate(moves) | Score::Tablebase(moves) if moves > 0 => 1.,
+            Score::Mate(_) => 0.,
+            Score::Tablebase(result) if result < 0 => 0.,
+            Score::Tablebase(_) => 0.5,
+        }
+    }
+
+    fn flip(self) -> Score {
+        match self {
+            Score::Centipawns(cp) => Score::Centipawns(-cp),
+            Score::Mate(moves) => Score::Mate(-moves),
+            Score::Tablebase(result) => Score::Tablebase(-result),
+        }
+    }
+}
+
+// What the engine found for the position being analysed.
+pub struct Evaluation {
+    pub depth: u32,
+    pub score: Score,
+    // The principal variation in SAN.
+    pub line: Vec<String>,
+    // The tablebases' verdict, e.g. "Win (DTZ 23)", when they gave the
+    // evaluation instead of a search.
+    pub verdict: Option<String>,
+}
+
+// A search result as it comes from the engine: the score from the side to
+// move's point of view and the line in UCI, to be read against the position
+// of analysis `generation`.
+struct AnalysisUpdate {
+    generation: u64,
+    depth: u32,
+    score: Score,
+    pv: Vec<String>,
+}
+
+enum Engine {
+    BuiltIn,
+    Uci(UciEngine),
+}
+
+// An external engine talking UCI, analysing with "go infinite".
+struct UciEngine {
+    child: Child,
+    stdin: ChildStdin,
+    searching: bool,
+    // Searches stopped whose "bestmove" hasn't come back yet; their output
+    // is for an old position and gets dropped.
+    stale: Arc<AtomicUsize>,
+    generation: Arc<AtomicU64>,
+    // Whether the engine has been told to play Chess960.
+    chess960: bool,
+}
+
+impl UciEngine {
+    fn start(path: &str, sender: mpsc::Sender<AnalysisUpdate>) -> std::io::Result<Self> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        writeln!(stdin, "uci\nsetoption name UCI_AnalyseMode value true\nisready")?;
+
+        let stale = Arc::new(AtomicUsize::new(0));
+        let generation = Arc::new(AtomicU64::new(0));
+        let (reader_stale, reader_generation) = (stale.clone(), generation.clone());
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => return,
+                };
+                if line.starts_with("bestmove") {
+                    let _ = reader_stale.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                        n.checked_sub(1)
+                    });
+                } else if reader_stale.load(Ordering::SeqCst) == 0 {
+                    if let Some((depth, score, pv)) = parse_info(&line) {
+                        let update = AnalysisUpdate {
+                            generation: reader_generation.load(Ordering::SeqCst),
+                            depth,
+                            score,
+                            pv,
+                        };
+                        if sender.send(update).is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            child,
+            stdin,
+            searching: false,
+            stale,
+            generation,
+            chess960: false,
+        })
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), String> {
+        writeln!(self.stdin, "{}", command)
+            .map_err(|err| format!("Couldn't send \"{}\" to the UCI engine: {}", command, err))
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let _ = self.child.wait();
+    }
+}
+
+// Reads the depth, score and principal variation out of a UCI "info" line,
+// if it has them all.
+fn parse_info(line: &str) -> Option<(u32, Score, Vec<String>)> {
+    let mut tokens = line.split_whitespace();
+    if tokens.next()? != "info" {
+        return None;
+    }
+    let (mut depth, mut score, mut pv) = (None, None, Vec::new());
+    while let Some(token) = tokens.next() {
+        match token {
+            "depth" => depth = tokens.next()?.parse().ok(),
+            "score" => {
+                score = match (tokens.next()?, tokens.next()?.parse().ok()?) {
+                    ("cp", cp) => Some(Score::Centipawns(cp)),
+                    ("mate", moves) => Some(Score::Mate(moves)),
+                    _ => None,
+                }
+            }
+            // The line runs to the end of the info.
+            "pv" => {
+                pv = tokens.map(|mv| mv.to_string()).collect();
+                break;
+            }
+            _ => {}
+        }
+    }
+    if pv.is_empty() {
+        return None;
+    }
+    Some((depth?, score?, pv))
+}
+
+// Analysis of the position on the board, by the built-in search or, when
+// UCI_ENGINE names one, an external UCI engine.
+pub struct Analysis {
+    pub enabled: bool,
+    engine: Engine,
+    // The position being analysed, as FEN, and its latest evaluation.
+    fen: String,
+    generation: u64,
+    pub evaluation: Option<Evaluation>,
+    // What went wrong with the external engine, shown on the panel.
+    pub error: Option<String>,
+    // Tells the built-in search to give up on its position.
+    cancel: Arc<AtomicBool>,
+    sender: Mutex<mpsc::Sender<AnalysisUpdate>>,
+    updates: Mutex<mpsc::Receiver<AnalysisUpdate>>,
+}
+
+impl Default for Analysis {
+    fn default() -> Self {
+        let (sender, updates) = mpsc::channel();
+        let mut error = None;
+        let engine = match env::var("UCI_ENGINE") {
+            Ok(path) => match UciEngine::start(&path, sender.clone()) {
+                Ok(engine) => Engine::Uci(engine),
+                Err(err) => {
+                    error = Some(format!("Couldn't start UCI engine {}: {}", path, err));
+                    Engine::BuiltIn
+                }
+            },
+            Err(_) => Engine::BuiltIn,
+        };
+
+        Self {
+            enabled: false,
+            engine,
+            fen: String::new(),
+            generation: 0,
+            evaluation: None,
+            error,
+            cancel: Arc::new(AtomicBool::new(false)),
+            sender: Mutex::new(sender),
+            updates: Mutex::new(updates),
+        }
+    }
+}
+
+impl Analysis {
+    // Starts analysing `position`, dropping whatever was being analysed.
+    // External engines only play standard chess, from the usual start or a
+    // Chess960 one; other variants are left to the built-in search.
+    fn analyse(
+        &mut self,
+        position: &Position,
+        variant: &'static dyn Variant,
+        standard: bool,
+        chess960: bool,
+    ) {
+        self.stop();
+        self.generation += 1;
+        self.evaluation = None;
+
+        match &mut self.engine {
+            Engine::Uci(engine) if standard => {
+                engine.generation.store(self.generation, Ordering::SeqCst);
+                let mut commands = String::new();
+                if engine.chess960 != chess960 {
+                    commands += &format!("setoption name UCI_Chess960 value {}\n", chess960);
+                }
+                commands += &format!("position fen {}\ngo infinite", self.fen);
+                match engine.send(&commands) {
+                    Ok(()) => {
+                        engine.chess960 = chess960;
+                        engine.searching = true;
+                    }
+                    Err(err) => self.error = Some(err),
+                }
+            }
+            _ => {
+                let cancel = Arc::new(AtomicBool::new(false));
+                self.cancel = cancel.clone();
+                let sender = self.sender.lock().unwrap().clone();
+                let generation = self.generation;
+                let position = position.clone();
+                thread::spawn(move || {
+                    for depth in 1..=MAX_ANALYSIS_DEPTH {
+                        let (score, pv) = match principal_variation(&position, variant, depth) {
+                            Some(result) => result,
+                            None => return,
+                        };
+                        if cancel.load(Ordering::SeqCst) {
+                            return;
+                        }
+                        let update = AnalysisUpdate { generation, depth, score, pv };
+                        if sender.send(update).is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        }
+    }
+
+    // Stops the search under way, if any.
+    fn stop(&mut self) {
+        self.cancel.store(true, Ordering::SeqCst);
+        if let Engine::Uci(engine) = &mut self.engine {
+            if engine.searching {
+                engine.stale.fetch_add(1, Ordering::SeqCst);
+                if let Err(err) = engine.send("stop") {
+                    self.error = Some(err);
+                }
+                engine.searching = false;
+            }
+        }
+    }
+
+    // Takes in the results found since last time for `position`, the one
+    // being analysed.
+    fn receive(&mut self, position: &Position, variant: &dyn Variant) {
+        let updates: Vec<AnalysisUpdate> = self.updates.lock().unwrap().try_iter().collect();
+        let generation = self.generation;
+        for update in updates.into_iter().filter(|update| update.generation == generation) {
+            // Engines score for the side to move.
+            let score = match position.side_to_move {
+                PieceColor::White => update.score,
+                PieceColor::Black => update.score.flip(),
+            };
+
+            let mut line = Vec::new();
+            let mut next = position.clone();
+            for mv in update.pv.iter().take(PV_LENGTH) {
+                let (from, to) = match parse_uci(mv) {
+                    Some((from, to)) if variant.is_move_valid(&next, from, to) => (from, to),
+                    _ => break,
+                };
+                line.push(to_san(&next, (from, to)));
+                variant.make_move(&mut next, from, to);
+            }
+
+            self.evaluation = Some(Evaluation {
+                depth: update.depth,
+                score,
+                line,
+                verdict: None,
+            });
+        }
+    }
+
+    // Shows `evaluation` for the position now being analysed, stopping any
+    // search so its results don't replace it.
+    fn show(&mut self, evaluation: Evaluation) {
+        self.stop();
+        self.generation += 1;
+        self.evaluation = Some(evaluation);
+    }
+}
+
+// The tablebases' evaluation of `position`, with the line they'd play out,
+// when they cover it.
+fn tablebase_evaluation(
+    tablebases: &Tablebases,
+    position: &Position,
+    variant: &dyn Variant,
+) -> Option<Evaluation> {
+    let (wdl, Dtz(dtz)) = tablebases.probe(position)?;
+    // Cursed wins and blessed losses are drawn by the fifty-move rule.
+    let result = match wdl {
+        Wdl::Win => 1,
+        Wdl::Loss => -1,
+        Wdl::CursedWin | Wdl::BlessedLoss | Wdl::Draw => 0,
+    };
+    let score = match position.side_to_move {
+        PieceColor::White => Score::Tablebase(result),
+        PieceColor::Black => Score::Tablebase(-result),
+    };
+
+    let mut line = Vec::new();
+    let mut next = position.clone();
+    while line.len() < PV_LENGTH {
+        let (from, to) = match tablebases.best_move(variant, &next) {
+            Some(mv) => mv,
+            None => break,
+        };
+        line.push(to_san(&next, (from, to)));
+        variant.make_move(&mut next, from, to);
+    }
+
+    Some(Evaluation {
+        depth: dtz.unsigned_abs(),
+        score,
+        line,
+        verdict: tablebases.verdict(position),
+    })
+}
+
+// The built-in search's score for `position` (from the side to move's point
+// of view) and the line it expects, searching `depth` plies.
+fn principal_variation(
+    position: &Position,
+    variant: &dyn Variant,
+    depth: u32,
+) -> Option<(Score, Vec<String>)> {
+    let (first, score) = search(position, variant, depth)?;
+
+    let mut pv = vec![first];
+    let mut next = position.clone();
+    variant.make_move(&mut next, first.0, first.1);
+    for remaining in (1..depth).rev() {
+        if variant.outcome(&next).is_some() {
+            break;
+        }
+        match search(&next, variant, remaining) {
+            Some((mv, _)) => {
+                variant.make_move(&mut next, mv.0, mv.1);
+                pv.push(mv);
+            }
+            None => break,
+        }
+    }
+
+    // A won game scores `KING_VALUE` plus the depth left when it was won,
+    // which gives how many plies away the king falls.
+    let score = if score.abs() >= KING_VALUE {
+        let plies = depth as i32 - (score.abs() - KING_VALUE);
+        Score::Mate(score.signum() * (plies + 1) / 2)
+    } else {
+        Score::Centipawns(score)
+    };
+    let pv = pv
+        .into_iter()
+        .map(|(from, to)| format!("{}{}", square_name(from), square_name(to)))
+        .collect();
+    Some((score, pv))
+}
+
+// Analysis starts on in analysis games; elsewhere it's switched on with V.
+fn start_analysis(setup: Res<GameSetup>, mut analysis: ResMut<Analysis>) {
+    analysis.enabled = setup.mode == GameMode::Analysis;
+    analysis.fen.clear();
+}
+
+fn stop_analysis(mut analysis: ResMut<Analysis>) {
+    analysis.stop();
+    analysis.fen.clear();
+}
+
+// Pressing V switches the analysis on and off, except in puzzles, which it
+// would give away.
+fn toggle_analysis(
+    keyboard_input: Res<Input<KeyCode>>,
+    move_text: Res<MoveTextInput>,
+    setup: Res<GameSetup>,
+    mut analysis: ResMut<Analysis>,
+) {
+    if move_text.focused
+        || setup.mode == GameMode::Puzzles
+        || !keyboard_input.just_pressed(KeyCode::V)
+    {
+        return;
+    }
+    analysis.enabled = !analysis.enabled;
+}
+
+// Restarts the analysis whenever the position changes, and collects its
+// results. Endings the tablebases cover are looked up rather than searched.
+// Nothing is analysed while the game is paused.
+fn analyse_position(
+    setup: Res<GameSetup>,
+    tablebases: Res<Tablebases>,
+    paused: Res<Paused>,
+    turn: Res<PlayerTurn>,
+    board_state: Res<BoardState>,
+    history: Res<MoveHistory>,
+    mut analysis: ResMut<Analysis>,
+    pieces_query: Query<&Piece>,
+) {
+    if !analysis.enabled || paused.0 {
+        if !analysis.fen.is_empty() {
+            analysis.stop();
+            analysis.fen.clear();
+        }
+        return;
+    }
+
+    let position = history.shown_position().cloned().unwrap_or_else(|| Position {
+        pieces: pieces_query.iter().copied().collect(),
+        side_to_move: turn.0,
+        state: board_state.clone(),
+    });
+    let fen = to_fen(&position, false);
+    let variant = setup.variant.rules();
+    if fen != analysis.fen {
+        analysis.fen = fen;
+        let standard = setup.variant == VariantKind::Standard;
+        let chess960 = matches!(setup.start_position, StartPosition::Chess960(_));
+        let known = if standard {
+            tablebase_evaluation(&tablebases, &position, variant)
+        } else {
+            None
+        };
+        match known {
+            Some(evaluation) => analysis.show(evaluation),
+            None => analysis.analyse(&position, variant, standard, chess960),
+        }
+    }
+    analysis.receive(&position, variant);
+}
+
+// Root nodes of the evaluation bar and the best-line panel.
+struct EvalPanel;
+// White's part of the evaluation bar, which fills it from the bottom.
+struct EvalFill;
+struct EvalText;
+struct LineText;
+
+// Shows the evaluation bar and best line while analysis is on.
+fn eval_panel_update(
+    commands: &mut Commands,
+    asset_server: Res<AssetServer>,
+    analysis: Res<Analysis>,
+    paused: Res<Paused>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+    panel_query: Query<Entity, With<EvalPanel>>,
+    mut fill_query: Query<&mut Style, With<EvalFill>>,
+    mut text_queries: QuerySet<(
+        Query<&mut Text, With<EvalText>>,
+        Query<&mut Text, With<LineText>>,
+    )>,
+) {
+    let spawned = panel_query.iter().next().is_some();
+    if spawned != analysis.enabled {
+        if spawned {
+            for entity in panel_query.iter() {
+                commands.despawn_recursive(entity);
+            }
+        } else {
+            spawn_eval_panel(commands, &asset_server, &mut color_materials);
+        }
+        return;
+    }
+
+    let share = analysis
+        .evaluation
+        .as_ref()
+        .map_or(0.5, |evaluation| evaluation.score.white_share());
+    for mut style in fill_query.iter_mut() {
+        style.size.height = Val::Percent(share * 100.);
+    }
+
+    let (eval, line) = match (&analysis.evaluation, paused.0) {
+        (_, true) => (String::new(), "Analysis paused".to_string()),
+        (Some(evaluation), _) => (
+            evaluation.score.describe(),
+            match &evaluation.verdict {
+                Some(verdict) => format!("Tablebase {}: {}", verdict, evaluation.line.join(" ")),
+                None => format!("Depth {}: {}", evaluation.depth, evaluation.line.join(" ")),
+            },
+        ),
+        (None, _) => (String::new(), "Analysing...".to_string()),
+    };
+    let line = match &analysis.error {
+        Some(error) => format!("{}\n{}", error, line),
+        None => line,
+    };
+    for mut text in text_queries.q0_mut().iter_mut() {
+        if text.value != eval {
+            text.value = eval.clone();
+        }
+    }
+    for mut text in text_queries.q1_mut().iter_mut() {
+        if text.value != line {
+            text.value = line.clone();
+        }
+    }
+}
+
+fn spawn_eval_panel(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    color_materials: &mut Assets<ColorMaterial>,
+) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let text = |font_size: f32| Text {
+        value: String::new(),
+        font: font.clone(),
+        style: TextStyle {
+            font_size,
+            color: Color::rgb(0.8, 0.8, 0.8),
+            ..Default::default()
+        },
+    };
+
+    // The bar, down the left edge beside the board, with the score below it.
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.),
+                    top: Val::Percent(25.),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: color_materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .with(EvalPanel)
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(20.), Val::Px(300.)),
+                        flex_direction: FlexDirection::Column,
+                        ..Default::default()
+                    },
+                    material: color_materials.add(Color::rgb(0.15, 0.15, 0.15).into()),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(100.), Val::Percent(50.)),
+                                ..Default::default()
+                            },
+                            material: color_materials.add(Color::rgb(0.9, 0.9, 0.9).into()),
+                            ..Default::default()
+                        })
+                        .with(EvalFill);
+                });
+            parent
+                .spawn(TextBundle {
+                    text: text(20.0),
+                    ..Default::default()
+                })
+                .with(EvalText);
+        });
+
+    // The best line, above the move text box.
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.),
+                    bottom: Val::Px(50.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            material: color_materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .with(EvalPanel)
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle {
+                    text: text(22.0),
+                    ..Default::default()
+                })
+                .with(LineText);
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pv(moves: &str) -> Vec<String> {
+        moves.split_whitespace().map(|mv| mv.to_string()).collect()
+    }
+
+    #[test]
+    fn info_lines() {
+        assert!(
+            parse_info("info depth 12 seldepth 18 multipv 1 score cp 34 nodes 52010 pv e2e4 e7e5 g1f3")
+                == Some((12, Score::Centipawns(34), pv("e2e4 e7e5 g1f3")))
+        );
+        assert!(
+            parse_info("info depth 20 score mate -3 time 80 pv f7f6 d1h5 g7g6")
+                == Some((20, Score::Mate(-3), pv("f7f6 d1h5 g7g6")))
+        );
+        // Bounds and other tokens between the score and the line are skipped.
+        assert!(
+            parse_info("info depth 9 score cp -120 upperbound hashfull 3 pv d7d5")
+                == Some((9, Score::Centipawns(-120), pv("d7d5")))
+        );
+    }
+
+    #[test]
+    fn incomplete_info_lines() {
+        assert!(parse_info("info string NNUE evaluation enabled").is_none());
+        assert!(parse_info("info depth 7 currmove e2e4 currmovenumber 1").is_none());
+        assert!(parse_info("info depth 7 score cp 20").is_none());
+        assert!(parse_info("info score cp 20 pv e2e4").is_none());
+        assert!(parse_info("info depth 7 score wdl 300 pv e2e4").is_none());
+        assert!(parse_info("bestmove e2e4 ponder e7e5").is_none());
+    }
+
+    #[test]
+    fn scores() {
+        assert_eq!(Score::Centipawns(34).describe(), "+0.34");
+        assert_eq!(Score::Centipawns(-120).describe(), "-1.20");
+        assert_eq!(Score::Mate(3).describe(), "#3");
+        assert_eq!(Score::Mate(-2).describe(), "#-2");
+        assert_eq!(Score::Tablebase(1).describe(), "1-0");
+        assert_eq!(Score::Tablebase(0).describe(), "=");
+
+        assert!(Score::Centipawns(34).flip() == Score::Centipawns(-34));
+        assert!(Score::Mate(-3).flip() == Score::Mate(3));
+        assert!(Score::Tablebase(1).flip() == Score::Tablebase(-1));
+
+        assert!((Score::Centipawns(0).white_share() - 0.5).abs() < 1e-6);
+        assert!(Score::Centipawns(100).white_share() > 0.5);
+        assert!(Score::Centipawns(-100).white_share() < 0.5);
+        assert!(Score::Mate(2).white_share() == 1.);
+        assert!(Score::Mate(-2).white_share() == 0.);
+        assert!(Score::Tablebase(0).white_share() == 0.5);
+    }
+}
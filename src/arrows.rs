@@ -0,0 +1,289 @@
+use crate::{
+    ai::{best_move, Move, SEARCH_DEPTH},
+    board::{MoveTextInput, PlayerTurn, Square},
+    game::*,
+    history::MoveHistory,
+    notation::to_fen,
+    pieces::*,
+    position::*,
+};
+use bevy::{
+    prelude::*,
+    render::{mesh::Indices, pipeline::PrimitiveTopology},
+};
+use bevy_mod_picking::*;
+
+// Plies searched for the opponent's threat; it only has to be seen, not
+// played, so this is kept quick.
+const THREAT_DEPTH: u32 = 2;
+
+// Sizes of an arrow, in squares.
+const SHAFT_WIDTH: f32 = 0.15;
+const HEAD_WIDTH: f32 = 0.45;
+const HEAD_LENGTH: f32 = 0.4;
+
+pub struct ArrowsPlugin;
+impl Plugin for ArrowsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<BoardArrows>()
+            .init_resource::<ArrowMaterials>()
+            .on_state_enter(STATE_STAGE, AppState::Playing, clear_arrows.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, hint_and_threat_keys.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, follow_position.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, draw_user_arrows.system())
+            .on_state_update(STATE_STAGE, AppState::GameOver, draw_user_arrows.system())
+            .on_state_exit(STATE_STAGE, AppState::GameOver, clear_arrows.system())
+            .add_system(spawn_arrows.system());
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ArrowKind {
+    // The move the search suggests, on request.
+    Hint,
+    // What the opponent would play if it were their move.
+    Threat,
+    // Drawn by the player with the right mouse button.
+    User,
+}
+
+// An arrow on the board from one square to another.
+struct Arrow;
+
+// The arrows to draw on the board.
+#[derive(Default)]
+struct BoardArrows {
+    hint: Option<Move>,
+    threat: Option<Move>,
+    show_threat: bool,
+    user: Vec<Move>,
+}
+
+impl BoardArrows {
+    fn all(&self) -> Vec<(Move, ArrowKind)> {
+        let mut arrows: Vec<(Move, ArrowKind)> =
+            self.user.iter().map(|mv| (*mv, ArrowKind::User)).collect();
+        arrows.extend(self.threat.filter(|_| self.show_threat).map(|mv| (mv, ArrowKind::Threat)));
+        arrows.extend(self.hint.map(|mv| (mv, ArrowKind::Hint)));
+        arrows
+    }
+}
+
+struct ArrowMaterials {
+    hint: Handle<StandardMaterial>,
+    threat: Handle<StandardMaterial>,
+    user: Handle<StandardMaterial>,
+}
+
+impl FromResources for ArrowMaterials {
+    fn from_resources(resources: &Resources) -> Self {
+        let mut materials = resources.get_mut::<Assets<StandardMaterial>>().unwrap();
+        let mut unlit = |color: Color| {
+            materials.add(StandardMaterial {
+                albedo: color,
+                shaded: false,
+                ..Default::default()
+            })
+        };
+        ArrowMaterials {
+            hint: unlit(Color::rgb(0.2, 0.7, 0.3)),
+            threat: unlit(Color::rgb(0.85, 0.2, 0.15)),
+            user: unlit(Color::rgb(0.95, 0.65, 0.1)),
+        }
+    }
+}
+
+impl ArrowMaterials {
+    fn get(&self, kind: ArrowKind) -> Handle<StandardMaterial> {
+        match kind {
+            ArrowKind::Hint => self.hint.clone(),
+            ArrowKind::Threat => self.threat.clone(),
+            ArrowKind::User => self.user.clone(),
+        }
+    }
+}
+
+fn clear_arrows(mut arrows: ResMut<BoardArrows>) {
+    *arrows = BoardArrows::default();
+}
+
+fn current_position(
+    history: &MoveHistory,
+    turn: &PlayerTurn,
+    board_state: &BoardState,
+    pieces_query: &Query<&Piece>,
+) -> Position {
+    history.shown_position().cloned().unwrap_or_else(|| Position {
+        pieces: pieces_query.iter().copied().collect(),
+        side_to_move: turn.0,
+        state: board_state.clone(),
+    })
+}
+
+// The opponent's best move were it their turn now.
+fn threat(position: &Position, setup: &GameSetup) -> Option<Move> {
+    let mut passed = position.clone();
+    passed.side_to_move = opponent(position.side_to_move);
+    passed.state.en_passant = None;
+    best_move(&passed, setup.variant.rules(), THREAT_DEPTH)
+}
+
+// H shows the search's move for the side to move; X shows and hides the
+// opponent's threat.
+fn hint_and_threat_keys(
+    keyboard_input: Res<Input<KeyCode>>,
+    move_text: Res<MoveTextInput>,
+    setup: Res<GameSetup>,
+    turn: Res<PlayerTurn>,
+    board_state: Res<BoardState>,
+    history: Res<MoveHistory>,
+    mut arrows: ResMut<BoardArrows>,
+    pieces_query: Query<&Piece>,
+) {
+    if move_text.focused {
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::H) {
+        let position = current_position(&history, &turn, &board_state, &pieces_query);
+        arrows.hint = best_move(&position, setup.variant.rules(), SEARCH_DEPTH);
+    }
+    if keyboard_input.just_pressed(KeyCode::X) {
+        arrows.show_threat = !arrows.show_threat;
+        arrows.threat = if arrows.show_threat {
+            threat(&current_position(&history, &turn, &board_state, &pieces_query), &setup)
+        } else {
+            None
+        };
+    }
+}
+
+// Once a move is made the hint has served its purpose, and the threat is
+// looked for again.
+fn follow_position(
+    setup: Res<GameSetup>,
+    turn: Res<PlayerTurn>,
+    board_state: Res<BoardState>,
+    history: Res<MoveHistory>,
+    mut fen: Local<String>,
+    mut arrows: ResMut<BoardArrows>,
+    pieces_query: Query<&Piece>,
+) {
+    let position = current_position(&history, &turn, &board_state, &pieces_query);
+    let current = to_fen(&position, false);
+    if *fen == current {
+        return;
+    }
+    *fen = current;
+
+    arrows.hint = None;
+    if arrows.show_threat {
+        arrows.threat = threat(&position, &setup);
+    }
+}
+
+// Dragging with the right mouse button from one square to another draws an
+// arrow between them, or rubs out the one already there; a left click
+// clears them all.
+fn draw_user_arrows(
+    pick_state: Res<PickState>,
+    mouse_button_inputs: Res<Input<MouseButton>>,
+    mut drag_start: Local<Option<(u8, u8)>>,
+    mut arrows: ResMut<BoardArrows>,
+    squares_query: Query<&Square>,
+) {
+    let hovered = pick_state
+        .top(Group::default())
+        .and_then(|(entity, _)| squares_query.get(*entity).ok())
+        .map(|square| (square.x, square.y));
+
+    if mouse_button_inputs.just_pressed(MouseButton::Left) && !arrows.user.is_empty() {
+        arrows.user.clear();
+    }
+    if mouse_button_inputs.just_pressed(MouseButton::Right) {
+        *drag_start = hovered;
+    }
+    if mouse_button_inputs.just_released(MouseButton::Right) {
+        let mv = match (drag_start.take(), hovered) {
+            (Some(from), Some(to)) if from != to => (from, to),
+            _ => return,
+        };
+        match arrows.user.iter().position(|drawn| *drawn == mv) {
+            Some(idx) => {
+                arrows.user.remove(idx);
+            }
+            None => arrows.user.push(mv),
+        }
+    }
+}
+
+// Respawns the arrow meshes whenever the arrows change, laid between the
+// centres of their squares.
+fn spawn_arrows(
+    commands: &mut Commands,
+    arrows: ChangedRes<BoardArrows>,
+    materials: Res<ArrowMaterials>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    arrow_query: Query<Entity, With<Arrow>>,
+    squares_query: Query<(&Square, &Transform)>,
+) {
+    for entity in arrow_query.iter() {
+        commands.despawn(entity);
+    }
+
+    let centre = |(x, y): (u8, u8)| {
+        squares_query
+            .iter()
+            .find(|(square, _)| square.x == x && square.y == y)
+            .map(|(_, transform)| transform.translation)
+    };
+    for (idx, ((from, to), kind)) in arrows.all().into_iter().enumerate() {
+        let (start, end) = match (centre(from), centre(to)) {
+            (Some(start), Some(end)) => (start, end),
+            _ => continue,
+        };
+        let direction = end - start;
+        // Just above the board, each arrow a little over the last so
+        // crossing arrows don't flicker.
+        let lift = Vec3::new(0., 0.02 + idx as f32 * 0.002, 0.);
+        let rotation = Quat::from_rotation_y((-direction.z).atan2(direction.x));
+
+        commands
+            .spawn(PbrBundle {
+                mesh: meshes.add(arrow_mesh(direction.length())),
+                material: materials.get(kind),
+                transform: Transform {
+                    translation: start + lift,
+                    rotation,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .with(Arrow);
+    }
+}
+
+// A flat arrow lying along +x from the origin, `length` long and facing up.
+fn arrow_mesh(length: f32) -> Mesh {
+    let head_length = HEAD_LENGTH.min(length / 2.);
+    let shaft_end = length - head_length;
+    let (shaft, head) = (SHAFT_WIDTH / 2., HEAD_WIDTH / 2.);
+
+    let positions = vec![
+        [0., 0., -shaft],
+        [shaft_end, 0., -shaft],
+        [shaft_end, 0., shaft],
+        [0., 0., shaft],
+        [shaft_end, 0., -head],
+        [length, 0., 0.],
+        [shaft_end, 0., head],
+    ];
+    let normals = vec![[0., 1., 0.]; positions.len()];
+    let uvs = vec![[0., 0.]; positions.len()];
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.set_indices(Some(Indices::U32(vec![0, 3, 2, 0, 2, 1, 4, 6, 5])));
+    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh
+}
@@ -1,16 +1,44 @@
-use bevy::{app::AppExit, prelude::*};
+use bevy::prelude::*;
 use bevy_mod_picking::*;
-use crate::pieces::*;
+use crate::{
+    ai,
+    book::OpeningBook,
+    game::*,
+    history::MoveHistory,
+    input::*,
+    moves::*,
+    notation::{parse_move, parse_promotion, square_name, to_fen},
+    pieces::*,
+    position::*,
+    puzzle::{PuzzleProgress, PuzzleTrainer},
+    tablebase::Tablebases,
+    theme::*,
+    variant::Variant,
+    BoardView,
+};
 
 pub struct BoardPlugin;
 impl Plugin for BoardPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.init_resource::<SelectedSquare>()
-            .init_resource::<SelectedPiece>()
-            .init_resource::<PlayerTurn>()
-            .add_startup_system(create_board.system())
+        app.init_resource::<PlayerTurn>()
+            .init_resource::<BoardState>()
+            .init_resource::<BoardCursor>()
+            .init_resource::<MoveTextInput>()
+            .on_state_enter(STATE_STAGE, AppState::Editor, create_board.system())
+            .on_state_exit(STATE_STAGE, AppState::Editor, despawn_board.system())
+            .on_state_enter(STATE_STAGE, AppState::Playing, create_board.system())
+            .on_state_exit(STATE_STAGE, AppState::GameOver, despawn_board.system())
             .add_system(color_squares.system())
-            .add_system(select_square.system());
+            .add_system(texture_squares.system())
+            .on_state_enter(STATE_STAGE, AppState::Playing, reset_selection.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, select_square.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, move_cursor.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, keyboard_select.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, choose_promotion.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, computer_move.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, puzzle_reply.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, check_puzzle_moves.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, export_fen.system());
     }
 }
 
@@ -21,8 +49,13 @@ pub struct Square {
 }
 
 impl Square {
+    // Algebraic name of the square, e.g. "e4".
+    pub fn name(&self) -> String {
+        square_name((self.x, self.y))
+    }
+
     fn is_white(&self) -> bool {
-        (self.x + self.y + 1) % 2 == 0
+        (self.x + self.y) % 2 == 1
     }
 }
 
@@ -32,164 +65,552 @@ impl PartialEq<Piece> for Square {
     }
 }
 
-#[derive(Default)]
-struct SelectedSquare {
-    entity: Option<Entity>,
+// Square under the keyboard cursor, moved with the arrow keys. Hidden until
+// the keyboard is first used, and again once the mouse is.
+pub struct BoardCursor {
+    pub x: u8,
+    pub y: u8,
+    pub visible: bool,
+}
+
+impl Default for BoardCursor {
+    fn default() -> Self {
+        // Start on the white king's pawn.
+        Self { x: 1, y: 4, visible: false }
+    }
 }
 
+// Move typed into the UI text box, in SAN or UCI notation.
 #[derive(Default)]
-struct SelectedPiece {
-    entity: Option<Entity>,
+pub struct MoveTextInput {
+    pub text: String,
+    pub focused: bool,
+    pub error: Option<String>,
+    // Something to tell the player in the text box's place while it's not
+    // in use, such as an exported FEN.
+    pub notice: Option<String>,
 }
 
+// Lays out the squares for the game (or position editor) about to start, at
+// the size its starting position asks for.
 fn create_board(
     commands: &mut Commands,
+    themes: Res<Themes>,
+    asset_server: Res<AssetServer>,
+    setup: Res<GameSetup>,
+    mut cursor: ResMut<BoardCursor>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
-    // Mesh used for each square, and color defs for white/black squares
+    // Mesh used for each square; each square gets its own material so it can
+    // be highlighted on its own.
     let mesh: Handle<Mesh> = meshes.add(Mesh::from(shape::Plane { size: 1. }));
+    let theme = themes.current();
+    let size = setup.board_size();
 
-    for i in 0..8 {
-        for j in 0..8 {
-            // Create mesh for a square on the board
-            commands
-                .spawn(PbrBundle {
-                    mesh: mesh.clone(),
-                    material: match (i + j) % 2 {
-                        0 => materials.add(Color::rgb(1., 0.9, 0.9).into()),
-                        _ => materials.add(Color::rgb(0., 0.1, 0.1).into())
-                    },
-                    // Squares are of size 1; simply lay them out with loop vars as coords
-                    transform: Transform::from_translation(Vec3::new(i as f32, 0., j as f32)),
-                    ..Default::default()
-                })
-                .with(PickableMesh::default())
-                .with(Square { x: i, y: j, });
-        }
-    };
+    for (i, j) in size.squares() {
+        let square = Square { x: i, y: j };
+        let (color, texture) = if square.is_white() {
+            (theme.white_square, &theme.white_square_texture)
+        } else {
+            (theme.black_square, &theme.black_square_texture)
+        };
+        let material = StandardMaterial {
+            albedo: rgb(color),
+            albedo_texture: load_texture(&asset_server, texture),
+            ..Default::default()
+        };
+
+        // Create mesh for a square on the board
+        commands
+            .spawn(PbrBundle {
+                mesh: mesh.clone(),
+                material: materials.add(material),
+                // Squares are of size 1; simply lay them out with loop vars as coords
+                transform: Transform::from_translation(Vec3::new(i as f32, 0., j as f32)),
+                ..Default::default()
+            })
+            .with(PickableMesh::default())
+            .with(square);
+    }
+
+    // Keep the keyboard cursor on the board.
+    cursor.x = cursor.x.min(size.ranks - 1);
+    cursor.y = cursor.y.min(size.files - 1);
+}
+
+// Clears the squares of the last game away; the next one may differ in size.
+fn despawn_board(commands: &mut Commands, query: Query<Entity, With<Square>>) {
+    for entity in query.iter() {
+        commands.despawn(entity);
+    }
 }
 
-// Changes colors of selected squares.
+// Colours the squares: the last move's squares and a king in check over the
+// board's own colours, then the keyboard cursor, the selection and the
+// square under the mouse, each layer blended over the ones below.
 fn color_squares(
     pick_state: Res<PickState>,
-    selected_square: Res<SelectedSquare>,
+    input: Res<InputState>,
+    cursor: Res<BoardCursor>,
+    themes: Res<Themes>,
+    state: Res<State<AppState>>,
+    turn: Res<PlayerTurn>,
+    board_state: Res<BoardState>,
+    history: Res<MoveHistory>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    pieces_query: Query<&Piece>,
     query: Query<(Entity, &Square, &Handle<StandardMaterial>)>
 ) {
+    let theme = themes.current();
+
     let top_entity = if let Some((entity, _intersection)) = pick_state.top(Group::default()) {
         Some(*entity)
     } else {
         None
     };
 
+    // The move into the position on the board, and the king in check there.
+    // The position editor has neither.
+    let (last_move, checked_king) = match state.current() {
+        AppState::Playing | AppState::GameOver => {
+            let ply = history.shown.unwrap_or_else(|| history.moves.len());
+            let last_move = ply.checked_sub(1).and_then(|idx| history.moves.get(idx));
+            let position = history.shown_position().cloned().unwrap_or_else(|| Position {
+                pieces: pieces_query.iter().copied().collect(),
+                side_to_move: turn.0,
+                state: board_state.clone(),
+            });
+            let checked_king = position
+                .king(position.side_to_move)
+                .filter(|_| position.in_check(position.side_to_move))
+                .map(|king| (king.x, king.y));
+            (last_move.map(|played| played.mv), checked_king)
+        }
+        _ => (None, None),
+    };
+
     for (entity, square, material_handle) in query.iter() {
         let material = materials.get_mut(material_handle).unwrap();
+        let pos = (square.x, square.y);
 
-        material.albedo = if Some(entity) == top_entity {
-            Color::rgb(0.8, 0.3, 0.3)
-        } else if Some(entity) == selected_square.entity {
-            Color::rgb(0.9, 0.1, 0.1)
-        } else if square.is_white() {
-            Color::rgb(1., 0.9, 0.9)
+        let mut color = if square.is_white() {
+            theme.white_square
         } else {
-            Color::rgb(0., 0.1, 0.1)
+            theme.black_square
         };
+        if last_move.is_some_and(|(from, to)| pos == from || pos == to) {
+            color = mix(color, theme.last_move, 0.5);
+        }
+        if checked_king == Some(pos) {
+            color = mix(color, theme.check, 0.7);
+        }
+        if cursor.visible && square.x == cursor.x && square.y == cursor.y {
+            color = mix(color, theme.cursor, 0.7);
+        }
+        if input.selected() == Some(pos) {
+            color = mix(color, theme.selected, 0.8);
+        }
+        if Some(entity) == top_entity {
+            color = mix(color, theme.hover, 0.6);
+        }
+        material.albedo = rgb(color);
     }
 }
 
+// Applies the theme's square textures whenever the theme changes.
+fn texture_squares(
+    themes: ChangedRes<Themes>,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    query: Query<(&Square, &Handle<StandardMaterial>)>,
+) {
+    let theme = themes.current();
+    let white_texture = load_texture(&asset_server, &theme.white_square_texture);
+    let black_texture = load_texture(&asset_server, &theme.black_square_texture);
+
+    for (square, material_handle) in query.iter() {
+        let material = materials.get_mut(material_handle).unwrap();
+        material.albedo_texture = if square.is_white() {
+            white_texture.clone()
+        } else {
+            black_texture.clone()
+        };
+    }
+}
+
+// Follows the left mouse button through the input state: pressing on one
+// of the player's pieces picks it up, letting go over another square moves
+// it there, and a click without dragging selects the piece or, with one
+// selected, moves it to the square clicked.
 fn select_square(
-    commands: &mut Commands,
     pick_state: Res<PickState>,
     mouse_button_inputs: Res<Input<MouseButton>>,
-    mut selected_square: ResMut<SelectedSquare>,
-    mut selected_piece: ResMut<SelectedPiece>,
-    mut turn: ResMut<PlayerTurn>,
-    mut app_exit_events: ResMut<Events<AppExit>>,
+    mut input: ResMut<InputState>,
+    mut cursor: ResMut<BoardCursor>,
+    setup: Res<GameSetup>,
+    turn: Res<PlayerTurn>,
+    board_state: Res<BoardState>,
+    mut requests: ResMut<Events<MoveRequested>>,
     squares_query: Query<&Square>,
-    mut pieces_query: Query<(Entity, &mut Piece, &Children)>,
+    pieces_query: Query<(Entity, &Piece)>,
 ) {
-    if !mouse_button_inputs.just_pressed(MouseButton::Left) {
+    if input.is_locked() {
         return;
     }
+    let hovered = pick_state
+        .top(Group::default())
+        .and_then(|(entity, _)| squares_query.get(*entity).ok())
+        .map(|square| (square.x, square.y));
+    input.hover(hovered);
+
+    let variant = setup.variant.rules();
+    let position = live_position(&turn, &board_state, &pieces_query);
+    let attempt = if mouse_button_inputs.just_pressed(MouseButton::Left) {
+        // Mouse input takes over from the keyboard cursor.
+        cursor.visible = false;
+        let own = hovered.and_then(|square| piece_on(square, &position, &pieces_query));
+        let legal = hovered.is_some_and(|square| {
+            can_move_selected(&input, variant, &position, square)
+        });
+        input.press(hovered, own, legal)
+    } else if mouse_button_inputs.just_released(MouseButton::Left) {
+        input.release(hovered)
+    } else {
+        None
+    };
+
+    if let Some(attempt) = attempt {
+        request_move(variant, &position, attempt, &mut input, &mut requests);
+    }
+}
 
-    // Get square under cursor and set as selected
-    if let Some((sq_entity, _intersection)) = pick_state.top(Group::default()) {
-        // Get the square, making sure extant and a square
-        if let Ok(square) = squares_query.get(*sq_entity) {
-            // Mark as selected
-            selected_square.entity = Some(*sq_entity);
-
-            match selected_piece.entity {
-                Some(ent) => {
-                    // Move piece to the selected square
-                    let pieces_ent_vec: Vec<(Entity, Piece, Vec<Entity>)> = pieces_query
-                        .iter_mut()
-                        .map(|(entity, piece, children)| {
-                            (entity, *piece, children.iter().map(|entity| *entity).collect())
-                        })
-                        .collect();
-
-                    let pieces_vec: Vec<Piece> = pieces_query
-                        .iter_mut()
-                        .map(|(_, piece, _)| *piece)
-                        .collect();
-
-                    if let Ok((_piece_entity, mut piece, _)) = pieces_query.get_mut(ent) {
-                        if piece.is_move_valid((square.x, square.y), pieces_vec) {
-                            for (other_ent, other_piece, other_children) in pieces_ent_vec {
-                                if other_piece.x == square.x
-                                    && other_piece.y == square.y
-                                    && other_piece.color != piece.color {
-                                    if other_piece.piece_type == PieceType::King {
-                                        println!(
-                                            "{} won!",
-                                            match turn.0 {
-                                                PieceColor::White => "White",
-                                                PieceColor::Black => "Black",
-                                            }
-                                        );
-                                        app_exit_events.send(AppExit);
-                                    }
-                                    commands.despawn(other_ent);
-                                    for child in other_children {
-                                        commands.despawn(child);
-                                    }
-                                }
-                            }
-                            piece.x = square.x;
-                            piece.y = square.y;
-
-                            // Switch players at the end of a turn
-                            turn.0 = match turn.0 {
-                                PieceColor::White => PieceColor::Black,
-                                PieceColor::Black => PieceColor::White,
-                            }
-                        }
-                    }
-                    selected_square.entity = None;
-                    selected_piece.entity = None;
-                },
-                _ => {
-                    // Select piece in the current square
-                    for (piece_ent, piece, _) in pieces_query.iter_mut() {
-                        if *piece == *square {
-                            // Move piece to the selected square
-                            selected_piece.entity = Some(piece_ent);
-                            break;
-                        }
-                    }
-                }
-            }
+// The position on the board, as the game has it.
+fn live_position(
+    turn: &PlayerTurn,
+    board_state: &BoardState,
+    pieces_query: &Query<(Entity, &Piece)>,
+) -> Position {
+    Position {
+        pieces: pieces_query.iter().map(|(_, piece)| *piece).collect(),
+        side_to_move: turn.0,
+        state: board_state.clone(),
+    }
+}
+
+// The piece of the side to move standing on `square`, if any.
+fn piece_on(
+    square: (u8, u8),
+    position: &Position,
+    pieces_query: &Query<(Entity, &Piece)>,
+) -> Option<Entity> {
+    pieces_query
+        .iter()
+        .find(|(_, piece)| (piece.x, piece.y) == square && piece.color == position.side_to_move)
+        .map(|(entity, _)| entity)
+}
+
+// Whether the piece the player has picked up may move to `square`.
+fn can_move_selected(
+    input: &InputState,
+    variant: &dyn Variant,
+    position: &Position,
+    square: (u8, u8),
+) -> bool {
+    input.selected().is_some_and(|from| variant.is_move_valid(position, from, square))
+}
+
+// Asks for a move the player made, unless it takes a pawn to the last rank
+// with more than one piece to choose for it, in which case the input state
+// waits for the choice. Returns whether the move was legal.
+fn request_move(
+    variant: &dyn Variant,
+    position: &Position,
+    attempt: MoveAttempt,
+    input: &mut InputState,
+    requests: &mut Events<MoveRequested>,
+) -> bool {
+    let MoveAttempt { from, to, promotion } = attempt;
+    if !variant.is_move_valid(position, from, to) {
+        return false;
+    }
+    if promotion.is_none()
+        && position.is_promotion(from, to)
+        && promotion_choices(variant).len() > 1
+    {
+        input.await_promotion(attempt);
+    } else {
+        requests.send(MoveRequested { from, to, promotion });
+    }
+    true
+}
+
+// In puzzle mode, checks the moves played against the puzzle's solution,
+// ending the game once the puzzle is solved or failed.
+fn check_puzzle_moves(
+    setup: Res<GameSetup>,
+    mut applied_reader: Local<EventReader<MoveApplied>>,
+    applied_events: Res<Events<MoveApplied>>,
+    mut trainer: ResMut<PuzzleTrainer>,
+    mut ended_events: ResMut<Events<GameEnded>>,
+) {
+    for applied in applied_reader.iter(&applied_events) {
+        // The puzzle starts with the game, so its moves are the game's.
+        if setup.mode != GameMode::Puzzles || trainer.followed().is_none() {
+            continue;
         }
+        let solver = trainer.solver();
+        let (winner, reason) = match trainer.record_move(setup.variant.rules(), applied.mv, applied.effects.promotion) {
+            PuzzleProgress::Continue => continue,
+            PuzzleProgress::Solved => (solver, "solving the puzzle"),
+            PuzzleProgress::Failed => (opponent(solver), "a wrong move"),
+        };
+        ended_events.send(GameEnded {
+            result: GameResult {
+                winner: Some(winner),
+                reason: reason.to_string(),
+            },
+        });
+    }
+}
+
+// Clears any selection left over from the last game.
+fn reset_selection(mut input: ResMut<InputState>, mut move_text: ResMut<MoveTextInput>) {
+    *input = InputState::Idle;
+    *move_text = MoveTextInput::default();
+}
+
+// Plays a move for the side to move when the computer controls it. Waits a
+// moment first so the last move can be seen.
+fn computer_move(
+    time: Res<Time>,
+    setup: Res<GameSetup>,
+    book: Res<OpeningBook>,
+    tablebases: Res<Tablebases>,
+    input: Res<InputState>,
+    mut delay: Local<f32>,
+    turn: Res<PlayerTurn>,
+    board_state: Res<BoardState>,
+    mut requests: ResMut<Events<MoveRequested>>,
+    pieces_query: Query<(Entity, &Piece)>,
+) {
+    // Puzzle replies come from the solution instead; see `puzzle_reply`.
+    if setup.player(turn.0) != PlayerType::Computer || setup.mode == GameMode::Puzzles {
+        *delay = 0.;
+        return;
+    }
+    // The board is the computer's while the input state holds it for it.
+    if *input != InputState::Locked(LockReason::ComputerToMove) {
+        return;
+    }
+    *delay += time.delta_seconds();
+    if *delay < 0.5 {
+        return;
+    }
+    *delay = 0.;
+
+    let position = live_position(&turn, &board_state, &pieces_query);
+    let computer_move = ai::computer_move(setup.variant, &book, &tablebases, &position);
+    if let Some(((from, to), promotion)) = computer_move {
+        requests.send(MoveRequested { from, to, promotion });
+    }
+}
+
+// Plays the opponent's reply from the puzzle's solution, after the same
+// pause as the computer takes.
+fn puzzle_reply(
+    time: Res<Time>,
+    setup: Res<GameSetup>,
+    input: Res<InputState>,
+    mut delay: Local<f32>,
+    trainer: Res<PuzzleTrainer>,
+    mut requests: ResMut<Events<MoveRequested>>,
+) {
+    let ((from, to), promotion) = match trainer.reply() {
+        Some(reply) if setup.mode == GameMode::Puzzles => reply,
+        _ => {
+            *delay = 0.;
+            return;
+        }
+    };
+    if *input != InputState::Locked(LockReason::ComputerToMove) {
+        return;
+    }
+    *delay += time.delta_seconds();
+    if *delay < 0.5 {
+        return;
+    }
+    *delay = 0.;
+
+    requests.send(MoveRequested { from, to, promotion });
+}
+
+// Pressing E shows the position on the board as X-FEN, and as Shredder-FEN
+// as well where the castling rights are written differently.
+fn export_fen(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut move_text: ResMut<MoveTextInput>,
+    turn: Res<PlayerTurn>,
+    board_state: Res<BoardState>,
+    history: Res<MoveHistory>,
+    pieces_query: Query<&Piece>,
+) {
+    if move_text.focused || !keyboard_input.just_pressed(KeyCode::E) {
+        return;
+    }
+    let position = history.shown_position().cloned().unwrap_or_else(|| Position {
+        pieces: pieces_query.iter().copied().collect(),
+        side_to_move: turn.0,
+        state: board_state.clone(),
+    });
+    let fen = to_fen(&position, false);
+    let shredder_fen = to_fen(&position, true);
+    move_text.notice = Some(if shredder_fen == fen {
+        format!("FEN: {}", fen)
+    } else {
+        format!("FEN: {}\nShredder-FEN: {}", fen, shredder_fen)
+    });
+}
+
+// Moves the keyboard cursor over the board with the arrow keys, which keep
+// pointing the way they do on screen when the view is flipped. They're left
+// to the move text box while it has focus.
+fn move_cursor(
+    keyboard_input: Res<Input<KeyCode>>,
+    move_text: Res<MoveTextInput>,
+    view: Res<BoardView>,
+    board_state: Res<BoardState>,
+    mut cursor: ResMut<BoardCursor>,
+) {
+    if move_text.focused {
+        return;
+    }
+    let (dx, dy): (i8, i8) = if keyboard_input.just_pressed(KeyCode::Up) {
+        (1, 0)
+    } else if keyboard_input.just_pressed(KeyCode::Down) {
+        (-1, 0)
+    } else if keyboard_input.just_pressed(KeyCode::Right) {
+        (0, 1)
+    } else if keyboard_input.just_pressed(KeyCode::Left) {
+        (0, -1)
     } else {
-        // Played clicked outside board; deselect everything.
-        selected_square.entity = None;
-        selected_piece.entity = None;
+        return;
+    };
+    let (dx, dy) = if view.flipped { (-dx, -dy) } else { (dx, dy) };
+
+    // The first key press only reveals the cursor where it was left.
+    if cursor.visible {
+        let size = board_state.size;
+        cursor.x = (cursor.x as i8 + dx).max(0).min(size.ranks as i8 - 1) as u8;
+        cursor.y = (cursor.y as i8 + dy).max(0).min(size.files as i8 - 1) as u8;
+    }
+    cursor.visible = true;
+}
+
+// Handles Enter: submits the typed move if there is one, otherwise activates
+// the square under the keyboard cursor as if it had been clicked.
+fn keyboard_select(
+    keyboard_input: Res<Input<KeyCode>>,
+    cursor: Res<BoardCursor>,
+    mut move_text: ResMut<MoveTextInput>,
+    mut input: ResMut<InputState>,
+    setup: Res<GameSetup>,
+    turn: Res<PlayerTurn>,
+    board_state: Res<BoardState>,
+    mut requests: ResMut<Events<MoveRequested>>,
+    pieces_query: Query<(Entity, &Piece)>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::Return) || input.is_locked() {
+        return;
+    }
+    let variant = setup.variant.rules();
+    let position = live_position(&turn, &board_state, &pieces_query);
+
+    if move_text.focused {
+        let text = move_text.text.trim().to_string();
+        if text.is_empty() {
+            return;
+        }
+
+        // A pawn's move to the last rank typed without a piece makes a
+        // queen.
+        let requested = parse_move(&text, &position).is_some_and(|(from, to)| {
+            let promotion = parse_promotion(&text).or(Some(PieceType::Queen));
+            let attempt = MoveAttempt { from, to, promotion };
+            request_move(variant, &position, attempt, &mut input, &mut requests)
+        });
+
+        if requested {
+            move_text.text.clear();
+            move_text.error = None;
+            input.cancel();
+        } else {
+            move_text.error = Some(format!("Illegal move: {}", text));
+        }
+    } else if cursor.visible {
+        let square = (cursor.x, cursor.y);
+        let own = piece_on(square, &position, &pieces_query);
+        let legal = can_move_selected(&input, variant, &position, square);
+        if let Some(attempt) = input.click(square, own, legal) {
+            request_move(variant, &position, attempt, &mut input, &mut requests);
+        }
+    }
+}
+
+// Keys choosing a pawn's promotion, by the piece's letter.
+const PROMOTION_KEYS: [(KeyCode, PieceType); 8] = [
+    (KeyCode::Q, PieceType::Queen),
+    (KeyCode::R, PieceType::Rook),
+    (KeyCode::B, PieceType::Bishop),
+    (KeyCode::N, PieceType::Knight),
+    (KeyCode::A, PieceType::Archbishop),
+    (KeyCode::C, PieceType::Chancellor),
+    (KeyCode::M, PieceType::Amazon),
+    (KeyCode::L, PieceType::Camel),
+];
+
+// Finishes a pawn's move to the last rank once its piece is picked, from the
+// promotion picker or by its letter; Escape takes the move back.
+fn choose_promotion(
+    keyboard_input: Res<Input<KeyCode>>,
+    move_text: Res<MoveTextInput>,
+    mut input: ResMut<InputState>,
+    setup: Res<GameSetup>,
+    mut requests: ResMut<Events<MoveRequested>>,
+    button_query: Query<(&Interaction, &PromotionButton), Mutated<Interaction>>,
+) {
+    if !matches!(*input, InputState::AwaitingPromotion { .. }) {
+        return;
+    }
+    let choices = promotion_choices(setup.variant.rules());
+
+    let clicked = button_query
+        .iter()
+        .find(|(interaction, _)| **interaction == Interaction::Clicked)
+        .map(|(_, button)| button.0);
+    let typed = if move_text.focused {
+        None
+    } else {
+        PROMOTION_KEYS
+            .iter()
+            .find(|(key, piece_type)| {
+                keyboard_input.just_pressed(*key) && choices.contains(piece_type)
+            })
+            .map(|(_, piece_type)| *piece_type)
+    };
+
+    match clicked.or(typed).and_then(|piece_type| input.promote(piece_type)) {
+        Some(MoveAttempt { from, to, promotion }) => {
+            requests.send(MoveRequested { from, to, promotion });
+        }
+        None if !move_text.focused && keyboard_input.just_pressed(KeyCode::Escape) => {
+            input.cancel();
+        }
+        None => {}
     }
 }
 
-pub struct PlayerTurn(PieceColor);
+pub struct PlayerTurn(pub PieceColor);
 impl Default for PlayerTurn {
     fn default() -> Self {
         Self(PieceColor::White)
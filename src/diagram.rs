@@ -0,0 +1,508 @@
+use crate::{
+    ai::Move,
+    notation::{parse_fen, parse_uci, piece_char, STANDARD_FEN},
+    pieces::*,
+    position::Position,
+    theme::{Rgb, Theme, Themes},
+};
+use std::{fmt::Write, fs, fs::File, io::BufWriter};
+
+// Width of a square in a diagram, in pixels.
+const SQUARE_PIXELS: f32 = 40.;
+// Width of the border holding the coordinates.
+const MARGIN_PIXELS: f32 = 24.;
+
+// Heights of the text on pieces and of the coordinates.
+const PIECE_LETTER_SIZE: f32 = 21.;
+const COORDINATE_SIZE: f32 = 14.;
+
+// Sizes of an arrow, in squares.
+const SHAFT_WIDTH: f32 = 0.15;
+const HEAD_WIDTH: f32 = 0.45;
+const HEAD_LENGTH: f32 = 0.4;
+
+const BORDER_COLOR: [u8; 3] = [40, 40, 40];
+const COORDINATE_COLOR: [u8; 3] = [220, 220, 220];
+const ARROW_COLOR: [u8; 3] = [242, 166, 25];
+const ARROW_OPACITY: f32 = 0.8;
+
+// What's drawn on a diagram besides the pieces.
+#[derive(Clone)]
+pub struct DiagramOptions {
+    // The side at the bottom of the board.
+    pub orientation: PieceColor,
+    // File letters and rank numbers around the board.
+    pub coordinates: bool,
+    // Its squares are highlighted.
+    pub last_move: Option<Move>,
+    pub arrows: Vec<Move>,
+}
+
+impl Default for DiagramOptions {
+    fn default() -> Self {
+        Self {
+            orientation: PieceColor::White,
+            coordinates: true,
+            last_move: None,
+            arrows: Vec::new(),
+        }
+    }
+}
+
+// Something drawn on a diagram, in pixels from its top left corner.
+enum Shape {
+    Rect { left: f32, top: f32, width: f32, height: f32, color: [u8; 3] },
+    Circle { centre: (f32, f32), radius: f32, color: [u8; 3] },
+    Text { centre: (f32, f32), text: String, height: f32, color: [u8; 3] },
+    Polygon { points: Vec<(f32, f32)>, color: [u8; 3], opacity: f32 },
+}
+
+// A position laid out as flat shapes, to be drawn in software as an image or
+// written out as SVG; nothing here needs the GPU or a window.
+pub struct Diagram {
+    width: f32,
+    height: f32,
+    shapes: Vec<Shape>,
+}
+
+impl Diagram {
+    // Lays out `position` in `theme`'s colours. Pieces are discs of their
+    // side's colour marked with their letter.
+    pub fn new(position: &Position, theme: &Theme, options: &DiagramOptions) -> Self {
+        let size = position.state.size;
+        let margin = if options.coordinates { MARGIN_PIXELS } else { 0. };
+        let flipped = options.orientation == PieceColor::Black;
+        // Top left corner of a square.
+        let corner = |(x, y): (u8, u8)| {
+            let (column, row) =
+                if flipped { (size.files - 1 - y, x) } else { (y, size.ranks - 1 - x) };
+            (margin + column as f32 * SQUARE_PIXELS, margin + row as f32 * SQUARE_PIXELS)
+        };
+        let centre = |square: (u8, u8)| {
+            let (left, top) = corner(square);
+            (left + SQUARE_PIXELS / 2., top + SQUARE_PIXELS / 2.)
+        };
+
+        let width = size.files as f32 * SQUARE_PIXELS + 2. * margin;
+        let height = size.ranks as f32 * SQUARE_PIXELS + 2. * margin;
+        let mut shapes =
+            vec![Shape::Rect { left: 0., top: 0., width, height, color: BORDER_COLOR }];
+
+        for square in size.squares() {
+            let color = match options.last_move {
+                Some((from, to)) if square == from || square == to => theme.last_move,
+                // a1 is dark.
+                _ if (square.0 + square.1) % 2 == 0 => theme.black_square,
+                _ => theme.white_square,
+            };
+            let (left, top) = corner(square);
+            shapes.push(Shape::Rect {
+                left,
+                top,
+                width: SQUARE_PIXELS,
+                height: SQUARE_PIXELS,
+                color: to_bytes(color),
+            });
+        }
+
+        if options.coordinates {
+            // Files along the bottom, ranks up the left.
+            for y in 0..size.files {
+                let (left, _) = corner((0, y));
+                shapes.push(Shape::Text {
+                    centre: (left + SQUARE_PIXELS / 2., height - margin / 2.),
+                    text: ((b'a' + y) as char).to_string(),
+                    height: COORDINATE_SIZE,
+                    color: COORDINATE_COLOR,
+                });
+            }
+            for x in 0..size.ranks {
+                let (_, top) = corner((x, 0));
+                shapes.push(Shape::Text {
+                    centre: (margin / 2., top + SQUARE_PIXELS / 2.),
+                    text: (x + 1).to_string(),
+                    height: COORDINATE_SIZE,
+                    color: COORDINATE_COLOR,
+                });
+            }
+        }
+
+        for piece in &position.pieces {
+            let fill = to_bytes(match piece.color {
+                PieceColor::White => theme.white_piece.albedo,
+                PieceColor::Black => theme.black_piece.albedo,
+            });
+            let ink = contrast(fill);
+            let centre = centre((piece.x, piece.y));
+            let radius = SQUARE_PIXELS * 0.4;
+            shapes.push(Shape::Circle { centre, radius, color: ink });
+            shapes.push(Shape::Circle { centre, radius: radius - 2., color: fill });
+            shapes.push(Shape::Text {
+                centre,
+                text: piece_char(piece).to_ascii_uppercase().to_string(),
+                height: PIECE_LETTER_SIZE,
+                color: ink,
+            });
+        }
+
+        for (from, to) in &options.arrows {
+            if let Some(points) = arrow_outline(centre(*from), centre(*to)) {
+                shapes.push(Shape::Polygon { points, color: ARROW_COLOR, opacity: ARROW_OPACITY });
+            }
+        }
+
+        Self { width, height, shapes }
+    }
+
+    pub fn to_canvas(&self) -> Canvas {
+        let mut canvas = Canvas::new(self.width as u32, self.height as u32);
+        for shape in &self.shapes {
+            match shape {
+                Shape::Rect { left, top, width, height, color } => {
+                    canvas.fill_rect(*left, *top, *width, *height, *color)
+                }
+                Shape::Circle { centre, radius, color } => {
+                    canvas.fill_circle(*centre, *radius, *color)
+                }
+                Shape::Text { centre, text, height, color } => {
+                    canvas.draw_text(*centre, text, *height, *color)
+                }
+                Shape::Polygon { points, color, opacity } => {
+                    canvas.fill_polygon(points, *color, *opacity)
+                }
+            }
+        }
+        canvas
+    }
+
+    pub fn to_svg(&self) -> String {
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" \
+             viewBox=\"0 0 {0} {1}\">\n",
+            self.width, self.height
+        );
+        for shape in &self.shapes {
+            // Writing to a String can't fail.
+            let _ = match shape {
+                Shape::Rect { left, top, width, height, color } => writeln!(
+                    svg,
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+                    left,
+                    top,
+                    width,
+                    height,
+                    hex(*color)
+                ),
+                Shape::Circle { centre, radius, color } => writeln!(
+                    svg,
+                    "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\"/>",
+                    centre.0,
+                    centre.1,
+                    radius,
+                    hex(*color)
+                ),
+                Shape::Text { centre, text, height, color } => writeln!(
+                    svg,
+                    "<text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-weight=\"bold\" \
+                     font-size=\"{}\" text-anchor=\"middle\" dominant-baseline=\"central\" \
+                     fill=\"{}\">{}</text>",
+                    centre.0,
+                    centre.1,
+                    height,
+                    hex(*color),
+                    text
+                ),
+                Shape::Polygon { points, color, opacity } => {
+                    let points: Vec<String> =
+                        points.iter().map(|(x, y)| format!("{:.1},{:.1}", x, y)).collect();
+                    writeln!(
+                        svg,
+                        "<polygon points=\"{}\" fill=\"{}\" fill-opacity=\"{}\"/>",
+                        points.join(" "),
+                        hex(*color),
+                        opacity
+                    )
+                }
+            };
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    // Writes the diagram as SVG or PNG, going by the file's extension.
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        if path.ends_with(".svg") {
+            fs::write(path, self.to_svg()).map_err(|err| err.to_string())
+        } else if path.ends_with(".png") {
+            self.to_canvas().save_png(path)
+        } else {
+            Err("diagrams are saved as .svg or .png".to_string())
+        }
+    }
+}
+
+// Corners of an arrow from one point to another, or `None` for one going
+// nowhere.
+fn arrow_outline(from: (f32, f32), to: (f32, f32)) -> Option<Vec<(f32, f32)>> {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let length = (dx * dx + dy * dy).sqrt();
+    if length < 1. {
+        return None;
+    }
+    // Along the arrow, and across it.
+    let (along, across) = ((dx / length, dy / length), (-dy / length, dx / length));
+    let neck = length - HEAD_LENGTH * SQUARE_PIXELS;
+    let point = |forward: f32, side: f32| {
+        (
+            from.0 + along.0 * forward + across.0 * side * SQUARE_PIXELS / 2.,
+            from.1 + along.1 * forward + across.1 * side * SQUARE_PIXELS / 2.,
+        )
+    };
+    Some(vec![
+        point(0., SHAFT_WIDTH),
+        point(neck, SHAFT_WIDTH),
+        point(neck, HEAD_WIDTH),
+        point(length, 0.),
+        point(neck, -HEAD_WIDTH),
+        point(neck, -SHAFT_WIDTH),
+        point(0., -SHAFT_WIDTH),
+    ])
+}
+
+// A theme colour as bytes.
+fn to_bytes(color: Rgb) -> [u8; 3] {
+    let byte = |channel: f32| (channel.clamp(0., 1.) * 255.).round() as u8;
+    [byte(color.0), byte(color.1), byte(color.2)]
+}
+
+fn hex(color: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+// Dark text on light colours and light text on dark ones.
+fn contrast(color: [u8; 3]) -> [u8; 3] {
+    let luma = 0.299 * color[0] as f32 + 0.587 * color[1] as f32 + 0.114 * color[2] as f32;
+    if luma > 128. {
+        [25, 25, 25]
+    } else {
+        [230, 230, 230]
+    }
+}
+
+// An RGBA image drawn in software.
+pub struct Canvas {
+    pub width: u32,
+    pub height: u32,
+    // Four bytes a pixel, row by row from the top left.
+    pub pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32) -> Self {
+        Canvas { width, height, pixels: vec![0; (width * height * 4) as usize] }
+    }
+
+    // Lays `color` over a pixel, `opacity` of the way.
+    fn blend_pixel(&mut self, x: i32, y: i32, color: [u8; 3], opacity: f32) {
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return;
+        }
+        let idx = ((y as u32 * self.width + x as u32) * 4) as usize;
+        for (channel, value) in color.iter().enumerate() {
+            let old = self.pixels[idx + channel] as f32;
+            self.pixels[idx + channel] = (old + (*value as f32 - old) * opacity).round() as u8;
+        }
+        self.pixels[idx + 3] = 255;
+    }
+
+    // Fills the pixels whose centres fall inside `inside`, within the given
+    // bounds.
+    fn fill_where(
+        &mut self,
+        (left, top, right, bottom): (f32, f32, f32, f32),
+        color: [u8; 3],
+        opacity: f32,
+        inside: impl Fn(f32, f32) -> bool,
+    ) {
+        for y in top.floor() as i32..bottom.ceil() as i32 {
+            for x in left.floor() as i32..right.ceil() as i32 {
+                if inside(x as f32 + 0.5, y as f32 + 0.5) {
+                    self.blend_pixel(x, y, color, opacity);
+                }
+            }
+        }
+    }
+
+    fn fill_rect(&mut self, left: f32, top: f32, width: f32, height: f32, color: [u8; 3]) {
+        let bounds = (left, top, left + width, top + height);
+        self.fill_where(bounds, color, 1., |_, _| true);
+    }
+
+    fn fill_circle(&mut self, centre: (f32, f32), radius: f32, color: [u8; 3]) {
+        let bounds = (centre.0 - radius, centre.1 - radius, centre.0 + radius, centre.1 + radius);
+        self.fill_where(bounds, color, 1., |x, y| {
+            let (dx, dy) = (x - centre.0, y - centre.1);
+            dx * dx + dy * dy <= radius * radius
+        });
+    }
+
+    fn fill_polygon(&mut self, points: &[(f32, f32)], color: [u8; 3], opacity: f32) {
+        let bounds = points.iter().fold(
+            (f32::MAX, f32::MAX, f32::MIN, f32::MIN),
+            |(left, top, right, bottom), (x, y)| {
+                (left.min(*x), top.min(*y), right.max(*x), bottom.max(*y))
+            },
+        );
+        // Even-odd rule: a point is inside if a ray from it crosses the
+        // outline an odd number of times.
+        self.fill_where(bounds, color, opacity, |x, y| {
+            let mut inside = false;
+            for (idx, (x1, y1)) in points.iter().enumerate() {
+                let (x2, y2) = points[(idx + 1) % points.len()];
+                if (*y1 > y) != (y2 > y) && x < x1 + (y - y1) * (x2 - x1) / (y2 - y1) {
+                    inside = !inside;
+                }
+            }
+            inside
+        });
+    }
+
+    // Draws `text` `height` pixels tall, centred on `centre`, in a 5x7 pixel
+    // font scaled up to fit; characters missing from it are left as gaps.
+    fn draw_text(&mut self, centre: (f32, f32), text: &str, height: f32, color: [u8; 3]) {
+        let scale = ((height / 7.).round() as i32).max(1);
+        let advance = 6 * scale;
+        let width = advance * text.chars().count() as i32 - scale;
+        let left = centre.0 as i32 - width / 2;
+        let top = centre.1 as i32 - 7 * scale / 2;
+        for (idx, c) in text.chars().enumerate() {
+            let rows = match glyph(c) {
+                Some(rows) => rows,
+                None => continue,
+            };
+            let glyph_left = left + advance * idx as i32;
+            for (row, bits) in rows.iter().enumerate() {
+                for column in 0..5 {
+                    if bits & (0x10 >> column) == 0 {
+                        continue;
+                    }
+                    for dy in 0..scale {
+                        for dx in 0..scale {
+                            self.blend_pixel(
+                                glyph_left + column * scale + dx,
+                                top + row as i32 * scale + dy,
+                                color,
+                                1.,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn save_png(&self, path: &str) -> Result<(), String> {
+        let file = File::create(path).map_err(|err| err.to_string())?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.pixels))
+            .map_err(|err| err.to_string())
+    }
+}
+
+// Rows of a character in the 5x7 font, top first, with the leftmost pixel
+// as the highest of five bits: 0x11 is a pixel at either end.
+fn glyph(c: char) -> Option<[u8; 7]> {
+    Some(match c {
+        'A' => [0x0e, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11],
+        'B' => [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e],
+        'C' => [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f],
+        'M' => [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'P' => [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10],
+        'Q' => [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d],
+        'R' => [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11],
+        // Files, up to the largest board's.
+        'a' => [0x00, 0x00, 0x0e, 0x01, 0x0f, 0x11, 0x0f],
+        'b' => [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x1e],
+        'c' => [0x00, 0x00, 0x0e, 0x10, 0x10, 0x11, 0x0e],
+        'd' => [0x01, 0x01, 0x0d, 0x13, 0x11, 0x11, 0x0f],
+        'e' => [0x00, 0x00, 0x0e, 0x11, 0x1f, 0x10, 0x0e],
+        'f' => [0x06, 0x09, 0x08, 0x1c, 0x08, 0x08, 0x08],
+        'g' => [0x00, 0x0f, 0x11, 0x11, 0x0f, 0x01, 0x0e],
+        'h' => [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x11],
+        'i' => [0x04, 0x00, 0x0c, 0x04, 0x04, 0x04, 0x0e],
+        'j' => [0x02, 0x00, 0x06, 0x02, 0x02, 0x12, 0x0c],
+        'k' => [0x10, 0x10, 0x12, 0x14, 0x18, 0x14, 0x12],
+        'l' => [0x0c, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        'm' => [0x00, 0x00, 0x1a, 0x15, 0x15, 0x11, 0x11],
+        'n' => [0x00, 0x00, 0x16, 0x19, 0x11, 0x11, 0x11],
+        'o' => [0x00, 0x00, 0x0e, 0x11, 0x11, 0x11, 0x0e],
+        'p' => [0x00, 0x00, 0x1e, 0x11, 0x1e, 0x10, 0x10],
+        '0' => [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e],
+        '1' => [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e],
+        '2' => [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f],
+        '3' => [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e],
+        '4' => [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02],
+        '5' => [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e],
+        '6' => [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e],
+        '7' => [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e],
+        '9' => [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c],
+        _ => return None,
+    })
+}
+
+const USAGE: &str = "usage: bevy-chess diagram <FEN|start> <file.svg|file.png> \
+                     [--black] [--no-coordinates] [--last-move e2e4] [--arrow g1f3]... \
+                     [--theme NAME]";
+
+// The `diagram` subcommand: draws a position given as FEN to a file, in one
+// of the themes.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let (fen, path) = match args {
+        [fen, path, ..] => (fen.as_str(), path.as_str()),
+        _ => return Err(USAGE.to_string()),
+    };
+    let fen = if fen == "start" { STANDARD_FEN } else { fen };
+    let position = parse_fen(fen).ok_or_else(|| format!("invalid FEN: {}", fen))?;
+
+    // Moves and arrows are given from square to square, as in UCI.
+    let size = position.state.size;
+    let parse_squares = |text: &str| {
+        parse_uci(text)
+            .filter(|(from, to)| size.contains(*from) && size.contains(*to))
+            .ok_or_else(|| format!("invalid move: {}", text))
+    };
+
+    let mut options = DiagramOptions::default();
+    let mut theme_name = None;
+    let mut flags = args[2..].iter();
+    while let Some(flag) = flags.next() {
+        let mut value = || flags.next().ok_or_else(|| format!("{} needs a value", flag));
+        match flag.as_str() {
+            "--black" => options.orientation = PieceColor::Black,
+            "--no-coordinates" => options.coordinates = false,
+            "--last-move" => options.last_move = Some(parse_squares(value()?)?),
+            "--arrow" => options.arrows.push(parse_squares(value()?)?),
+            "--theme" => theme_name = Some(value()?.clone()),
+            _ => return Err(format!("unknown option {}\n{}", flag, USAGE)),
+        }
+    }
+
+    let themes = Themes::default();
+    let theme = match theme_name {
+        Some(name) => themes
+            .themes
+            .iter()
+            .find(|theme| theme.name.eq_ignore_ascii_case(&name))
+            .ok_or_else(|| format!("no theme named {}", name))?,
+        None => themes.current(),
+    };
+    Diagram::new(&position, theme, &options).save(path)
+}
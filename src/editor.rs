@@ -0,0 +1,593 @@
+use crate::{
+    board::{PlayerTurn, Square},
+    game::*,
+    notation::{piece_char, square_name, to_fen},
+    piece_set::PieceSets,
+    pieces::*,
+    position::*,
+    ui::{despawn_screen, spawn_button, ButtonMaterials},
+    variant::VariantKind,
+};
+use bevy::{prelude::*, window::CursorMoved};
+use bevy_mod_picking::*;
+
+// Setting up a position by hand: pieces are dragged from a palette onto the
+// board, moved around and deleted with a right-click, while a panel sets the
+// side to move, castling rights and en passant square. The position can be
+// exported as FEN or played from once it's legal.
+pub struct EditorPlugin;
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Editor>()
+            .on_state_enter(STATE_STAGE, AppState::Editor, start_editor.system())
+            .on_state_enter(STATE_STAGE, AppState::Editor, spawn_palette.system())
+            .on_state_update(STATE_STAGE, AppState::Editor, palette_buttons.system())
+            .on_state_update(STATE_STAGE, AppState::Editor, edit_squares.system())
+            .on_state_update(STATE_STAGE, AppState::Editor, editor_buttons.system())
+            .on_state_update(STATE_STAGE, AppState::Editor, update_editor_panel.system())
+            .on_state_update(STATE_STAGE, AppState::Editor, drag_label_update.system())
+            .on_state_exit(STATE_STAGE, AppState::Editor, despawn_screen::<Palette>.system())
+            .on_state_exit(STATE_STAGE, AppState::Editor, despawn_screen::<EditorPanel>.system())
+            .on_state_exit(STATE_STAGE, AppState::Editor, despawn_screen::<DragLabel>.system());
+    }
+}
+
+// What's being dragged onto the board.
+#[derive(Clone, Copy)]
+enum Drag {
+    // A new piece from the palette.
+    New(PieceColor, PieceType),
+    // A piece already on the board.
+    Existing(Entity),
+}
+
+// Everything about the position being set up besides the pieces, which are
+// ordinary `Piece` entities.
+pub struct Editor {
+    // The variant the position is for, which decides what's legal.
+    variant: VariantKind,
+    size: BoardSize,
+    side_to_move: PieceColor,
+    // Castling rights asked for; each applies to the outermost rook on that
+    // side of the king.
+    castling: Vec<(PieceColor, CastleSide)>,
+    en_passant: Option<(u8, u8)>,
+    drag: Option<Drag>,
+    // The exported FEN, or why the position can't be exported or played.
+    message: Option<String>,
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Self {
+            variant: VariantKind::Standard,
+            size: BoardSize::STANDARD,
+            side_to_move: PieceColor::White,
+            castling: Vec::new(),
+            en_passant: None,
+            drag: None,
+            message: None,
+        }
+    }
+}
+
+impl Editor {
+    // The editor's settings for starting from `position` in `variant`.
+    fn from_position(position: &Position, variant: VariantKind) -> Self {
+        let mut castling = Vec::new();
+        for color in &[PieceColor::White, PieceColor::Black] {
+            let rights = position.state.castling.get(*color);
+            for side in &[CastleSide::Kingside, CastleSide::Queenside] {
+                if rights.rook_file(*side).is_some() {
+                    castling.push((*color, *side));
+                }
+            }
+        }
+
+        Self {
+            variant,
+            size: position.state.size,
+            side_to_move: position.side_to_move,
+            castling,
+            en_passant: position.state.en_passant,
+            ..Default::default()
+        }
+    }
+
+    // The position as set up, without checking that it's legal.
+    fn draft(&self, pieces: Vec<Piece>) -> Position {
+        Position {
+            pieces,
+            side_to_move: self.side_to_move,
+            state: BoardState {
+                size: self.size,
+                en_passant: self.en_passant,
+                fullmove_number: 1,
+                ..Default::default()
+            },
+        }
+    }
+
+    // The position as set up, if it's legal, or what's wrong with it.
+    fn position(&self, pieces: Vec<Piece>) -> Result<Position, String> {
+        let mut position = self.draft(pieces);
+        for (color, side) in &self.castling {
+            let rook = position.outermost_rook(*color, *side).ok_or_else(|| {
+                format!(
+                    "{} can't castle {} without its king and rook at home",
+                    color.name(),
+                    side_name(*side)
+                )
+            })?;
+            let rights = position.state.castling.get_mut(*color);
+            match side {
+                CastleSide::Kingside => rights.kingside = Some(rook),
+                CastleSide::Queenside => rights.queenside = Some(rook),
+            }
+        }
+        position.validate(self.variant)?;
+        Ok(position)
+    }
+}
+
+fn side_name(side: CastleSide) -> &'static str {
+    match side {
+        CastleSide::Kingside => "kingside",
+        CastleSide::Queenside => "queenside",
+    }
+}
+
+// Root node of the piece palette.
+struct Palette;
+// Picks up a new piece of this colour and type.
+struct PaletteButton(PieceColor, PieceType);
+// Root node of the panel with the position's settings.
+struct EditorPanel;
+// Names the piece being dragged, next to the mouse.
+struct DragLabel;
+
+#[derive(Clone, Copy)]
+enum EditorButton {
+    SideToMove,
+    Castling(PieceColor, CastleSide),
+    EnPassant,
+    Clear,
+    Reset,
+    ExportFen,
+    StartGame,
+    Back,
+}
+
+fn start_editor(setup: Res<GameSetup>, mut editor: ResMut<Editor>) {
+    *editor = Editor::from_position(&setup.start(), setup.variant);
+}
+
+// Spawns the palette of pieces the variant uses, and the label following the
+// mouse while dragging.
+fn spawn_palette(
+    commands: &mut Commands,
+    asset_server: ResMut<AssetServer>,
+    setup: Res<GameSetup>,
+    button_materials: Res<ButtonMaterials>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let material = color_materials.add(Color::rgba(0., 0., 0., 0.7).into());
+    let row_material = color_materials.add(Color::NONE.into());
+    let piece_types = setup.variant.rules().piece_types();
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.),
+                    top: Val::Px(120.),
+                    ..Default::default()
+                },
+                // UI is laid out bottom-up, so reverse the column to list top-down.
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: Rect::all(Val::Px(5.)),
+                ..Default::default()
+            },
+            material,
+            ..Default::default()
+        })
+        .with(Palette)
+        .with_children(|parent| {
+            for color in &[PieceColor::White, PieceColor::Black] {
+                parent.spawn(TextBundle {
+                    text: Text {
+                        value: color.name().to_string(),
+                        font: font.clone(),
+                        style: TextStyle {
+                            font_size: 20.0,
+                            color: Color::rgb(0.8, 0.8, 0.8),
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                });
+                parent
+                    .spawn(NodeBundle {
+                        material: row_material.clone(),
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        for piece_type in &piece_types {
+                            let piece =
+                                Piece { color: *color, piece_type: *piece_type, x: 0, y: 0 };
+                            parent
+                                .spawn(ButtonBundle {
+                                    style: Style {
+                                        size: Size::new(Val::Px(32.), Val::Px(32.)),
+                                        margin: Rect::all(Val::Px(2.)),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..Default::default()
+                                    },
+                                    material: button_materials.normal.clone(),
+                                    ..Default::default()
+                                })
+                                .with(PaletteButton(*color, *piece_type))
+                                .with_children(|parent| {
+                                    parent.spawn(TextBundle {
+                                        text: Text {
+                                            value: piece_char(&piece).to_string(),
+                                            font: font.clone(),
+                                            style: TextStyle {
+                                                font_size: 20.0,
+                                                color: Color::rgb(0.9, 0.9, 0.9),
+                                                ..Default::default()
+                                            },
+                                        },
+                                        ..Default::default()
+                                    });
+                                });
+                        }
+                    });
+            }
+        });
+
+    commands
+        .spawn(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                ..Default::default()
+            },
+            text: Text {
+                value: String::new(),
+                font,
+                style: TextStyle {
+                    font_size: 20.0,
+                    color: Color::rgb(1., 1., 0.6),
+                    ..Default::default()
+                },
+            },
+            ..Default::default()
+        })
+        .with(DragLabel);
+}
+
+// Pressing a palette button picks up a new piece, to be dropped on a square.
+fn palette_buttons(
+    mut editor: ResMut<Editor>,
+    query: Query<(&Interaction, &PaletteButton), Mutated<Interaction>>,
+) {
+    for (interaction, button) in query.iter() {
+        if *interaction == Interaction::Clicked {
+            editor.drag = Some(Drag::New(button.0, button.1));
+        }
+    }
+}
+
+// Drags pieces around the board and drops new ones onto it, replacing
+// whatever stood there; a right-click empties a square.
+fn edit_squares(
+    commands: &mut Commands,
+    pick_state: Res<PickState>,
+    mouse_button_inputs: Res<Input<MouseButton>>,
+    asset_server: Res<AssetServer>,
+    piece_sets: Res<PieceSets>,
+    piece_materials: Res<PieceMaterials>,
+    mut editor: ResMut<Editor>,
+    squares_query: Query<&Square>,
+    mut pieces_query: Query<(Entity, &mut Piece)>,
+) {
+    let square = pick_state
+        .top(Group::default())
+        .and_then(|(entity, _intersection)| squares_query.get(*entity).ok())
+        .map(|square| (square.x, square.y));
+
+    if mouse_button_inputs.just_pressed(MouseButton::Right) {
+        if let Some(pos) = square {
+            for (entity, piece) in pieces_query.iter_mut() {
+                if (piece.x, piece.y) == pos {
+                    commands.despawn_recursive(entity);
+                }
+            }
+            editor.message = None;
+        }
+    }
+
+    // Pressing on a piece picks it up.
+    if mouse_button_inputs.just_pressed(MouseButton::Left) && editor.drag.is_none() {
+        if let Some(pos) = square {
+            editor.drag = pieces_query
+                .iter_mut()
+                .find(|(_, piece)| (piece.x, piece.y) == pos)
+                .map(|(entity, _)| Drag::Existing(entity));
+        }
+    }
+
+    if !mouse_button_inputs.just_released(MouseButton::Left) {
+        return;
+    }
+    // Letting go off the board puts a dragged piece back where it was.
+    let (drag, pos) = match (editor.drag.take(), square) {
+        (Some(drag), Some(pos)) => (drag, pos),
+        _ => return,
+    };
+    editor.message = None;
+
+    for (entity, piece) in pieces_query.iter_mut() {
+        let dragged = matches!(drag, Drag::Existing(dragged) if dragged == entity);
+        if (piece.x, piece.y) == pos && !dragged {
+            commands.despawn_recursive(entity);
+        }
+    }
+    match drag {
+        Drag::New(color, piece_type) => {
+            spawn_piece(
+                commands,
+                &asset_server,
+                piece_sets.current(),
+                &piece_materials,
+                color,
+                piece_type,
+                pos,
+            );
+        }
+        Drag::Existing(entity) => {
+            if let Ok((_, mut piece)) = pieces_query.get_mut(entity) {
+                piece.x = pos.0;
+                piece.y = pos.1;
+            }
+        }
+    }
+}
+
+// Keeps the dragged piece's name next to the mouse.
+fn drag_label_update(
+    editor: Res<Editor>,
+    cursor_events: Res<Events<CursorMoved>>,
+    mut cursor_reader: Local<EventReader<CursorMoved>>,
+    mut cursor_position: Local<Vec2>,
+    pieces_query: Query<&Piece>,
+    mut query: Query<(&mut Text, &mut Style), With<DragLabel>>,
+) {
+    if let Some(event) = cursor_reader.iter(&cursor_events).last() {
+        *cursor_position = event.position;
+    }
+
+    let dragged = match editor.drag {
+        Some(Drag::New(color, piece_type)) => Some((color, piece_type)),
+        Some(Drag::Existing(entity)) => {
+            pieces_query.get(entity).ok().map(|piece| (piece.color, piece.piece_type))
+        }
+        None => None,
+    };
+
+    for (mut text, mut style) in query.iter_mut() {
+        match dragged {
+            Some((color, piece_type)) => {
+                text.value = format!("{} {}", color.name(), piece_type.name());
+                style.position.left = Val::Px(cursor_position.x + 16.);
+                style.position.bottom = Val::Px(cursor_position.y + 8.);
+            }
+            None => text.value.clear(),
+        }
+    }
+}
+
+fn editor_buttons(
+    commands: &mut Commands,
+    asset_server: Res<AssetServer>,
+    piece_sets: Res<PieceSets>,
+    piece_materials: Res<PieceMaterials>,
+    mut setup: ResMut<GameSetup>,
+    mut editor: ResMut<Editor>,
+    mut state: ResMut<State<AppState>>,
+    mut turn: ResMut<PlayerTurn>,
+    pieces_query: Query<(Entity, &Piece)>,
+    query: Query<(&Interaction, &EditorButton), Mutated<Interaction>>,
+) {
+    let pieces: Vec<Piece> = pieces_query.iter().map(|(_, piece)| *piece).collect();
+
+    for (interaction, button) in query.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+        editor.message = None;
+
+        match button {
+            EditorButton::SideToMove => {
+                editor.side_to_move = opponent(editor.side_to_move);
+                editor.en_passant = None;
+            }
+            EditorButton::Castling(color, side) => {
+                let right = (*color, *side);
+                if editor.castling.contains(&right) {
+                    editor.castling.retain(|other| *other != right);
+                } else {
+                    editor.castling.push(right);
+                }
+            }
+            EditorButton::EnPassant => {
+                // Cycle through the squares that make sense, then none.
+                let squares = editor.draft(pieces.clone()).en_passant_squares();
+                let next = editor.en_passant.map_or(0, |current| {
+                    squares.iter().position(|square| *square == current).map_or(0, |idx| idx + 1)
+                });
+                editor.en_passant = squares.get(next).copied();
+            }
+            EditorButton::Clear => {
+                for (entity, _) in pieces_query.iter() {
+                    commands.despawn_recursive(entity);
+                }
+                editor.castling.clear();
+                editor.en_passant = None;
+            }
+            EditorButton::Reset => {
+                for (entity, _) in pieces_query.iter() {
+                    commands.despawn_recursive(entity);
+                }
+                let start = setup.start();
+                for piece in start.pieces.iter() {
+                    spawn_piece(
+                        commands,
+                        &asset_server,
+                        piece_sets.current(),
+                        &piece_materials,
+                        piece.color,
+                        piece.piece_type,
+                        (piece.x, piece.y),
+                    );
+                }
+                *editor = Editor::from_position(&start, setup.variant);
+            }
+            EditorButton::ExportFen => {
+                editor.message = Some(match editor.position(pieces.clone()) {
+                    Ok(position) => to_fen(&position, false),
+                    Err(err) => err,
+                });
+            }
+            EditorButton::StartGame => match editor.position(pieces.clone()) {
+                Ok(position) => {
+                    setup.start_position = StartPosition::Fen(to_fen(&position, false));
+                    // A network game waits in the menu for the other player.
+                    let next = match setup.mode.is_network() {
+                        true => AppState::Menu,
+                        false => AppState::Playing,
+                    };
+                    state.set_next(next).unwrap();
+                }
+                Err(err) => editor.message = Some(err),
+            },
+            EditorButton::Back => state.set_next(AppState::Menu).unwrap(),
+        }
+    }
+
+    // The next-move text shows the side to move.
+    if turn.0 != editor.side_to_move {
+        turn.0 = editor.side_to_move;
+    }
+}
+
+// Labels of the editor panel's buttons and the text lines below them.
+fn editor_panel_contents(
+    editor: &Editor,
+    pieces: Vec<Piece>,
+) -> (Vec<(EditorButton, String)>, Vec<String>) {
+    let mut options = vec![(
+        EditorButton::SideToMove,
+        format!("To move: {}", editor.side_to_move.name()),
+    )];
+    for color in &[PieceColor::White, PieceColor::Black] {
+        for (side, castle) in &[(CastleSide::Kingside, "O-O"), (CastleSide::Queenside, "O-O-O")] {
+            let allowed = if editor.castling.contains(&(*color, *side)) { "yes" } else { "no" };
+            options.push((
+                EditorButton::Castling(*color, *side),
+                format!("{} {}: {}", color.name(), castle, allowed),
+            ));
+        }
+    }
+    options.push((
+        EditorButton::EnPassant,
+        format!("En passant: {}", editor.en_passant.map_or("-".to_string(), square_name)),
+    ));
+    options.push((EditorButton::Clear, "Clear board".to_string()));
+    options.push((EditorButton::Reset, "Starting position".to_string()));
+    options.push((EditorButton::ExportFen, "Export FEN".to_string()));
+    options.push((EditorButton::StartGame, "Start game".to_string()));
+    options.push((EditorButton::Back, "Main menu".to_string()));
+
+    let mut lines = vec![match editor.position(pieces) {
+        Ok(_) => "Position is legal".to_string(),
+        Err(err) => err,
+    }];
+    if let Some(message) = &editor.message {
+        lines.push(message.clone());
+    }
+
+    (options, lines)
+}
+
+// Spawns the editor panel, and rebuilds it whenever its contents change.
+fn update_editor_panel(
+    commands: &mut Commands,
+    asset_server: ResMut<AssetServer>,
+    editor: Res<Editor>,
+    button_materials: Res<ButtonMaterials>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+    mut shown: Local<Vec<String>>,
+    pieces_query: Query<&Piece>,
+    query: Query<Entity, With<EditorPanel>>,
+) {
+    let (options, lines) = editor_panel_contents(&editor, pieces_query.iter().copied().collect());
+    let contents: Vec<String> = options
+        .iter()
+        .map(|(_, label)| label.clone())
+        .chain(lines.iter().cloned())
+        .collect();
+
+    let spawned = query.iter().next().is_some();
+    if spawned && *shown == contents {
+        return;
+    }
+    for entity in query.iter() {
+        commands.despawn_recursive(entity);
+    }
+    *shown = contents;
+
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let material = color_materials.add(Color::rgba(0., 0., 0., 0.7).into());
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(10.),
+                    bottom: Val::Px(10.),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::FlexEnd,
+                padding: Rect::all(Val::Px(5.)),
+                ..Default::default()
+            },
+            material,
+            ..Default::default()
+        })
+        .with(EditorPanel)
+        .with_children(|parent| {
+            for (button, label) in options {
+                spawn_button(parent, &label, font.clone(), &button_materials).with(button);
+            }
+            for line in lines {
+                parent.spawn(TextBundle {
+                    text: Text {
+                        value: line,
+                        font: font.clone(),
+                        style: TextStyle {
+                            font_size: 18.0,
+                            color: Color::rgb(0.8, 0.8, 0.8),
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                });
+            }
+        });
+}
@@ -0,0 +1,385 @@
+use crate::{
+    board::{MoveTextInput, PlayerTurn},
+    moves::{GameEnded, MoveApplied},
+    network::DEFAULT_PORT,
+    notation::{parse_fen, STANDARD_FEN},
+    pieces::PieceColor,
+    position::{chess960_position, opponent, BoardSize, Position},
+    puzzle::PuzzleTrainer,
+    tablebase::Tablebases,
+    variant::VariantKind,
+};
+use bevy::prelude::*;
+
+// Stage running the systems tied to `AppState`.
+pub const STATE_STAGE: &str = "app_state";
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum AppState {
+    Menu,
+    // Setting up a position by hand.
+    Editor,
+    Playing,
+    GameOver,
+}
+
+pub struct GamePlugin;
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_resource(State::new(AppState::Menu))
+            .add_stage_after(stage::UPDATE, STATE_STAGE, StateStage::<AppState>::default())
+            .init_resource::<GameSetup>()
+            .init_resource::<Clocks>()
+            .init_resource::<GameResult>()
+            .init_resource::<PuzzleTrainer>()
+            .init_resource::<Tablebases>()
+            .init_resource::<Paused>()
+            .on_state_exit(STATE_STAGE, AppState::Menu, deal_chess960.system())
+            .on_state_exit(STATE_STAGE, AppState::GameOver, deal_chess960.system())
+            .on_state_enter(STATE_STAGE, AppState::Playing, start_clocks.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, toggle_pause.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, tick_clocks.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, add_increments.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, end_game.system());
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum GameMode {
+    // Two players sharing the machine; either side may be the computer.
+    Local,
+    // One human against the computer.
+    VsComputer,
+    // Free play: no clocks, and both sides are moved by hand.
+    Analysis,
+    // Tactics training: find the solution's moves while the opponent's
+    // replies are played from it.
+    Puzzles,
+    // A game against another player over the network, set up here and
+    // waiting for them to join.
+    Host,
+    // Joining a game another player hosts, which brings its own setup.
+    Join,
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 6] = [
+        GameMode::Local,
+        GameMode::VsComputer,
+        GameMode::Analysis,
+        GameMode::Puzzles,
+        GameMode::Host,
+        GameMode::Join,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameMode::Local => "Local",
+            GameMode::VsComputer => "Vs computer",
+            GameMode::Analysis => "Analysis",
+            GameMode::Puzzles => "Puzzles",
+            GameMode::Host => "Host network game",
+            GameMode::Join => "Join network game",
+        }
+    }
+
+    pub fn is_network(&self) -> bool {
+        matches!(self, GameMode::Host | GameMode::Join)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PlayerType {
+    Human,
+    Computer,
+    // The other player of a network game.
+    Remote,
+}
+
+impl PlayerType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlayerType::Human => "Human",
+            PlayerType::Computer => "Computer",
+            PlayerType::Remote => "Remote",
+        }
+    }
+}
+
+// Minutes on each clock and seconds added after every move; `None` is untimed.
+#[derive(Clone, Copy, PartialEq)]
+pub struct TimeControl(pub Option<(u32, u32)>);
+
+impl TimeControl {
+    pub const ALL: [TimeControl; 5] = [
+        TimeControl(None),
+        TimeControl(Some((3, 2))),
+        TimeControl(Some((5, 0))),
+        TimeControl(Some((10, 0))),
+        TimeControl(Some((30, 0))),
+    ];
+
+    pub fn name(&self) -> String {
+        match self.0 {
+            Some((minutes, increment)) => format!("{}+{}", minutes, increment),
+            None => "Untimed".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub enum StartPosition {
+    // The variant's usual starting position.
+    Standard,
+    Fen(String),
+    // Chess960 position by number (0-959), or a random one.
+    Chess960(Option<u16>),
+}
+
+impl StartPosition {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StartPosition::Standard => "Standard",
+            StartPosition::Fen(_) => "FEN",
+            StartPosition::Chess960(_) => "Chess960",
+        }
+    }
+}
+
+// Options picked in the main menu for the next game.
+#[derive(Clone)]
+pub struct GameSetup {
+    pub mode: GameMode,
+    pub variant: VariantKind,
+    pub time_control: TimeControl,
+    pub start_position: StartPosition,
+    // Who plays each side in a local game.
+    pub white: PlayerType,
+    pub black: PlayerType,
+    // The human's side when playing the computer, solving a puzzle or
+    // playing over the network.
+    pub human_side: PieceColor,
+    // Where the game to join is hosted, as "host:port" or just the host.
+    pub host_address: String,
+    // The position a random Chess960 start deals, drawn again before each
+    // game so everything setting the game up agrees on it.
+    pub random_chess960: u16,
+}
+
+impl Default for GameSetup {
+    fn default() -> Self {
+        Self {
+            mode: GameMode::Local,
+            variant: VariantKind::Standard,
+            time_control: TimeControl(None),
+            start_position: StartPosition::Standard,
+            white: PlayerType::Human,
+            black: PlayerType::Human,
+            human_side: PieceColor::White,
+            host_address: format!("127.0.0.1:{}", DEFAULT_PORT),
+            random_chess960: random_chess960_index(),
+        }
+    }
+}
+
+impl GameSetup {
+    pub fn player(&self, color: PieceColor) -> PlayerType {
+        match self.mode {
+            GameMode::Local => match color {
+                PieceColor::White => self.white,
+                PieceColor::Black => self.black,
+            },
+            GameMode::VsComputer | GameMode::Puzzles if color == self.human_side => {
+                PlayerType::Human
+            }
+            GameMode::VsComputer | GameMode::Puzzles => PlayerType::Computer,
+            GameMode::Host | GameMode::Join if color == self.human_side => PlayerType::Human,
+            GameMode::Host | GameMode::Join => PlayerType::Remote,
+            GameMode::Analysis => PlayerType::Human,
+        }
+    }
+
+    // The position the game starts from; a FEN that doesn't parse falls
+    // back to the standard position.
+    pub fn start(&self) -> Position {
+        match &self.start_position {
+            StartPosition::Standard => Some(self.variant.rules().setup()),
+            StartPosition::Fen(fen) => parse_fen(fen),
+            StartPosition::Chess960(index) => {
+                Some(chess960_position(index.unwrap_or(self.random_chess960)))
+            }
+        }
+        .unwrap_or_else(|| parse_fen(STANDARD_FEN).unwrap())
+    }
+
+    // Size of the board the game is played on.
+    pub fn board_size(&self) -> BoardSize {
+        match self.start_position {
+            StartPosition::Chess960(_) => BoardSize::STANDARD,
+            _ => self.start().state.size,
+        }
+    }
+}
+
+// Leaving the menu or a finished game may start a new one, which gets its
+// own random Chess960 position.
+fn deal_chess960(mut setup: ResMut<GameSetup>) {
+    setup.random_chess960 = random_chess960_index();
+}
+
+// A Chess960 starting position picked at random (seeded from the clock).
+fn random_chess960_index() -> u16 {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.subsec_nanos());
+    (nanos % 960) as u16
+}
+
+// Time left for each side, in seconds.
+#[derive(Default)]
+pub struct Clocks {
+    pub white: f32,
+    pub black: f32,
+    pub increment: f32,
+    pub running: bool,
+}
+
+impl Clocks {
+    // The clocks for a game set up as `setup`; only games against a time
+    // control have them running.
+    pub fn start(setup: &GameSetup) -> Self {
+        match (setup.mode, setup.time_control.0) {
+            (GameMode::Analysis, _) | (GameMode::Puzzles, _) | (_, None) => Clocks::default(),
+            (_, Some((minutes, increment))) => Clocks {
+                white: minutes as f32 * 60.,
+                black: minutes as f32 * 60.,
+                increment: increment as f32,
+                running: true,
+            },
+        }
+    }
+
+    pub fn remaining(&self, color: PieceColor) -> f32 {
+        match color {
+            PieceColor::White => self.white,
+            PieceColor::Black => self.black,
+        }
+    }
+
+    // Adds the increment for the side that just moved.
+    pub fn moved(&mut self, color: PieceColor) {
+        if !self.running {
+            return;
+        }
+        match color {
+            PieceColor::White => self.white += self.increment,
+            PieceColor::Black => self.black += self.increment,
+        }
+    }
+
+    // Takes `seconds` off `color`'s clock, stopping the clocks and giving the
+    // result if that runs it out.
+    pub fn run_down(&mut self, color: PieceColor, seconds: f32) -> Option<GameResult> {
+        if !self.running {
+            return None;
+        }
+        let remaining = match color {
+            PieceColor::White => &mut self.white,
+            PieceColor::Black => &mut self.black,
+        };
+        *remaining = (*remaining - seconds).max(0.);
+        if *remaining > 0. {
+            return None;
+        }
+        self.running = false;
+        Some(GameResult {
+            winner: Some(opponent(color)),
+            reason: "timeout".to_string(),
+        })
+    }
+}
+
+// Whether the game is paused: clocks stop, and neither side (nor the
+// analysis) moves until it's resumed.
+#[derive(Default)]
+pub struct Paused(pub bool);
+
+// How the last game ended.
+#[derive(Clone, Default)]
+pub struct GameResult {
+    pub winner: Option<PieceColor>,
+    pub reason: String,
+}
+
+impl GameResult {
+    pub fn describe(&self) -> String {
+        match self.winner {
+            Some(PieceColor::White) => format!("White won by {}", self.reason),
+            Some(PieceColor::Black) => format!("Black won by {}", self.reason),
+            None => format!("Draw by {}", self.reason),
+        }
+    }
+}
+
+fn start_clocks(setup: Res<GameSetup>, mut clocks: ResMut<Clocks>, mut paused: ResMut<Paused>) {
+    paused.0 = false;
+    *clocks = Clocks::start(&setup);
+}
+
+// Pressing P pauses and resumes the game, except over the network, where
+// the other player's clock would carry on.
+fn toggle_pause(
+    keyboard_input: Res<Input<KeyCode>>,
+    move_text: Res<MoveTextInput>,
+    setup: Res<GameSetup>,
+    mut paused: ResMut<Paused>,
+) {
+    if !move_text.focused
+        && !setup.mode.is_network()
+        && keyboard_input.just_pressed(KeyCode::P)
+    {
+        paused.0 = !paused.0;
+    }
+}
+
+// Runs down the clock of the side to move, ending the game when it flags.
+fn tick_clocks(
+    time: Res<Time>,
+    turn: Res<PlayerTurn>,
+    paused: Res<Paused>,
+    mut clocks: ResMut<Clocks>,
+    mut ended_events: ResMut<Events<GameEnded>>,
+) {
+    if paused.0 {
+        return;
+    }
+    if let Some(result) = clocks.run_down(turn.0, time.delta_seconds()) {
+        ended_events.send(GameEnded { result });
+    }
+}
+
+// Adds the increment to the clock of each side that moves.
+fn add_increments(
+    mut applied_reader: Local<EventReader<MoveApplied>>,
+    applied_events: Res<Events<MoveApplied>>,
+    mut clocks: ResMut<Clocks>,
+) {
+    for applied in applied_reader.iter(&applied_events) {
+        clocks.moved(applied.color);
+    }
+}
+
+// Ends the game with the first result announced for it.
+fn end_game(
+    mut ended_reader: Local<EventReader<GameEnded>>,
+    ended_events: Res<Events<GameEnded>>,
+    mut clocks: ResMut<Clocks>,
+    mut result: ResMut<GameResult>,
+    mut state: ResMut<State<AppState>>,
+) {
+    if let Some(ended) = ended_reader.iter(&ended_events).next() {
+        clocks.running = false;
+        *result = ended.result.clone();
+        state.set_next(AppState::GameOver).unwrap();
+    }
+}
@@ -0,0 +1,305 @@
+use crate::{
+    ai::Move,
+    game::*,
+    moves::MoveApplied,
+    notation::{to_fen, STANDARD_FEN},
+    piece_set::PieceSets,
+    pieces::*,
+    position::Position,
+    variant::VariantKind,
+};
+use bevy::prelude::*;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub struct HistoryPlugin;
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<MoveHistory>()
+            .on_state_enter(STATE_STAGE, AppState::Playing, clear_history.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, record_moves.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, show_history_ply.system())
+            .on_state_update(STATE_STAGE, AppState::GameOver, show_history_ply.system());
+    }
+}
+
+// A move as it was played.
+#[derive(Clone)]
+pub struct PlayedMove {
+    // As made on the board: the piece's origin and the square picked for it.
+    pub mv: Move,
+    pub san: String,
+    // The position it led to.
+    pub after: Position,
+}
+
+// Extra text for a move in the PGN: a suffix such as "?" or "!" and an
+// optional comment.
+#[derive(Default)]
+pub struct Annotation {
+    pub suffix: &'static str,
+    pub comment: Option<String>,
+}
+
+// The moves of the game being played (or just finished), and which of its
+// positions the board is showing.
+#[derive(Clone, Default)]
+pub struct MoveHistory {
+    start: Option<Position>,
+    pub moves: Vec<PlayedMove>,
+    // Number of moves into the game the board shows; `None` is the position
+    // on the board now.
+    pub shown: Option<usize>,
+}
+
+impl MoveHistory {
+    // An empty history for a game starting from `start`.
+    pub fn new(start: Position) -> Self {
+        Self {
+            start: Some(start),
+            ..Default::default()
+        }
+    }
+
+    // Adds a move just made from `before`.
+    pub fn record(&mut self, before: &Position, mv: Move, san: String, after: Position) {
+        if self.start.is_none() {
+            self.start = Some(before.clone());
+        }
+        self.moves.push(PlayedMove { mv, san, after });
+    }
+
+    // The position `ply` moves into the game.
+    pub fn position(&self, ply: usize) -> Option<&Position> {
+        match ply {
+            0 => self.start.as_ref(),
+            _ => self.moves.get(ply - 1).map(|played| &played.after),
+        }
+    }
+
+    // The position picked to look at, while the board is showing an earlier
+    // one than the game's. The pieces are there, but the game's turn and
+    // board state aren't this position's.
+    pub fn shown_position(&self) -> Option<&Position> {
+        self.position(self.shown?)
+    }
+
+    // The game's positions, from the start to the one on the board now.
+    pub fn positions(&self) -> Vec<Position> {
+        (0..=self.moves.len())
+            .filter_map(|ply| self.position(ply).cloned())
+            .collect()
+    }
+
+    // The move leading to position `ply` as it's numbered in the game's
+    // text, e.g. "12." or "12...".
+    pub fn move_number(&self, ply: usize) -> String {
+        let before = match self.position(ply.saturating_sub(1)) {
+            Some(before) if ply > 0 => before,
+            _ => return String::new(),
+        };
+        match before.side_to_move {
+            PieceColor::White => format!("{}.", before.state.fullmove_number),
+            PieceColor::Black => format!("{}...", before.state.fullmove_number),
+        }
+    }
+
+    // The game as PGN, with `annotations` (by move, and possibly fewer than
+    // there are moves) added to the moves.
+    pub fn to_pgn(
+        &self,
+        setup: &GameSetup,
+        result: &GameResult,
+        annotations: &[Annotation],
+    ) -> String {
+        let outcome = match result.winner {
+            Some(PieceColor::White) => "1-0",
+            Some(PieceColor::Black) => "0-1",
+            None => "1/2-1/2",
+        };
+
+        let mut headers = vec![
+            ("Event", format!("{} game", setup.mode.name())),
+            ("Site", "bevy-chess".to_string()),
+            ("Date", today()),
+            ("Round", "-".to_string()),
+            ("White", setup.player(PieceColor::White).name().to_string()),
+            ("Black", setup.player(PieceColor::Black).name().to_string()),
+            ("Result", outcome.to_string()),
+            ("Termination", result.reason.clone()),
+        ];
+        let chess960 = matches!(setup.start_position, StartPosition::Chess960(_));
+        if setup.variant != VariantKind::Standard {
+            headers.push(("Variant", setup.variant.rules().name().to_string()));
+        } else if chess960 {
+            headers.push(("Variant", "Chess960".to_string()));
+        }
+        // Chess960 games always give their position, even the one that's
+        // the standard start.
+        if let Some(start) = &self.start {
+            let fen = to_fen(start, false);
+            if fen != STANDARD_FEN || chess960 {
+                headers.push(("SetUp", "1".to_string()));
+                headers.push(("FEN", fen));
+            }
+        }
+
+        let mut pgn: String = headers
+            .iter()
+            .map(|(name, value)| format!("[{} \"{}\"]\n", name, value.replace('"', "'")))
+            .collect();
+        pgn.push('\n');
+
+        let mut tokens = Vec::new();
+        for (idx, played) in self.moves.iter().enumerate() {
+            let number = self.move_number(idx + 1);
+            // Black's moves only carry a number to start the game or
+            // after a comment.
+            let after_comment = idx > 0
+                && annotations
+                    .get(idx - 1)
+                    .is_some_and(|annotation| annotation.comment.is_some());
+            if !number.ends_with("...") || idx == 0 || after_comment {
+                tokens.push(number);
+            }
+
+            let annotation = annotations.get(idx);
+            let suffix = annotation.map_or("", |annotation| annotation.suffix);
+            tokens.push(format!("{}{}", played.san, suffix));
+            if let Some(comment) = annotation.and_then(|annotation| annotation.comment.as_ref()) {
+                tokens.push(format!("{{ {} }}", comment));
+            }
+        }
+        tokens.push(outcome.to_string());
+
+        // Movetext lines are kept under 80 characters.
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + token.len() + 1 > 79 {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        pgn.push_str(&line);
+        pgn.push('\n');
+        pgn
+    }
+}
+
+// Today's date as PGN writes it, e.g. "2021.01.23".
+fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() / 86_400) as i64;
+
+    // Days since 1970 to a civil date, after Howard Hinnant's algorithm.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+fn clear_history(setup: Res<GameSetup>, mut history: ResMut<MoveHistory>) {
+    *history = MoveHistory::new(setup.start());
+}
+
+// Adds each move to the history as it's played.
+fn record_moves(
+    mut applied_reader: Local<EventReader<MoveApplied>>,
+    applied_events: Res<Events<MoveApplied>>,
+    mut history: ResMut<MoveHistory>,
+) {
+    for applied in applied_reader.iter(&applied_events) {
+        history.record(&applied.before, applied.mv, applied.san.clone(), applied.after.clone());
+    }
+}
+
+// Puts the position picked in the history on the board, or the one the game
+// reached when going back to it.
+fn show_history_ply(
+    commands: &mut Commands,
+    asset_server: Res<AssetServer>,
+    piece_sets: Res<PieceSets>,
+    piece_materials: Res<PieceMaterials>,
+    history: Res<MoveHistory>,
+    mut showing: Local<Option<usize>>,
+    mut pieces_query: Query<(Entity, &mut Piece)>,
+) {
+    if *showing == history.shown {
+        return;
+    }
+    *showing = history.shown;
+
+    let ply = history.shown.unwrap_or_else(|| history.moves.len());
+    let position = match history.position(ply) {
+        Some(position) => position,
+        None => return,
+    };
+    show_position(
+        commands,
+        &asset_server,
+        piece_sets.current(),
+        &piece_materials,
+        position,
+        &mut pieces_query,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pgn(setup: &GameSetup) -> String {
+        let result = GameResult { winner: None, reason: "agreement".to_string() };
+        MoveHistory::new(setup.start()).to_pgn(setup, &result, &[])
+    }
+
+    #[test]
+    fn standard_start() {
+        let pgn = pgn(&GameSetup::default());
+        assert!(!pgn.contains("[Variant "));
+        assert!(!pgn.contains("[SetUp "));
+        assert!(pgn.ends_with("\n1/2-1/2\n"));
+    }
+
+    #[test]
+    fn custom_start_without_moves() {
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1";
+        let setup = GameSetup {
+            start_position: StartPosition::Fen(fen.to_string()),
+            ..Default::default()
+        };
+        let pgn = pgn(&setup);
+        assert!(pgn.contains("[SetUp \"1\"]\n"));
+        assert!(pgn.contains(&format!("[FEN \"{}\"]\n", fen)));
+    }
+
+    #[test]
+    fn chess960_start() {
+        let setup = GameSetup {
+            start_position: StartPosition::Chess960(Some(0)),
+            ..Default::default()
+        };
+        let text = pgn(&setup);
+        assert!(text.contains("[Variant \"Chess960\"]\n"));
+        assert!(text.contains(&format!("[FEN \"{}\"]\n", to_fen(&setup.start(), false))));
+
+        // Position 518 is the usual start, which still gets its FEN.
+        let setup = GameSetup {
+            start_position: StartPosition::Chess960(Some(518)),
+            ..Default::default()
+        };
+        assert!(pgn(&setup).contains(&format!("[FEN \"{}\"]\n", STANDARD_FEN)));
+    }
+}
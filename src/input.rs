@@ -0,0 +1,463 @@
+use crate::{
+    board::PlayerTurn,
+    game::*,
+    history::MoveHistory,
+    pieces::*,
+    ui::{despawn_screen, spawn_button, ButtonMaterials},
+    variant::Variant,
+};
+use bevy::prelude::*;
+
+pub struct InputPlugin;
+impl Plugin for InputPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<InputState>()
+            .on_state_update(STATE_STAGE, AppState::Playing, lock_input.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, promotion_picker_update.system())
+            .on_state_enter(STATE_STAGE, AppState::GameOver, lock_game_over.system())
+            .on_state_enter(
+                STATE_STAGE,
+                AppState::GameOver,
+                despawn_screen::<PromotionPicker>.system(),
+            );
+    }
+}
+
+// Where the player is in making a move on the board. Clicks, drags, the
+// keyboard cursor and typed moves all go through it, and it stays locked
+// while the board isn't the player's to move on, which is when the
+// computer, a puzzle's solution, the other player of a network game or the
+// move list's replay has it.
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum InputState {
+    // Nothing picked up.
+    #[default]
+    Idle,
+    // A piece of the side to move is picked up; the next square chosen is
+    // where it goes.
+    PieceSelected { piece: Entity, from: (u8, u8) },
+    // A piece is held with the mouse button down, over the square `over`.
+    // `reselected` if it was already selected, so putting it back down
+    // where it started lets go of it.
+    Dragging {
+        piece: Entity,
+        from: (u8, u8),
+        over: Option<(u8, u8)>,
+        reselected: bool,
+    },
+    // A pawn's move to the last rank, waiting for the piece it becomes.
+    AwaitingPromotion { from: (u8, u8), to: (u8, u8) },
+    // The board takes no moves from the player.
+    Locked(LockReason),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LockReason {
+    Paused,
+    // An earlier position from the history is on the board.
+    Browsing,
+    // The computer, or a puzzle's solution, moves next.
+    ComputerToMove,
+    // The other player of a network game moves next.
+    OpponentToMove,
+    GameOver,
+}
+
+// A move the player asked for, still to be checked against the rules.
+#[derive(Clone, Copy)]
+pub struct MoveAttempt {
+    pub from: (u8, u8),
+    pub to: (u8, u8),
+    // What a pawn reaching the last rank becomes; a queen if not given.
+    pub promotion: Option<PieceType>,
+}
+
+impl InputState {
+    // The square of the piece picked up, if any.
+    pub fn selected(&self) -> Option<(u8, u8)> {
+        match *self {
+            InputState::PieceSelected { from, .. }
+            | InputState::Dragging { from, .. }
+            | InputState::AwaitingPromotion { from, .. } => Some(from),
+            _ => None,
+        }
+    }
+
+    // The piece held with the mouse and the square it's over.
+    pub fn dragged(&self) -> Option<(Entity, (u8, u8))> {
+        match *self {
+            InputState::Dragging { piece, over: Some(over), .. } => Some((piece, over)),
+            _ => None,
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        matches!(self, InputState::Locked(_))
+    }
+
+    // Locks the board, dropping whatever the player had picked up.
+    pub fn lock(&mut self, reason: LockReason) {
+        if *self != InputState::Locked(reason) {
+            *self = InputState::Locked(reason);
+        }
+    }
+
+    pub fn unlock(&mut self) {
+        if self.is_locked() {
+            *self = InputState::Idle;
+        }
+    }
+
+    // Puts down whatever the player has picked up.
+    pub fn cancel(&mut self) {
+        if !self.is_locked() && *self != InputState::Idle {
+            *self = InputState::Idle;
+        }
+    }
+
+    // A square chosen with a click or the keyboard cursor. `own` is the
+    // piece of the side to move standing on it, and `legal` whether the
+    // selected piece may move there: clicking another of the player's
+    // pieces selects it instead, unless it's a rook the king castles onto.
+    pub fn click(
+        &mut self,
+        square: (u8, u8),
+        own: Option<Entity>,
+        legal: bool,
+    ) -> Option<MoveAttempt> {
+        match *self {
+            InputState::Locked(_) | InputState::AwaitingPromotion { .. } => None,
+            InputState::PieceSelected { from, .. } if legal => {
+                *self = InputState::Idle;
+                Some(MoveAttempt { from, to: square, promotion: None })
+            }
+            InputState::PieceSelected { piece, .. } => {
+                *self = match own {
+                    Some(other) if other != piece => {
+                        InputState::PieceSelected { piece: other, from: square }
+                    }
+                    _ => InputState::Idle,
+                };
+                None
+            }
+            _ => {
+                *self = own.map_or(InputState::Idle, |piece| InputState::PieceSelected {
+                    piece,
+                    from: square,
+                });
+                None
+            }
+        }
+    }
+
+    // The mouse button goes down over `square`, or off the board: picks up
+    // one of the player's pieces to drag, or else acts as a click.
+    pub fn press(
+        &mut self,
+        square: Option<(u8, u8)>,
+        own: Option<Entity>,
+        legal: bool,
+    ) -> Option<MoveAttempt> {
+        let selected = match *self {
+            InputState::Locked(_) | InputState::AwaitingPromotion { .. } => return None,
+            InputState::PieceSelected { piece, .. } => Some(piece),
+            _ => None,
+        };
+        let square = match square {
+            Some(square) => square,
+            None => {
+                self.cancel();
+                return None;
+            }
+        };
+        match own {
+            Some(piece) if !legal => {
+                *self = InputState::Dragging {
+                    piece,
+                    from: square,
+                    over: Some(square),
+                    reselected: selected == Some(piece),
+                };
+                None
+            }
+            _ => self.click(square, own, legal),
+        }
+    }
+
+    // Follows the mouse with a held piece.
+    pub fn hover(&mut self, square: Option<(u8, u8)>) {
+        if let InputState::Dragging { over, .. } = self {
+            *over = square;
+        }
+    }
+
+    // The mouse button comes up over `square`, or off the board: a held
+    // piece goes there, or is put back where it was picked up.
+    pub fn release(&mut self, square: Option<(u8, u8)>) -> Option<MoveAttempt> {
+        let (piece, from, reselected) = match *self {
+            InputState::Dragging { piece, from, reselected, .. } => (piece, from, reselected),
+            _ => return None,
+        };
+        match square {
+            Some(to) if to != from => {
+                *self = InputState::Idle;
+                Some(MoveAttempt { from, to, promotion: None })
+            }
+            Some(_) if !reselected => {
+                *self = InputState::PieceSelected { piece, from };
+                None
+            }
+            _ => {
+                *self = InputState::Idle;
+                None
+            }
+        }
+    }
+
+    // Holds a pawn's move to the last rank back until its piece is chosen.
+    pub fn await_promotion(&mut self, attempt: MoveAttempt) {
+        *self = InputState::AwaitingPromotion { from: attempt.from, to: attempt.to };
+    }
+
+    // The piece chosen for the pawn waiting to promote.
+    pub fn promote(&mut self, piece_type: PieceType) -> Option<MoveAttempt> {
+        match *self {
+            InputState::AwaitingPromotion { from, to } => {
+                *self = InputState::Idle;
+                Some(MoveAttempt { from, to, promotion: Some(piece_type) })
+            }
+            _ => None,
+        }
+    }
+}
+
+// The pieces a pawn may become in `variant`, best first.
+pub fn promotion_choices(variant: &dyn Variant) -> Vec<PieceType> {
+    let mut choices: Vec<PieceType> = variant
+        .piece_types()
+        .into_iter()
+        .filter(|piece_type| !matches!(piece_type, PieceType::King | PieceType::Pawn))
+        .collect();
+    // The queen heads the list, as the piece a promotion makes by default.
+    if let Some(idx) = choices.iter().position(|piece_type| *piece_type == PieceType::Queen) {
+        choices.remove(idx);
+        choices.insert(0, PieceType::Queen);
+    }
+    choices
+}
+
+// Locks the board while the game is paused, an earlier position is shown
+// or it isn't a human's turn, and frees it once none of those hold.
+fn lock_input(
+    setup: Res<GameSetup>,
+    turn: Res<PlayerTurn>,
+    paused: Res<Paused>,
+    history: Res<MoveHistory>,
+    mut input: ResMut<InputState>,
+) {
+    let reason = if paused.0 {
+        Some(LockReason::Paused)
+    } else if history.shown.is_some() {
+        Some(LockReason::Browsing)
+    } else {
+        match setup.player(turn.0) {
+            PlayerType::Human => None,
+            PlayerType::Computer => Some(LockReason::ComputerToMove),
+            PlayerType::Remote => Some(LockReason::OpponentToMove),
+        }
+    };
+    match reason {
+        Some(reason) => input.lock(reason),
+        None => input.unlock(),
+    }
+}
+
+fn lock_game_over(mut input: ResMut<InputState>) {
+    input.lock(LockReason::GameOver);
+}
+
+// Root node of the promotion picker.
+struct PromotionPicker;
+// A piece to promote to, in the picker.
+pub struct PromotionButton(pub PieceType);
+
+// Shows the pieces a pawn can become while its move waits for one of them.
+fn promotion_picker_update(
+    commands: &mut Commands,
+    asset_server: Res<AssetServer>,
+    setup: Res<GameSetup>,
+    input: Res<InputState>,
+    button_materials: Res<ButtonMaterials>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+    query: Query<Entity, With<PromotionPicker>>,
+) {
+    let awaiting = matches!(*input, InputState::AwaitingPromotion { .. });
+    if awaiting == query.iter().next().is_some() {
+        return;
+    }
+    for entity in query.iter() {
+        commands.despawn_recursive(entity);
+    }
+    if !awaiting {
+        return;
+    }
+
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(50.),
+                    top: Val::Percent(35.),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                padding: Rect::all(Val::Px(5.)),
+                ..Default::default()
+            },
+            material: color_materials.add(Color::rgba(0., 0., 0., 0.7).into()),
+            ..Default::default()
+        })
+        .with(PromotionPicker)
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text {
+                    value: "Promote to".to_string(),
+                    font: font.clone(),
+                    style: TextStyle {
+                        font_size: 20.0,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                        ..Default::default()
+                    },
+                },
+                ..Default::default()
+            });
+            for piece_type in promotion_choices(setup.variant.rules()) {
+                spawn_button(parent, piece_type.name(), font.clone(), &button_materials)
+                    .with(PromotionButton(piece_type));
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KNIGHT: (u8, u8) = (0, 1);
+    const BISHOP: (u8, u8) = (0, 2);
+    const KING: (u8, u8) = (0, 4);
+    const ROOK: (u8, u8) = (0, 7);
+
+    fn piece(id: u32) -> Entity {
+        Entity::new(id)
+    }
+
+    fn is_attempt(attempt: Option<MoveAttempt>, from: (u8, u8), to: (u8, u8)) -> bool {
+        matches!(attempt, Some(attempt) if attempt.from == from && attempt.to == to)
+    }
+
+    #[test]
+    fn reselecting() {
+        let mut input = InputState::Idle;
+        assert!(input.click(KNIGHT, Some(piece(1)), false).is_none());
+        assert!(input == InputState::PieceSelected { piece: piece(1), from: KNIGHT });
+
+        // Another of the player's pieces takes over the selection.
+        assert!(input.click(BISHOP, Some(piece(2)), false).is_none());
+        assert!(input == InputState::PieceSelected { piece: piece(2), from: BISHOP });
+
+        // The selected piece itself lets go of it.
+        assert!(input.click(BISHOP, Some(piece(2)), false).is_none());
+        assert!(input == InputState::Idle);
+    }
+
+    #[test]
+    fn dropping_back_on_the_origin() {
+        // A piece picked up and put back where it was stays selected.
+        let mut input = InputState::Idle;
+        assert!(input.press(Some(KNIGHT), Some(piece(1)), false).is_none());
+        assert!(input.dragged() == Some((piece(1), KNIGHT)));
+        input.hover(Some((2, 2)));
+        input.hover(Some(KNIGHT));
+        assert!(input.release(Some(KNIGHT)).is_none());
+        assert!(input == InputState::PieceSelected { piece: piece(1), from: KNIGHT });
+
+        // Picked up again while selected and put back, it's let go of.
+        assert!(input.press(Some(KNIGHT), Some(piece(1)), false).is_none());
+        assert!(input.release(Some(KNIGHT)).is_none());
+        assert!(input == InputState::Idle);
+    }
+
+    #[test]
+    fn dragging_onto_a_square() {
+        let mut input = InputState::Idle;
+        input.press(Some(KNIGHT), Some(piece(1)), false);
+        input.hover(Some((2, 2)));
+        assert!(is_attempt(input.release(Some((2, 2))), KNIGHT, (2, 2)));
+        assert!(input == InputState::Idle);
+    }
+
+    #[test]
+    fn releasing_off_the_board() {
+        let mut input = InputState::Idle;
+        input.press(Some(KNIGHT), Some(piece(1)), false);
+        input.hover(None);
+        assert!(input.dragged().is_none());
+        assert!(input.release(None).is_none());
+        assert!(input == InputState::Idle);
+    }
+
+    #[test]
+    fn castling_onto_a_rook() {
+        // In Chess960 the king castles by moving onto its own rook, which
+        // is then a move rather than a new selection.
+        let mut input = InputState::Idle;
+        input.click(KING, Some(piece(1)), false);
+        assert!(is_attempt(input.click(ROOK, Some(piece(2)), true), KING, ROOK));
+        assert!(input == InputState::Idle);
+
+        // The same goes for pressing the mouse on the rook.
+        input.click(KING, Some(piece(1)), false);
+        assert!(is_attempt(input.press(Some(ROOK), Some(piece(2)), true), KING, ROOK));
+        assert!(input == InputState::Idle);
+    }
+
+    #[test]
+    fn cancelling_a_promotion() {
+        let mut input = InputState::Idle;
+        input.await_promotion(MoveAttempt { from: (6, 0), to: (7, 0), promotion: None });
+        assert!(input.selected() == Some((6, 0)));
+        // Nothing else can be picked up meanwhile.
+        assert!(input.click(KNIGHT, Some(piece(1)), false).is_none());
+
+        // Escape takes the move back.
+        input.cancel();
+        assert!(input == InputState::Idle);
+        assert!(input.promote(PieceType::Knight).is_none());
+    }
+
+    #[test]
+    fn promoting() {
+        let mut input = InputState::Idle;
+        input.await_promotion(MoveAttempt { from: (6, 0), to: (7, 0), promotion: None });
+        let attempt = input.promote(PieceType::Knight);
+        assert!(is_attempt(attempt, (6, 0), (7, 0)));
+        assert!(attempt.unwrap().promotion == Some(PieceType::Knight));
+        assert!(input == InputState::Idle);
+    }
+
+    #[test]
+    fn locked() {
+        let mut input = InputState::Idle;
+        input.click(KNIGHT, Some(piece(1)), false);
+        input.lock(LockReason::ComputerToMove);
+        assert!(input.selected().is_none());
+        assert!(input.click(KNIGHT, Some(piece(1)), false).is_none());
+        input.cancel();
+        assert!(input == InputState::Locked(LockReason::ComputerToMove));
+        input.unlock();
+        assert!(input == InputState::Idle);
+    }
+}
@@ -1,14 +1,76 @@
+// Bevy systems take every resource and query they use as an argument, so
+// long argument lists and query types come with them.
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+
 use bevy::prelude::*;
 use bevy_mod_picking::*;
 
+mod ai;
+mod analysis;
+mod arrows;
 mod board;
+mod book;
+mod diagram;
+mod editor;
+mod game;
+mod history;
+mod input;
+mod move_list;
+mod moves;
+mod network;
+mod notation;
+mod openings;
+mod piece_set;
 mod pieces;
+mod position;
+mod puzzle;
+mod replay;
+mod review;
+mod sound;
+mod tablebase;
+mod theme;
+mod tui;
 mod ui;
+mod variant;
+use analysis::AnalysisPlugin;
+use arrows::ArrowsPlugin;
 use board::*;
+use book::BookPlugin;
+use editor::EditorPlugin;
+use game::{AppState, GamePlugin, GameSetup, STATE_STAGE};
+use history::HistoryPlugin;
+use input::InputPlugin;
+use move_list::MoveListPlugin;
+use moves::MovesPlugin;
+use network::NetworkPlugin;
 use pieces::*;
+use position::{BoardSize, BoardState};
+use review::ReviewPlugin;
+use sound::SoundPlugin;
+use theme::ThemePlugin;
 use ui::UIPlugin;
 
+// Runs one of the command-line modes on the arguments after its name.
+type Command = fn(&[String]) -> Result<(), String>;
+
 fn main() {
+    // `bevy-chess diagram ...` draws a position to a file and
+    // `bevy-chess --tui ...` plays in the terminal, instead of opening the
+    // game's window.
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command: Option<Command> = match args.first().map(String::as_str) {
+        Some("diagram") => Some(diagram::run),
+        Some("--tui") => Some(tui::run),
+        _ => None,
+    };
+    if let Some(run) = command {
+        if let Err(err) = run(&args[1..]) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     App::build()
         // set AA to MSAA with 4 samples
         .add_resource(Msaa { samples: 4 })
@@ -22,29 +84,116 @@ fn main() {
         .add_plugins(DefaultPlugins)
         .add_plugin(PickingPlugin)
         .add_plugin(DebugPickingPlugin)
+        .add_plugin(GamePlugin)
+        .add_plugin(ThemePlugin)
         .add_plugin(BoardPlugin)
+        .add_plugin(InputPlugin)
+        .add_plugin(MovesPlugin)
         .add_plugin(PiecesPlugin)
         .add_plugin(UIPlugin)
+        .add_plugin(EditorPlugin)
+        .add_plugin(BookPlugin)
+        .add_plugin(AnalysisPlugin)
+        .add_plugin(HistoryPlugin)
+        .add_plugin(ReviewPlugin)
+        .add_plugin(ArrowsPlugin)
+        .add_plugin(MoveListPlugin)
+        .add_plugin(SoundPlugin)
+        .add_plugin(NetworkPlugin)
+        .init_resource::<BoardView>()
         .add_startup_system(setup.system())
+        .on_state_enter(STATE_STAGE, AppState::Editor, reset_view.system())
+        .on_state_enter(STATE_STAGE, AppState::Playing, reset_view.system())
+        .add_system(flip_view.system())
         .run()
 }
 
+// Marks the 3D camera looking at the board (as opposed to the UI camera).
+pub struct MainCamera;
+
+// Which side of the board the camera looks from.
+#[derive(Default)]
+pub struct BoardView {
+    pub flipped: bool,
+}
+
+// Camera transform looking at the board from white's side.
+fn white_view_transform() -> Transform {
+    Transform::from_matrix(
+        Mat4::from_rotation_translation(
+            Quat::from_xyzw(-0.3, -0.5, -0.3, 0.5).normalize(),
+            Vec3::new(-7.0, 20.0, 4.0),
+        )
+    )
+}
+
+// Camera transform looking at a board of `size` from white's side, or from
+// black's if `flipped`.
+fn view_transform(size: BoardSize, flipped: bool) -> Transform {
+    // The white view is set up for an 8x8 board; move it over to this board's
+    // centre, backing off or closing in to fit the board in.
+    let (x, y) = size.centre();
+    let center = Vec3::new(x, 0., y);
+    let scale = size.ranks.max(size.files) as f32 / 8.;
+
+    let mut camera_transform = white_view_transform();
+    let offset = camera_transform.translation - Vec3::new(3.5, 0., 3.5);
+    camera_transform.translation = center + offset * scale;
+    if flipped {
+        // Rotate half a turn about the vertical axis through the board's centre.
+        let rotation = Quat::from_rotation_y(std::f32::consts::PI);
+        camera_transform.translation = center + rotation * (camera_transform.translation - center);
+        camera_transform.rotation = rotation * camera_transform.rotation;
+    }
+    camera_transform
+}
+
 fn setup(commands: &mut Commands) {
     commands
         // Create a 3D camera
         .spawn(Camera3dBundle {
-            transform: Transform::from_matrix(
-                Mat4::from_rotation_translation(
-                    Quat::from_xyzw(-0.3, -0.5, -0.3, 0.5).normalize(),
-                    Vec3::new(-7.0, 20.0, 4.0),
-                )
-            ),
+            transform: white_view_transform(),
             ..Default::default()
         })
         .with(PickSource::default())
+        .with(MainCamera)
         // Spawn our lighting
         .spawn(LightBundle {
             transform: Transform::from_translation(Vec3::new(4.0, 8.0, 4.0)),
             ..Default::default()
         });
 }
+
+// Points the camera at the board of the game (or position editor) about to start.
+fn reset_view(
+    setup: Res<GameSetup>,
+    view: Res<BoardView>,
+    mut query: Query<&mut Transform, With<MainCamera>>,
+) {
+    for mut transform in query.iter_mut() {
+        *transform = view_transform(setup.board_size(), view.flipped);
+    }
+}
+
+// Pressing F turns the camera around to look from the other side of the board.
+fn flip_view(
+    keyboard_input: Res<Input<KeyCode>>,
+    move_text: Res<MoveTextInput>,
+    state: Res<State<AppState>>,
+    board_state: Res<BoardState>,
+    mut view: ResMut<BoardView>,
+    mut query: Query<&mut Transform, With<MainCamera>>,
+) {
+    // Letters are typed into the move box or the menu's FEN field instead.
+    if move_text.focused
+        || *state.current() == AppState::Menu
+        || !keyboard_input.just_pressed(KeyCode::F)
+    {
+        return;
+    }
+    view.flipped = !view.flipped;
+
+    for mut transform in query.iter_mut() {
+        *transform = view_transform(board_state.size, view.flipped);
+    }
+}
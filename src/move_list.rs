@@ -0,0 +1,251 @@
+use crate::{
+    game::*,
+    history::MoveHistory,
+    pieces::PieceColor,
+    ui::{despawn_screen, spawn_button, ButtonMaterials},
+};
+use bevy::{input::mouse::MouseWheel, prelude::*};
+
+// Rows of moves shown at once; the mouse wheel scrolls through the rest.
+const VISIBLE_ROWS: usize = 14;
+
+pub struct MoveListPlugin;
+impl Plugin for MoveListPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<MoveListScroll>()
+            .on_state_enter(STATE_STAGE, AppState::Playing, reset_scroll.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, scroll_move_list.system())
+            .on_state_update(STATE_STAGE, AppState::GameOver, scroll_move_list.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, move_list_update.system())
+            .on_state_update(STATE_STAGE, AppState::GameOver, move_list_update.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, move_list_buttons.system())
+            .on_state_update(STATE_STAGE, AppState::GameOver, move_list_buttons.system())
+            .on_state_exit(STATE_STAGE, AppState::GameOver, despawn_screen::<MoveList>.system());
+    }
+}
+
+// First row of the move list shown; `None` keeps up with the latest move.
+#[derive(Default)]
+struct MoveListScroll(Option<usize>);
+
+// Root node of the move list.
+struct MoveList;
+// A move in the list; clicking it shows the position it led to.
+struct MoveListEntry(usize);
+struct BackToGameButton;
+
+// A numbered row of the list, e.g. "12." with white's and black's moves,
+// as indices into the history.
+struct Row {
+    number: String,
+    white: Option<usize>,
+    black: Option<usize>,
+}
+
+fn rows(history: &MoveHistory) -> Vec<Row> {
+    let mut rows: Vec<Row> = Vec::new();
+    for idx in 0..history.moves.len() {
+        let number = history.move_number(idx + 1);
+        match history.position(idx).map(|position| position.side_to_move) {
+            Some(PieceColor::White) => rows.push(Row {
+                number,
+                white: Some(idx),
+                black: None,
+            }),
+            // Black's move finishes the row white started, unless black
+            // moved first.
+            _ => match rows.last_mut() {
+                Some(row) if row.black.is_none() => row.black = Some(idx),
+                _ => rows.push(Row {
+                    number: number.trim_end_matches("..").to_string(),
+                    white: None,
+                    black: Some(idx),
+                }),
+            },
+        }
+    }
+    rows
+}
+
+// The first row shown, as scrolled or else so the latest move is in view.
+fn first_row(scroll: &MoveListScroll, rows: usize) -> usize {
+    let last_page = rows.saturating_sub(VISIBLE_ROWS);
+    scroll.0.unwrap_or(last_page).min(last_page)
+}
+
+fn reset_scroll(mut scroll: ResMut<MoveListScroll>) {
+    *scroll = MoveListScroll::default();
+}
+
+// Scrolls the move list with the mouse wheel; scrolling back to the bottom
+// follows the game again.
+fn scroll_move_list(
+    mut wheel_reader: Local<EventReader<MouseWheel>>,
+    wheel_events: Res<Events<MouseWheel>>,
+    history: Res<MoveHistory>,
+    mut scroll: ResMut<MoveListScroll>,
+) {
+    let delta: f32 = wheel_reader.iter(&wheel_events).map(|event| event.y).sum();
+    if delta == 0. {
+        return;
+    }
+    let rows = rows(&history).len();
+    let last_page = rows.saturating_sub(VISIBLE_ROWS);
+    let first = first_row(&scroll, rows) as i32 - delta.signum() as i32;
+    let first = first.max(0).min(last_page as i32) as usize;
+    scroll.0 = if first == last_page { None } else { Some(first) };
+}
+
+// Rebuilds the move list whenever a move is made, another position is
+// picked or the list is scrolled.
+fn move_list_update(
+    commands: &mut Commands,
+    asset_server: Res<AssetServer>,
+    history: Res<MoveHistory>,
+    scroll: Res<MoveListScroll>,
+    button_materials: Res<ButtonMaterials>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+    mut shown: Local<Option<(usize, usize, Option<usize>)>>,
+    query: Query<Entity, With<MoveList>>,
+) {
+    let rows = rows(&history);
+    let first = first_row(&scroll, rows.len());
+    let spawned = query.iter().next().is_some();
+    let state = (history.moves.len(), first, history.shown);
+    if spawned != rows.is_empty() && *shown == Some(state) {
+        return;
+    }
+    for entity in query.iter() {
+        commands.despawn_recursive(entity);
+    }
+    *shown = Some(state);
+    if rows.is_empty() {
+        return;
+    }
+
+    // The move leading to the position on the board stands out.
+    let current = history.shown.unwrap_or_else(|| history.moves.len()).checked_sub(1);
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let text = |value: String, highlighted: bool| Text {
+        value,
+        font: font.clone(),
+        style: TextStyle {
+            font_size: 20.0,
+            color: if highlighted {
+                Color::rgb(0.95, 0.8, 0.3)
+            } else {
+                Color::rgb(0.85, 0.85, 0.85)
+            },
+            ..Default::default()
+        },
+    };
+    let cell = |width: f32| Style {
+        size: Size::new(Val::Px(width), Val::Px(24.)),
+        margin: Rect::all(Val::Px(1.)),
+        align_items: AlignItems::Center,
+        ..Default::default()
+    };
+    let clear = color_materials.add(Color::NONE.into());
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(10.),
+                    top: Val::Px(60.),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: Rect::all(Val::Px(5.)),
+                ..Default::default()
+            },
+            material: color_materials.add(Color::rgba(0., 0., 0., 0.5).into()),
+            ..Default::default()
+        })
+        .with(MoveList)
+        .with_children(|parent| {
+            if first > 0 {
+                parent.spawn(TextBundle {
+                    text: text("...".to_string(), false),
+                    ..Default::default()
+                });
+            }
+            for row in rows.iter().skip(first).take(VISIBLE_ROWS) {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Row,
+                            ..Default::default()
+                        },
+                        material: clear.clone(),
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        parent
+                            .spawn(NodeBundle {
+                                style: cell(40.),
+                                material: clear.clone(),
+                                ..Default::default()
+                            })
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle {
+                                    text: text(row.number.clone(), false),
+                                    ..Default::default()
+                                });
+                            });
+                        for idx in [row.white, row.black].iter() {
+                            let idx = match *idx {
+                                Some(idx) => idx,
+                                None => {
+                                    parent.spawn(NodeBundle {
+                                        style: cell(80.),
+                                        material: clear.clone(),
+                                        ..Default::default()
+                                    });
+                                    continue;
+                                }
+                            };
+                            parent
+                                .spawn(ButtonBundle {
+                                    style: cell(80.),
+                                    material: button_materials.normal.clone(),
+                                    ..Default::default()
+                                })
+                                .with(MoveListEntry(idx))
+                                .with_children(|parent| {
+                                    parent.spawn(TextBundle {
+                                        text: text(
+                                            history.moves[idx].san.clone(),
+                                            current == Some(idx),
+                                        ),
+                                        ..Default::default()
+                                    });
+                                });
+                        }
+                    });
+            }
+            if history.shown.is_some() {
+                spawn_button(parent, "Back to the game", font.clone(), &button_materials)
+                    .with(BackToGameButton);
+            }
+        });
+}
+
+// Clicking a move shows the position it led to, pieces sliding into place;
+// the board takes no moves until it's back at the game's position.
+fn move_list_buttons(
+    mut history: ResMut<MoveHistory>,
+    entry_query: Query<(&Interaction, &MoveListEntry), Mutated<Interaction>>,
+    back_query: Query<&Interaction, (Mutated<Interaction>, With<BackToGameButton>)>,
+) {
+    for (interaction, entry) in entry_query.iter() {
+        if *interaction == Interaction::Clicked {
+            let ply = entry.0 + 1;
+            history.shown = if ply == history.moves.len() { None } else { Some(ply) };
+        }
+    }
+    if back_query.iter().any(|interaction| *interaction == Interaction::Clicked) {
+        history.shown = None;
+    }
+}
@@ -0,0 +1,194 @@
+use crate::{
+    ai::Move,
+    board::PlayerTurn,
+    game::*,
+    notation::to_san_promoting,
+    pieces::*,
+    position::*,
+    variant::Variant,
+};
+use bevy::prelude::*;
+
+// Moves are asked for with `MoveRequested` and played by `apply_moves`,
+// which announces what happened with the other events here. Anything that
+// follows the game, such as the history, the clocks or the puzzle trainer,
+// listens for those instead of watching the pieces.
+pub struct MovesPlugin;
+impl Plugin for MovesPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<MoveRequested>()
+            .add_event::<MoveApplied>()
+            .add_event::<PieceCaptured>()
+            .add_event::<Check>()
+            .add_event::<GameEnded>()
+            // Played ahead of the state stage, so the game's listeners hear
+            // of a move in the same frame, before a finished game moves on.
+            .add_system(apply_moves.system());
+    }
+}
+
+// Asks for a move for the side to move: the piece on `from` to `to`, as
+// picked on the board. It's played only if the rules allow it.
+#[derive(Clone, Copy)]
+pub struct MoveRequested {
+    pub from: (u8, u8),
+    pub to: (u8, u8),
+    // What a pawn reaching the last rank becomes; a queen if not given.
+    pub promotion: Option<PieceType>,
+}
+
+// A move has been played.
+#[derive(Clone)]
+pub struct MoveApplied {
+    pub color: PieceColor,
+    // As requested: the piece's origin and the square picked for it.
+    pub mv: Move,
+    pub san: String,
+    // What the move did besides moving its piece.
+    pub effects: MoveEffects,
+    pub before: Position,
+    pub after: Position,
+}
+
+// A piece was taken off the board by the move just applied.
+#[derive(Clone, Copy)]
+pub struct PieceCaptured {
+    // As it stood before the move.
+    pub piece: Piece,
+}
+
+// The side to move is in check after the move just applied.
+#[derive(Clone, Copy)]
+pub struct Check {
+    pub color: PieceColor,
+}
+
+// The game is over, by a move or otherwise; the first result announced
+// stands.
+#[derive(Clone)]
+pub struct GameEnded {
+    pub result: GameResult,
+}
+
+// Plays the piece on `from` to `to` in `position` if the rules allow it,
+// making a pawn reaching the last rank a `promotion` (a queen if not given).
+pub fn play_move(
+    variant: &dyn Variant,
+    position: &mut Position,
+    from: (u8, u8),
+    to: (u8, u8),
+    promotion: Option<PieceType>,
+) -> Option<MoveEffects> {
+    if !variant.is_move_valid(position, from, to) {
+        return None;
+    }
+    let mut effects = variant.make_move(position, from, to);
+    // Variants play every promotion as a queen; any other piece is swapped
+    // in afterwards.
+    if let (Some(_), Some(piece_type)) = (effects.promotion, promotion) {
+        position.promote(effects.destination, piece_type);
+        effects.promotion = Some(piece_type);
+    }
+    Some(effects)
+}
+
+// Plays requested moves that are legal for the side to move, updating the
+// pieces on the board, the turn and the board state.
+fn apply_moves(
+    commands: &mut Commands,
+    state: Res<State<AppState>>,
+    setup: Res<GameSetup>,
+    mut request_reader: Local<EventReader<MoveRequested>>,
+    requests: Res<Events<MoveRequested>>,
+    mut turn: ResMut<PlayerTurn>,
+    mut board_state: ResMut<BoardState>,
+    mut applied_events: ResMut<Events<MoveApplied>>,
+    mut captured_events: ResMut<Events<PieceCaptured>>,
+    mut check_events: ResMut<Events<Check>>,
+    mut ended_events: ResMut<Events<GameEnded>>,
+    mut pieces_query: Query<(Entity, &mut Piece, &Children)>,
+) {
+    // Only one move is played a frame: the pieces only catch up with the
+    // commands at the end of it, so any other request is already stale.
+    let request = match request_reader.iter(&requests).next() {
+        Some(request) => *request,
+        None => return,
+    };
+    if *state.current() != AppState::Playing || state.next().is_some() {
+        return;
+    }
+    let variant = setup.variant.rules();
+
+    let pieces_ent_vec: Vec<(Entity, Piece, Vec<Entity>)> = pieces_query
+        .iter_mut()
+        .map(|(entity, piece, children)| {
+            (entity, *piece, children.iter().copied().collect())
+        })
+        .collect();
+    let mut position = Position {
+        pieces: pieces_ent_vec.iter().map(|(_, piece, _)| *piece).collect(),
+        side_to_move: turn.0,
+        state: board_state.clone(),
+    };
+
+    let (from, target) = (request.from, request.to);
+    let mover = pieces_ent_vec.iter().find(|(_, piece, _)| (piece.x, piece.y) == from);
+    let (entity, color) = match mover {
+        Some((entity, piece, _)) if piece.color == turn.0 => (*entity, piece.color),
+        _ => return,
+    };
+    let before = position.clone();
+    let effects = match play_move(variant, &mut position, from, target, request.promotion) {
+        Some(effects) => effects,
+        None => return,
+    };
+
+    for (other_ent, other_piece, other_children) in pieces_ent_vec {
+        if effects.captured.contains(&(other_piece.x, other_piece.y)) {
+            commands.despawn(other_ent);
+            for child in other_children {
+                commands.despawn(child);
+            }
+            captured_events.send(PieceCaptured { piece: other_piece });
+        }
+    }
+
+    for (other_ent, mut other_piece, _) in pieces_query.iter_mut() {
+        if other_ent == entity {
+            other_piece.x = effects.destination.0;
+            other_piece.y = effects.destination.1;
+            if let Some(piece_type) = effects.promotion {
+                // `rebuild_promoted` swaps in the new piece's meshes.
+                other_piece.piece_type = piece_type;
+                commands.insert_one(entity, Promoted);
+            }
+        } else if let Some((rook_from, rook_to)) = effects.castling_rook {
+            // Castling moves the rook along with the king.
+            if (other_piece.x, other_piece.y) == rook_from {
+                other_piece.x = rook_to.0;
+                other_piece.y = rook_to.1;
+            }
+        }
+    }
+
+    // Switch players at the end of a turn
+    turn.0 = position.side_to_move;
+    *board_state = position.state.clone();
+
+    let outcome = variant.outcome(&position);
+    let in_check = position.in_check(position.side_to_move);
+    applied_events.send(MoveApplied {
+        color,
+        mv: (from, target),
+        san: to_san_promoting(&before, (from, target), effects.promotion),
+        effects: effects.clone(),
+        before,
+        after: position.clone(),
+    });
+    if in_check {
+        check_events.send(Check { color: position.side_to_move });
+    }
+    if let Some(result) = outcome {
+        ended_events.send(GameEnded { result });
+    }
+}
@@ -0,0 +1,405 @@
+use crate::{
+    ai::Move,
+    game::*,
+    moves::{GameEnded, MoveApplied, MoveRequested},
+    notation::{parse_promotion, parse_uci, to_fen, to_uci},
+    pieces::*,
+    position::opponent,
+    variant::VariantKind,
+};
+use bevy::prelude::*;
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        mpsc::{self, TryRecvError},
+        Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+// A network game is two copies of the game talking over TCP, a line of text
+// per message. The host listens on `DEFAULT_PORT` and sets the game up,
+// sending it to the other player in a "start" line once the game begins;
+// from then on both sides send the moves played on their side as "move"
+// lines in UCI.
+pub const DEFAULT_PORT: u16 = 7878;
+// How long joining waits for the host to answer.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct NetworkPlugin;
+impl Plugin for NetworkPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Network>()
+            .on_state_update(STATE_STAGE, AppState::Menu, await_opponent.system())
+            .on_state_enter(STATE_STAGE, AppState::Playing, send_setup.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, send_moves.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, receive_moves.system())
+            .on_state_exit(STATE_STAGE, AppState::GameOver, hang_up.system());
+    }
+}
+
+// A message from one player to the other.
+#[derive(Clone, PartialEq)]
+enum Message {
+    // The host's game: the side the joining player has, the variant, the
+    // time control and the starting position.
+    Start {
+        side: PieceColor,
+        variant: VariantKind,
+        time_control: TimeControl,
+        fen: String,
+    },
+    Move(Move, Option<PieceType>),
+}
+
+impl Message {
+    // The message as a line of text, without the line break. The fields of
+    // "start" are split by '|', as the variant's name may have spaces in it.
+    fn encode(&self) -> String {
+        match self {
+            Message::Start { side, variant, time_control, fen } => format!(
+                "start|{}|{}|{}|{}",
+                side.name(),
+                variant.rules().name(),
+                time_control.name(),
+                fen
+            ),
+            Message::Move(mv, promotion) => format!("move {}", to_uci(*mv, *promotion)),
+        }
+    }
+
+    fn decode(line: &str) -> Option<Message> {
+        if let Some(mv) = line.strip_prefix("move ") {
+            return Some(Message::Move(parse_uci(mv)?, parse_promotion(mv)));
+        }
+        let fields: Vec<&str> = line.strip_prefix("start|")?.split('|').collect();
+        let (side, variant, time_control, fen) = match fields[..] {
+            [side, variant, time_control, fen] => (side, variant, time_control, fen),
+            _ => return None,
+        };
+        Some(Message::Start {
+            side: [PieceColor::White, PieceColor::Black]
+                .iter()
+                .copied()
+                .find(|color| color.name() == side)?,
+            variant: VariantKind::ALL
+                .iter()
+                .copied()
+                .find(|kind| kind.rules().name() == variant)?,
+            time_control: TimeControl::ALL
+                .iter()
+                .copied()
+                .find(|control| control.name() == time_control)?,
+            fen: fen.to_string(),
+        })
+    }
+}
+
+// What the thread reading from the other player passes on.
+enum Received {
+    // Joining has reached the host; this is the stream to write to it.
+    Connected(TcpStream),
+    Message(Message),
+    // The connection couldn't be made, or has gone; why.
+    Lost(String),
+}
+
+enum Link {
+    Closed,
+    // Hosting, until the other player connects.
+    Listening(TcpListener),
+    // Talking to the other player. While joining, `stream` is only there
+    // once the host has answered.
+    Connected {
+        stream: Option<TcpStream>,
+        received: Mutex<mpsc::Receiver<Received>>,
+    },
+}
+
+// The connection to the other player of a network game.
+pub struct Network {
+    link: Link,
+    // How setting the connection up is going, or why it failed, for the
+    // main menu.
+    pub status: Option<String>,
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Self {
+            link: Link::Closed,
+            status: None,
+        }
+    }
+}
+
+impl Network {
+    // Waits for another player to join the game set up in the menu.
+    pub fn host(&mut self) {
+        self.close();
+        let listener = TcpListener::bind(("0.0.0.0", DEFAULT_PORT)).and_then(|listener| {
+            // Polled each frame by `await_opponent` instead of blocking.
+            listener.set_nonblocking(true)?;
+            Ok(listener)
+        });
+        match listener {
+            Ok(listener) => {
+                self.link = Link::Listening(listener);
+                self.status = Some(format!("Waiting for an opponent on port {}...", DEFAULT_PORT));
+            }
+            Err(err) => self.status = Some(format!("Can't host on port {}: {}", DEFAULT_PORT, err)),
+        }
+    }
+
+    // Joins the game hosted at `address`, on `DEFAULT_PORT` unless it names
+    // a port. Connecting is left to another thread so the window keeps
+    // responding meanwhile.
+    pub fn join(&mut self, address: &str) {
+        self.close();
+        let address = match address.trim() {
+            address if address.contains(':') => address.to_string(),
+            host => format!("{}:{}", host, DEFAULT_PORT),
+        };
+        self.status = Some(format!("Connecting to {}...", address));
+
+        let (sender, received) = mpsc::channel();
+        thread::spawn(move || {
+            let stream = connect(&address).and_then(|stream| Ok((stream.try_clone()?, stream)));
+            match stream {
+                Ok((writer, reader)) => {
+                    if sender.send(Received::Connected(writer)).is_ok() {
+                        read_messages(reader, sender);
+                    }
+                }
+                Err(err) => {
+                    let _ = sender.send(Received::Lost(format!("Can't join {}: {}", address, err)));
+                }
+            }
+        });
+        self.link = Link::Connected { stream: None, received: Mutex::new(received) };
+    }
+
+    // Hangs up on the other player, or stops waiting for one.
+    pub fn close(&mut self) {
+        if let Link::Connected { stream: Some(stream), .. } = &self.link {
+            // Also ends the thread reading from it.
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        self.link = Link::Closed;
+        self.status = None;
+    }
+
+    // Takes the other player's connection to a hosted game, if they've
+    // connected. Returns whether they have.
+    fn accept(&mut self) -> bool {
+        let accepted = match &self.link {
+            Link::Listening(listener) => listener.accept(),
+            _ => return false,
+        };
+        let link = accepted.and_then(|(stream, _)| {
+            stream.set_nonblocking(false)?;
+            let reader = stream.try_clone()?;
+            let (sender, received) = mpsc::channel();
+            thread::spawn(move || read_messages(reader, sender));
+            Ok(Link::Connected { stream: Some(stream), received: Mutex::new(received) })
+        });
+        match link {
+            Ok(link) => {
+                self.link = link;
+                self.status = None;
+                true
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => false,
+            Err(err) => {
+                self.close();
+                self.status = Some(format!("Can't host: {}", err));
+                false
+            }
+        }
+    }
+
+    // Sends `message` to the other player, if there is one. A failed write
+    // isn't reported here: the reading thread finds the connection gone.
+    fn send(&mut self, message: &Message) {
+        if let Link::Connected { stream: Some(stream), .. } = &mut self.link {
+            let _ = writeln!(stream, "{}", message.encode());
+        }
+    }
+
+    // The next message from the other player, if one has come in, or why
+    // the connection was lost, which closes it.
+    fn next_message(&mut self) -> Option<Result<Message, String>> {
+        let (stream, received) = match &mut self.link {
+            Link::Connected { stream, received } => (stream, received),
+            _ => return None,
+        };
+        let received = received.get_mut().unwrap();
+        let lost = loop {
+            match received.try_recv() {
+                Ok(Received::Connected(connected)) => *stream = Some(connected),
+                Ok(Received::Message(message)) => return Some(Ok(message)),
+                Ok(Received::Lost(reason)) => break reason,
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => break "Connection lost".to_string(),
+            }
+        };
+        self.close();
+        Some(Err(lost))
+    }
+}
+
+fn connect(address: &str) -> io::Result<TcpStream> {
+    let address = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "unknown host"))?;
+    TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)
+}
+
+// Passes on the other player's messages until the connection goes. Lines
+// that aren't messages are passed over.
+fn read_messages(stream: TcpStream, sender: mpsc::Sender<Received>) {
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                let _ = sender.send(Received::Lost(format!("Connection lost: {}", err)));
+                return;
+            }
+        };
+        if let Some(message) = Message::decode(&line) {
+            if sender.send(Received::Message(message)).is_err() {
+                return;
+            }
+        }
+    }
+    let _ = sender.send(Received::Lost("The opponent left".to_string()));
+}
+
+// Starts a network game from the menu once the other player is there: when
+// they connect to the game hosted here, or when the host sends the game
+// joined.
+fn await_opponent(
+    mut network: ResMut<Network>,
+    mut setup: ResMut<GameSetup>,
+    mut state: ResMut<State<AppState>>,
+) {
+    match setup.mode {
+        GameMode::Host => {
+            if network.accept() {
+                state.set_next(AppState::Playing).unwrap();
+            }
+        }
+        GameMode::Join => match network.next_message() {
+            Some(Ok(Message::Start { side, variant, time_control, fen })) => {
+                setup.human_side = side;
+                setup.variant = variant;
+                setup.time_control = time_control;
+                setup.start_position = StartPosition::Fen(fen);
+                network.status = None;
+                state.set_next(AppState::Playing).unwrap();
+            }
+            // No moves come before the game starts.
+            Some(Ok(Message::Move(..))) | None => {}
+            Some(Err(reason)) => network.status = Some(reason),
+        },
+        // The mode was changed while waiting for the other player.
+        _ => network.close(),
+    }
+}
+
+// Sends the hosted game to the other player as it starts, so both boards
+// start from the same position, a random Chess960 one included.
+fn send_setup(setup: Res<GameSetup>, mut network: ResMut<Network>) {
+    if setup.mode != GameMode::Host {
+        return;
+    }
+    network.send(&Message::Start {
+        side: opponent(setup.human_side),
+        variant: setup.variant,
+        time_control: setup.time_control,
+        fen: to_fen(&setup.start(), true),
+    });
+}
+
+// Sends the moves played on this side to the other player.
+fn send_moves(
+    setup: Res<GameSetup>,
+    mut applied_reader: Local<EventReader<MoveApplied>>,
+    applied_events: Res<Events<MoveApplied>>,
+    mut network: ResMut<Network>,
+) {
+    for applied in applied_reader.iter(&applied_events) {
+        if setup.mode.is_network() && setup.player(applied.color) == PlayerType::Human {
+            network.send(&Message::Move(applied.mv, applied.effects.promotion));
+        }
+    }
+}
+
+// Plays the other player's moves, and ends the game in this side's favour
+// if they leave it. Only one move is asked for a frame, as that's all
+// `apply_moves` plays; the rest wait their turn.
+fn receive_moves(
+    setup: Res<GameSetup>,
+    mut network: ResMut<Network>,
+    mut requests: ResMut<Events<MoveRequested>>,
+    mut ended_events: ResMut<Events<GameEnded>>,
+) {
+    if !setup.mode.is_network() {
+        return;
+    }
+    match network.next_message() {
+        Some(Ok(Message::Move((from, to), promotion))) => {
+            requests.send(MoveRequested { from, to, promotion });
+        }
+        Some(Ok(Message::Start { .. })) | None => {}
+        Some(Err(_)) => ended_events.send(GameEnded {
+            result: GameResult {
+                winner: Some(setup.human_side),
+                reason: "abandonment".to_string(),
+            },
+        }),
+    }
+}
+
+// Leaving a finished game hangs up on the other player.
+fn hang_up(mut network: ResMut<Network>) {
+    network.close();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notation::{parse_square, STANDARD_FEN};
+
+    fn round_trip(message: Message) {
+        assert!(Message::decode(&message.encode()) == Some(message));
+    }
+
+    #[test]
+    fn messages() {
+        let e2 = parse_square("e2").unwrap();
+        let e4 = parse_square("e4").unwrap();
+        assert_eq!(Message::Move((e2, e4), None).encode(), "move e2e4");
+        round_trip(Message::Move((e2, e4), None));
+        round_trip(Message::Move((e2, e4), Some(PieceType::Knight)));
+        round_trip(Message::Start {
+            side: PieceColor::Black,
+            variant: VariantKind::KingOfTheHill,
+            time_control: TimeControl(Some((3, 2))),
+            fen: STANDARD_FEN.to_string(),
+        });
+        round_trip(Message::Start {
+            side: PieceColor::White,
+            variant: VariantKind::Standard,
+            time_control: TimeControl(None),
+            fen: STANDARD_FEN.to_string(),
+        });
+
+        assert!(Message::decode("move e2").is_none());
+        assert!(Message::decode("start|Black|Standard|Untimed").is_none());
+        assert!(Message::decode("resign").is_none());
+    }
+}
@@ -0,0 +1,580 @@
+use crate::{pieces::*, position::*};
+
+// Name of the square at board position (x, y), e.g. (3, 4) => "e4".
+// `x` is the rank (0 is white's back rank) and `y` is the file (0 is the a-file).
+pub fn square_name(pos: (u8, u8)) -> String {
+    format!("{}{}", (b'a' + pos.1) as char, pos.0 + 1)
+}
+
+// Board position of a square name, e.g. "e4" => (3, 4). Larger boards have
+// more files ("j4") and two-digit ranks ("e10").
+pub fn parse_square(name: &str) -> Option<(u8, u8)> {
+    let mut chars = name.chars();
+    let file = chars.next()?;
+    let rank = chars.as_str();
+    if rank.is_empty() || !rank.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let rank: u8 = rank.parse().ok()?;
+
+    let last_file = (b'a' + BoardSize::MAX - 1) as char;
+    if !('a'..=last_file).contains(&file) || !(1..=BoardSize::MAX).contains(&rank) {
+        return None;
+    }
+
+    Some((rank - 1, file as u8 - b'a'))
+}
+
+// Splits a square name off the end of `text`, e.g. "Nbd2" => ("Nbd", (1, 3)).
+fn split_square(text: &str) -> Option<(&str, (u8, u8))> {
+    let digits = text.len() - text.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    if digits == 0 || digits >= text.len() {
+        return None;
+    }
+    let start = text.len() - digits - 1;
+    Some((&text[..start], parse_square(&text[start..])?))
+}
+
+// Parses a move typed in either UCI ("e2e4") or SAN ("Nf3") notation, returning
+// the origin and destination squares. Legality is left to `Position::is_move_valid`.
+pub fn parse_move(text: &str, position: &Position) -> Option<((u8, u8), (u8, u8))> {
+    parse_uci(text).or_else(|| parse_san(text, position))
+}
+
+// Parses a UCI move ("e2e4", "e7e8q"); a trailing promotion letter is accepted
+// and read by `parse_promotion`. Castling is written as the
+// king's move, either to its castled square ("e1g1") or onto the rook as in
+// Chess960 ("e1h1").
+pub fn parse_uci(text: &str) -> Option<((u8, u8), (u8, u8))> {
+    let text = text.trim();
+    if !text.is_ascii() {
+        return None;
+    }
+    let text = match text.chars().last() {
+        Some(c) if c.is_ascii_alphabetic() => &text[..text.len() - 1],
+        _ => text,
+    };
+
+    let (rest, to) = split_square(text)?;
+    let (rest, from) = split_square(rest)?;
+    if !rest.is_empty() {
+        return None;
+    }
+
+    Some((from, to))
+}
+
+// Writes a move in UCI ("e2e4"), with the letter of the piece a pawn
+// promotes to if given ("e7e8n").
+pub fn to_uci(mv: ((u8, u8), (u8, u8)), promotion: Option<PieceType>) -> String {
+    let (from, to) = mv;
+    let mut text = format!("{}{}", square_name(from), square_name(to));
+    if let Some(piece_type) = promotion {
+        text.push(piece_char(&Piece { color: PieceColor::Black, piece_type, x: to.0, y: to.1 }));
+    }
+    text
+}
+
+// Parses a SAN move ("e4", "Nbd2", "exd5", "R1xa3+", "O-O") for the side to
+// move, returning the only piece of that side which can make it.
+pub fn parse_san(text: &str, position: &Position) -> Option<((u8, u8), (u8, u8))> {
+    let color = position.side_to_move;
+    // Check/mate markers and annotations don't change the move.
+    let text = text.trim().trim_end_matches(|c| "+#!?".contains(c));
+
+    // Castling is written as the king moving onto its own rook, which is
+    // unambiguous in Chess960 too.
+    let castle_side = match text {
+        "O-O" | "0-0" => Some(CastleSide::Kingside),
+        "O-O-O" | "0-0-0" => Some(CastleSide::Queenside),
+        _ => None,
+    };
+    if let Some(side) = castle_side {
+        let king = position.king(color)?;
+        let rook_file = position.state.castling.get(color).rook_file(side)?;
+        return Some(((king.x, king.y), (king.x, rook_file)));
+    }
+
+    // Promotion suffix (e.g. "e8=Q"), read by `parse_promotion`.
+    let text = match text.find('=') {
+        Some(idx) => &text[..idx],
+        None => text,
+    };
+    if !text.is_ascii() || text.len() < 2 {
+        return None;
+    }
+
+    let (piece_type, rest) = match text.as_bytes()[0] {
+        b'K' => (PieceType::King, &text[1..]),
+        b'Q' => (PieceType::Queen, &text[1..]),
+        b'R' => (PieceType::Rook, &text[1..]),
+        b'B' => (PieceType::Bishop, &text[1..]),
+        b'N' => (PieceType::Knight, &text[1..]),
+        b'A' => (PieceType::Archbishop, &text[1..]),
+        b'C' => (PieceType::Chancellor, &text[1..]),
+        b'M' => (PieceType::Amazon, &text[1..]),
+        b'L' => (PieceType::Camel, &text[1..]),
+        _ => (PieceType::Pawn, text),
+    };
+    let (disambiguation, to) = split_square(rest)?;
+
+    // Whatever sits between the piece letter and the destination narrows down
+    // which piece moves: a file, a rank, or both, optionally followed by 'x'.
+    let mut from_file = None;
+    let mut from_rank: Option<u8> = None;
+    for c in disambiguation.bytes() {
+        match c {
+            b'x' => {}
+            b'0'..=b'9' => from_rank = Some(from_rank.unwrap_or(0) * 10 + (c - b'0')),
+            c if c.is_ascii_lowercase() && c - b'a' < BoardSize::MAX => from_file = Some(c - b'a'),
+            _ => return None,
+        }
+    }
+
+    let mut candidates = position.pieces.iter().filter(|piece| {
+        piece.color == color
+            && piece.piece_type == piece_type
+            && from_rank.is_none_or(|rank| piece.x + 1 == rank)
+            && from_file.is_none_or(|file| piece.y == file)
+            && position.is_move_valid((piece.x, piece.y), to)
+    });
+
+    match (candidates.next(), candidates.next()) {
+        (Some(piece), None) => Some(((piece.x, piece.y), to)),
+        // Either no piece can make the move, or it's ambiguous.
+        _ => None,
+    }
+}
+
+// The piece a typed move promotes to, if it names one: "e8=N" in SAN or
+// "e7e8n" in UCI.
+pub fn parse_promotion(text: &str) -> Option<PieceType> {
+    let text = text.trim().trim_end_matches(|c| "+#!?".contains(c));
+    let letter = match text.find('=') {
+        Some(idx) => text[idx + 1..].chars().next()?,
+        None if parse_uci(text).is_some() => {
+            text.chars().last().filter(|c| c.is_ascii_alphabetic())?
+        }
+        None => return None,
+    };
+    piece_type_from_char(letter)
+        .filter(|piece_type| !matches!(piece_type, PieceType::King | PieceType::Pawn))
+}
+
+// Writes a move in SAN ("e4", "Nbd2", "exd5", "O-O", "e8=Q+") for the side
+// to move, assuming it's valid.
+pub fn to_san(position: &Position, mv: ((u8, u8), (u8, u8))) -> String {
+    to_san_promoting(position, mv, None)
+}
+
+// Like `to_san`, with a pawn reaching the last rank becoming `promotion`
+// rather than a queen.
+pub fn to_san_promoting(
+    position: &Position,
+    (from, to): ((u8, u8), (u8, u8)),
+    promotion: Option<PieceType>,
+) -> String {
+    let piece = match position.piece_at(from) {
+        Some(piece) => *piece,
+        None => return String::new(),
+    };
+    let piece_letter = |piece_type| piece_char(&Piece { piece_type, ..piece }).to_ascii_uppercase();
+
+    let mut san = match position.castling_side(from, to) {
+        Some(CastleSide::Kingside) => "O-O".to_string(),
+        Some(CastleSide::Queenside) => "O-O-O".to_string(),
+        None => {
+            let mut san = String::new();
+            let capture = position.piece_at(to).is_some()
+                || (piece.piece_type == PieceType::Pawn && from.1 != to.1);
+            if piece.piece_type == PieceType::Pawn {
+                if capture {
+                    san.push((b'a' + from.1) as char);
+                }
+            } else {
+                san.push(piece_letter(piece.piece_type));
+                // Name the file, the rank or else the whole square of the
+                // moving piece when another like it could move there too.
+                let rivals: Vec<&Piece> = position
+                    .pieces
+                    .iter()
+                    .filter(|other| {
+                        other.color == piece.color
+                            && other.piece_type == piece.piece_type
+                            && (other.x, other.y) != from
+                            && position.is_move_valid((other.x, other.y), to)
+                    })
+                    .collect();
+                if !rivals.is_empty() {
+                    if rivals.iter().all(|other| other.y != from.1) {
+                        san.push((b'a' + from.1) as char);
+                    } else if rivals.iter().all(|other| other.x != from.0) {
+                        san.push_str(&(from.0 + 1).to_string());
+                    } else {
+                        san.push_str(&square_name(from));
+                    }
+                }
+            }
+            if capture {
+                san.push('x');
+            }
+            san.push_str(&square_name(to));
+            san
+        }
+    };
+
+    let mut next = position.clone();
+    let effects = next.make_move(from, to);
+    if let Some(piece_type) = effects.promotion.map(|queen| promotion.unwrap_or(queen)) {
+        next.promote(effects.destination, piece_type);
+        san.push('=');
+        san.push(piece_letter(piece_type));
+    }
+    if next.in_check(next.side_to_move) {
+        san.push('+');
+    }
+    san
+}
+
+// The standard starting position.
+pub const STANDARD_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+// Parses a FEN string. Castling rights may be given as in FEN ("KQkq"), X-FEN
+// (a rook's file letter where "K"/"Q" would be ambiguous) or Shredder-FEN
+// (always file letters, "HAha"), so Chess960 positions can be read too.
+pub fn parse_fen(fen: &str) -> Option<Position> {
+    let mut fields = fen.split_whitespace();
+    let placement = fields.next()?;
+    let side_to_move = match fields.next().unwrap_or("w") {
+        "w" => PieceColor::White,
+        "b" => PieceColor::Black,
+        _ => return None,
+    };
+
+    // The board is as big as the placement says: as many ranks as there are
+    // fields, as many files as each of them adds up to.
+    let ranks: Vec<&str> = placement.split('/').collect();
+    let sizes = 3..=BoardSize::MAX as u32;
+    if !sizes.contains(&(ranks.len() as u32)) {
+        return None;
+    }
+
+    let mut pieces = Vec::new();
+    let mut files = None;
+    // FEN lists ranks from the last down to the 1st.
+    for (i, rank) in ranks.iter().enumerate() {
+        let x = (ranks.len() - 1 - i) as u8;
+        let mut y: u32 = 0;
+        // Runs of empty squares may take two digits on wide boards ("10").
+        let mut empty = 0;
+        for c in rank.chars() {
+            if let Some(digit) = c.to_digit(10) {
+                empty = empty * 10 + digit;
+                continue;
+            }
+            y += empty;
+            empty = 0;
+            if y >= BoardSize::MAX as u32 {
+                return None;
+            }
+
+            let piece_type = piece_type_from_char(c)?;
+            let color = if c.is_ascii_uppercase() { PieceColor::White } else { PieceColor::Black };
+            pieces.push(Piece { color, piece_type, x, y: y as u8 });
+            y += 1;
+        }
+        y += empty;
+        if !sizes.contains(&y) || files.is_some_and(|files| files != y) {
+            return None;
+        }
+        files = Some(y);
+    }
+
+    let mut position = Position {
+        pieces,
+        side_to_move,
+        state: BoardState {
+            size: BoardSize { ranks: ranks.len() as u8, files: files? as u8 },
+            castling: CastlingRights::default(),
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            checks_given: (0, 0),
+        },
+    };
+
+    let castling = fields.next().unwrap_or("-");
+    if castling != "-" {
+        for c in castling.chars() {
+            let color = if c.is_ascii_uppercase() { PieceColor::White } else { PieceColor::Black };
+            let king = *position.king(color)?;
+            let rank = position.state.size.back_rank(color);
+            if king.x != rank {
+                return None;
+            }
+
+            let rook_file = match c.to_ascii_lowercase() {
+                'k' => position.outermost_rook(color, CastleSide::Kingside)?,
+                'q' => position.outermost_rook(color, CastleSide::Queenside)?,
+                file @ 'a'..='p' => {
+                    let file = file as u8 - b'a';
+                    match position.piece_at((rank, file)) {
+                        Some(rook) if rook.color == color && rook.piece_type == PieceType::Rook => {}
+                        _ => return None,
+                    }
+                    file
+                }
+                _ => return None,
+            };
+
+            let rights = position.state.castling.get_mut(color);
+            if rook_file > king.y {
+                rights.kingside = Some(rook_file);
+            } else {
+                rights.queenside = Some(rook_file);
+            }
+        }
+    }
+
+    position.state.en_passant = match fields.next().unwrap_or("-") {
+        "-" => None,
+        square => Some(parse_square(square)?),
+    };
+    if let Some(halfmove_clock) = fields.next() {
+        position.state.halfmove_clock = halfmove_clock.parse().ok()?;
+    }
+    if let Some(fullmove_number) = fields.next() {
+        position.state.fullmove_number = fullmove_number.parse().ok()?;
+    }
+
+    Some(position)
+}
+
+// Writes a position as FEN. Castling rights use X-FEN, which is plain FEN for
+// classical positions, unless `shredder` asks for Shredder-FEN file letters.
+pub fn to_fen(position: &Position, shredder: bool) -> String {
+    let size = position.state.size;
+    let mut placement = String::new();
+    for x in (0..size.ranks).rev() {
+        let mut empty = 0;
+        for y in 0..size.files {
+            match position.piece_at((x, y)) {
+                Some(piece) => {
+                    if empty > 0 {
+                        placement.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    placement.push(piece_char(piece));
+                }
+                None => empty += 1,
+            }
+        }
+        if empty > 0 {
+            placement.push_str(&empty.to_string());
+        }
+        if x > 0 {
+            placement.push('/');
+        }
+    }
+
+    let mut castling = String::new();
+    for color in &[PieceColor::White, PieceColor::Black] {
+        let rights = position.state.castling.get(*color);
+        let rank = size.back_rank(*color);
+        // Rooks past the castling rook on the same side make K/Q ambiguous.
+        let outermost = |file: u8, kingside: bool| {
+            !position.pieces.iter().any(|piece| {
+                piece.color == *color
+                    && piece.piece_type == PieceType::Rook
+                    && piece.x == rank
+                    && if kingside { piece.y > file } else { piece.y < file }
+            })
+        };
+
+        for (file, kingside, letter) in &[(rights.kingside, true, 'k'), (rights.queenside, false, 'q')] {
+            if let Some(file) = file {
+                let c = if shredder || !outermost(*file, *kingside) {
+                    (b'a' + file) as char
+                } else {
+                    *letter
+                };
+                castling.push(match color {
+                    PieceColor::White => c.to_ascii_uppercase(),
+                    PieceColor::Black => c,
+                });
+            }
+        }
+    }
+    if castling.is_empty() {
+        castling.push('-');
+    }
+
+    format!(
+        "{} {} {} {} {} {}",
+        placement,
+        match position.side_to_move {
+            PieceColor::White => "w",
+            PieceColor::Black => "b",
+        },
+        castling,
+        position.state.en_passant.map_or("-".to_string(), square_name),
+        position.state.halfmove_clock,
+        position.state.fullmove_number,
+    )
+}
+
+// Piece letters are the usual ones, plus A (archbishop), C (chancellor),
+// M (amazon) and L (camel) for fairy pieces.
+fn piece_type_from_char(c: char) -> Option<PieceType> {
+    match c.to_ascii_lowercase() {
+        'k' => Some(PieceType::King),
+        'q' => Some(PieceType::Queen),
+        'r' => Some(PieceType::Rook),
+        'b' => Some(PieceType::Bishop),
+        'n' => Some(PieceType::Knight),
+        'p' => Some(PieceType::Pawn),
+        'a' => Some(PieceType::Archbishop),
+        'c' => Some(PieceType::Chancellor),
+        'm' => Some(PieceType::Amazon),
+        'l' => Some(PieceType::Camel),
+        _ => None,
+    }
+}
+
+// FEN letter for a piece: uppercase for white, lowercase for black.
+pub fn piece_char(piece: &Piece) -> char {
+    let c = match piece.piece_type {
+        PieceType::King => 'k',
+        PieceType::Queen => 'q',
+        PieceType::Rook => 'r',
+        PieceType::Bishop => 'b',
+        PieceType::Knight => 'n',
+        PieceType::Pawn => 'p',
+        PieceType::Archbishop => 'a',
+        PieceType::Chancellor => 'c',
+        PieceType::Amazon => 'm',
+        PieceType::Camel => 'l',
+    };
+    match piece.color {
+        PieceColor::White => c.to_ascii_uppercase(),
+        PieceColor::Black => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(name: &str) -> (u8, u8) {
+        parse_square(name).unwrap()
+    }
+
+    fn mv(from: &str, to: &str) -> ((u8, u8), (u8, u8)) {
+        (square(from), square(to))
+    }
+
+    #[test]
+    fn uci_moves() {
+        assert_eq!(parse_uci("e2e4"), Some(mv("e2", "e4")));
+        assert_eq!(parse_uci("e7e8q"), Some(mv("e7", "e8")));
+        assert!(parse_promotion("e7e8n") == Some(PieceType::Knight));
+        assert_eq!(to_uci(mv("e2", "e4"), None), "e2e4");
+        assert_eq!(to_uci(mv("e7", "e8"), Some(PieceType::Knight)), "e7e8n");
+        assert_eq!(parse_uci("e2"), None);
+        assert_eq!(parse_uci("e2e4e6"), None);
+    }
+
+    #[test]
+    fn san_moves() {
+        let position = parse_fen(STANDARD_FEN).unwrap();
+        assert_eq!(parse_san("e4", &position), Some(mv("e2", "e4")));
+        assert_eq!(parse_san("Nf3+", &position), Some(mv("g1", "f3")));
+        assert_eq!(parse_san("Ke2", &position), None);
+        assert_eq!(to_san(&position, mv("e2", "e4")), "e4");
+        assert_eq!(to_san(&position, mv("g1", "f3")), "Nf3");
+
+        let position = parse_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(parse_san("a8=N", &position), Some(mv("a7", "a8")));
+        assert!(parse_promotion("a8=N") == Some(PieceType::Knight));
+        assert_eq!(to_san_promoting(&position, mv("a7", "a8"), Some(PieceType::Knight)), "a8=N");
+    }
+
+    #[test]
+    fn san_disambiguation() {
+        // Knights on b1 and f3 can both reach d2.
+        let position = parse_fen("4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1").unwrap();
+        assert_eq!(parse_san("Nbd2", &position), Some(mv("b1", "d2")));
+        assert_eq!(parse_san("Nfd2", &position), Some(mv("f3", "d2")));
+        assert_eq!(parse_san("Nd2", &position), None);
+        assert_eq!(to_san(&position, mv("b1", "d2")), "Nbd2");
+
+        // Rooks on e1 and e3 can both reach e2.
+        let position = parse_fen("k7/8/8/8/8/4R3/8/4R2K w - - 0 1").unwrap();
+        assert_eq!(parse_san("R1e2", &position), Some(mv("e1", "e2")));
+        assert_eq!(parse_san("R3e2", &position), Some(mv("e3", "e2")));
+        assert_eq!(parse_san("Re2", &position), None);
+        assert_eq!(to_san(&position, mv("e1", "e2")), "R1e2");
+    }
+
+    #[test]
+    fn castling_notation() {
+        let position = parse_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        // SAN castling is read as the king moving onto its rook.
+        assert_eq!(parse_san("O-O", &position), Some(mv("e1", "h1")));
+        assert_eq!(parse_san("0-0-0", &position), Some(mv("e1", "a1")));
+
+        // The king's castled square and its rook's square are the same move.
+        for (to, san) in &[("g1", "O-O"), ("h1", "O-O"), ("c1", "O-O-O"), ("a1", "O-O-O")] {
+            let castle = parse_uci(&format!("e1{}", to)).unwrap();
+            assert_eq!(to_san(&position, castle), *san);
+            let effects = position.clone().make_move(castle.0, castle.1);
+            let (king_to, rook) = match *san {
+                "O-O" => ("g1", mv("h1", "f1")),
+                _ => ("c1", mv("a1", "d1")),
+            };
+            assert_eq!(effects.destination, square(king_to));
+            assert_eq!(effects.castling_rook, Some(rook));
+        }
+    }
+
+    #[test]
+    fn fen_round_trips() {
+        let fens = [
+            STANDARD_FEN,
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "8/8/4k3/8/8/2K5/8/8 b - - 12 60",
+            // Rooks past the castling rooks need X-FEN's file letters.
+            "1k1r2r1/8/8/8/8/8/8/1K1R2R1 w Dd - 0 1",
+            "r1r1k2r/8/8/8/8/8/8/R1R1K2R w KCkc - 0 1",
+        ];
+        for fen in fens.iter() {
+            let position = parse_fen(fen).unwrap();
+            assert_eq!(to_fen(&position, false), *fen);
+            let shredder = to_fen(&position, true);
+            assert_eq!(to_fen(&parse_fen(&shredder).unwrap(), false), *fen);
+        }
+    }
+
+    #[test]
+    fn shredder_fen() {
+        let position = parse_fen(STANDARD_FEN).unwrap();
+        assert_eq!(
+            to_fen(&position, true),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1"
+        );
+        let shredder = parse_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1").unwrap();
+        assert_eq!(to_fen(&shredder, false), STANDARD_FEN);
+    }
+
+    #[test]
+    fn chess960_fen_round_trips() {
+        for index in 0..960 {
+            let position = chess960_position(index);
+            for shredder in &[false, true] {
+                let fen = to_fen(&position, *shredder);
+                assert_eq!(to_fen(&parse_fen(&fen).unwrap(), *shredder), fen);
+            }
+        }
+    }
+}
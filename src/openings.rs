@@ -0,0 +1,82 @@
+use crate::{
+    notation::{parse_fen, parse_san, to_fen, STANDARD_FEN},
+    position::Position,
+};
+use std::{collections::HashMap, fs};
+
+// Opening names in the tab-separated format of Lichess's chess-openings
+// tables (eco, name, pgn), so any of those files can be dropped in.
+const ECO_FILE: &str = "assets/openings/eco.tsv";
+
+pub struct Opening {
+    pub eco: String,
+    pub name: String,
+}
+
+impl Opening {
+    // e.g. "C50 Italian Game".
+    pub fn describe(&self) -> String {
+        format!("{} {}", self.eco, self.name)
+    }
+}
+
+// Named openings by the position their line leads to, so a transposition
+// into a known opening is recognised whatever the move order.
+pub struct EcoTable {
+    openings: HashMap<String, Opening>,
+}
+
+impl Default for EcoTable {
+    fn default() -> Self {
+        let mut openings = HashMap::new();
+        let contents = fs::read_to_string(ECO_FILE).unwrap_or_default();
+
+        for line in contents.lines().skip(1) {
+            let mut fields = line.split('\t');
+            let (eco, name, pgn) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(eco), Some(name), Some(pgn)) => (eco, name, pgn),
+                _ => continue,
+            };
+            // Where two lines reach the same position, the first listed
+            // names it. A line that can't be played out names nothing.
+            if let Some(position) = play_line(pgn) {
+                openings.entry(position_key(&position)).or_insert(Opening {
+                    eco: eco.to_string(),
+                    name: name.to_string(),
+                });
+            }
+        }
+
+        Self { openings }
+    }
+}
+
+impl EcoTable {
+    pub fn opening(&self, position: &Position) -> Option<&Opening> {
+        self.openings.get(&position_key(position))
+    }
+}
+
+// Plays a line of SAN moves ("1. e4 e5 2. Nf3") from the starting position.
+fn play_line(pgn: &str) -> Option<Position> {
+    let mut position = parse_fen(STANDARD_FEN)?;
+    for token in pgn.split_whitespace() {
+        // Move numbers ("1.", "1...") aren't moves.
+        if token.trim_end_matches('.').parse::<u32>().is_ok() {
+            continue;
+        }
+        let (from, to) = parse_san(token, &position)?;
+        position.make_move(from, to);
+    }
+    Some(position)
+}
+
+// The placement, side to move and castling rights from the FEN; the move
+// counters (and en passant square) would keep transpositions apart.
+fn position_key(position: &Position) -> String {
+    to_fen(position, false)
+        .split_whitespace()
+        .take(3)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,156 @@
+use crate::pieces::PieceType;
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::{collections::HashMap, fs};
+
+// Directory holding one RON descriptor per piece set.
+const PIECE_SETS_DIR: &str = "assets/piece_sets";
+
+// How to build one piece from a glTF file: which sub-meshes make it up, and
+// the transform that places them on a square (removing any offset baked into
+// the model and scaling it to fit).
+#[derive(Clone, Deserialize)]
+pub struct PieceModel {
+    // glTF file, relative to the assets directory.
+    pub path: String,
+    // Sub-mesh labels within the file, e.g. "Mesh0/Primitive0".
+    pub sub_meshes: Vec<String>,
+    #[serde(default)]
+    pub offset: (f32, f32, f32),
+    #[serde(default = "unit_scale")]
+    pub scale: (f32, f32, f32),
+}
+
+fn unit_scale() -> (f32, f32, f32) {
+    (1., 1., 1.)
+}
+
+impl PieceModel {
+    pub fn transform(&self) -> Transform {
+        let mut transform =
+            Transform::from_translation(Vec3::new(self.offset.0, self.offset.1, self.offset.2));
+        transform.apply_non_uniform_scale(Vec3::new(self.scale.0, self.scale.1, self.scale.2));
+        transform
+    }
+
+    pub fn meshes(&self, asset_server: &AssetServer) -> Vec<Handle<Mesh>> {
+        self.sub_meshes
+            .iter()
+            .map(|sub_mesh| asset_server.load(format!("{}#{}", self.path, sub_mesh).as_str()))
+            .collect()
+    }
+}
+
+// A piece set descriptor, mapping each piece type to its model.
+#[derive(Clone, Deserialize)]
+pub struct PieceSet {
+    pub name: String,
+    pub king: PieceModel,
+    pub queen: PieceModel,
+    pub bishop: PieceModel,
+    pub knight: PieceModel,
+    pub rook: PieceModel,
+    pub pawn: PieceModel,
+    // Models for fairy pieces, by name (e.g. "Archbishop"). Those without
+    // one are drawn as their stand-in standard piece, tinted and marked.
+    #[serde(default)]
+    pub fairy: HashMap<String, PieceModel>,
+}
+
+impl PieceSet {
+    // The model for a piece type, and whether it's its stand-in's. Every set
+    // has the standard pieces (one missing any isn't loaded), so only a
+    // fairy piece ever borrows a model.
+    pub fn model(&self, piece_type: PieceType) -> (&PieceModel, bool) {
+        match piece_type {
+            PieceType::King => (&self.king, false),
+            PieceType::Queen => (&self.queen, false),
+            PieceType::Bishop => (&self.bishop, false),
+            PieceType::Knight => (&self.knight, false),
+            PieceType::Rook => (&self.rook, false),
+            PieceType::Pawn => (&self.pawn, false),
+            fairy => match self.fairy.get(fairy.name()) {
+                Some(model) => (model, false),
+                None => (self.model(fairy.fallback()).0, true),
+            },
+        }
+    }
+}
+
+// All piece sets found in the piece sets directory, and which one is in use.
+pub struct PieceSets {
+    pub sets: Vec<PieceSet>,
+    pub current: usize,
+    // Piece set files that couldn't be read, and why, for the settings menu.
+    pub skipped: Vec<String>,
+}
+
+impl PieceSets {
+    pub fn current(&self) -> &PieceSet {
+        &self.sets[self.current]
+    }
+}
+
+impl Default for PieceSets {
+    fn default() -> Self {
+        let mut paths: Vec<_> = match fs::read_dir(PIECE_SETS_DIR) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "ron"))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        paths.sort();
+
+        let (mut sets, mut skipped) = (Vec::new(), Vec::new());
+        for path in paths {
+            let parsed = fs::read_to_string(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|contents| ron::de::from_str(&contents).map_err(|err| err.to_string()));
+            match parsed {
+                Ok(set) => sets.push(set),
+                Err(err) => skipped.push(format!("Skipped {}: {}", path.display(), err)),
+            }
+        }
+
+        // Always have something to draw with, even when started from another
+        // directory: the bundled chess kit's descriptor is built in.
+        if sets.is_empty() {
+            sets.push(
+                ron::de::from_str(include_str!("../assets/piece_sets/chess_kit.ron"))
+                    .expect("bundled piece set should parse"),
+            );
+        }
+
+        // Prefer the bundled chess kit when it's there.
+        let current = sets
+            .iter()
+            .position(|set: &PieceSet| set.name == "Chess Kit")
+            .unwrap_or(0);
+
+        Self { sets, current, skipped }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHESS_KIT: &str = include_str!("../assets/piece_sets/chess_kit.ron");
+
+    #[test]
+    fn stand_in_models() {
+        let set: PieceSet = ron::de::from_str(CHESS_KIT).unwrap();
+        assert!(!set.model(PieceType::Knight).1);
+        let (model, stand_in) = set.model(PieceType::Camel);
+        assert!(stand_in);
+        assert_eq!(model.sub_meshes, set.knight.sub_meshes);
+    }
+
+    #[test]
+    fn incomplete_sets() {
+        // A set without one of the standard pieces isn't loaded at all.
+        let without_king = CHESS_KIT.replacen("king:", "emperor:", 1);
+        assert!(ron::de::from_str::<PieceSet>(&without_king).is_err());
+    }
+}
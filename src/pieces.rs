@@ -1,11 +1,52 @@
 use bevy::prelude::*;
-use crate::board::Square;
+use crate::{
+    board::{PlayerTurn, Square},
+    game::*,
+    input::InputState,
+    piece_set::*,
+    position::*,
+    theme::*,
+};
 
 pub struct PiecesPlugin;
 impl Plugin for PiecesPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_startup_system(create_pieces.system())
-            .add_system(move_pieces.system());
+        app.init_resource::<PieceSets>()
+            .add_startup_system(create_piece_materials.system())
+            .on_state_enter(STATE_STAGE, AppState::Editor, create_pieces.system())
+            .on_state_exit(STATE_STAGE, AppState::Editor, despawn_pieces.system())
+            .on_state_enter(STATE_STAGE, AppState::Playing, create_pieces.system())
+            .on_state_exit(STATE_STAGE, AppState::GameOver, despawn_pieces.system())
+            .add_system(move_pieces.system())
+            .add_system(swap_piece_set.system())
+            .add_system(rebuild_promoted.system())
+            .add_system(apply_piece_theme.system());
+    }
+}
+
+// Marks a piece whose type changed, e.g. by promotion, and whose meshes
+// need rebuilding.
+pub struct Promoted;
+
+// Materials shared by all pieces of each colour.
+pub struct PieceMaterials {
+    pub white: Handle<StandardMaterial>,
+    pub black: Handle<StandardMaterial>,
+    // Tinted versions for fairy pieces drawn with a stand-in model.
+    pub white_fairy: Handle<StandardMaterial>,
+    pub black_fairy: Handle<StandardMaterial>,
+    // Small ball floating above stand-in fairy pieces.
+    pub marker_mesh: Handle<Mesh>,
+}
+
+impl PieceMaterials {
+    fn get(&self, color: PieceColor, stand_in: bool) -> Handle<StandardMaterial> {
+        match (color, stand_in) {
+            (PieceColor::White, false) => self.white.clone(),
+            (PieceColor::Black, false) => self.black.clone(),
+            (PieceColor::White, true) => self.white_fairy.clone(),
+            (PieceColor::Black, true) => self.black_fairy.clone(),
+        }
     }
 }
 
@@ -15,6 +56,15 @@ pub enum PieceColor {
     Black,
 }
 
+impl PieceColor {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PieceColor::White => "White",
+            PieceColor::Black => "Black",
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq)]
 pub enum PieceType {
     King,
@@ -23,6 +73,121 @@ pub enum PieceType {
     Knight,
     Rook,
     Pawn,
+    // Fairy pieces, moving as described by `PieceType::movement`.
+    Archbishop,
+    Chancellor,
+    Amazon,
+    Camel,
+}
+
+// Movement of a fairy piece, as leaps (jumping straight to an offset) and
+// rides (sliding along a direction until blocked). Each offset is given once
+// and stands for all eight of its reflections and rotations.
+#[derive(Clone, Copy)]
+pub struct Movement {
+    pub leaps: &'static [(i8, i8)],
+    pub rides: &'static [(i8, i8)],
+}
+
+const KNIGHT_LEAP: (i8, i8) = (1, 2);
+const ROOK_RIDE: (i8, i8) = (0, 1);
+const BISHOP_RIDE: (i8, i8) = (1, 1);
+
+impl PieceType {
+    // How a fairy piece moves; the standard pieces have their own rules.
+    pub fn movement(&self) -> Option<Movement> {
+        match self {
+            // Bishop + knight.
+            PieceType::Archbishop => Some(Movement { leaps: &[KNIGHT_LEAP], rides: &[BISHOP_RIDE] }),
+            // Rook + knight.
+            PieceType::Chancellor => Some(Movement { leaps: &[KNIGHT_LEAP], rides: &[ROOK_RIDE] }),
+            // Queen + knight.
+            PieceType::Amazon => {
+                Some(Movement { leaps: &[KNIGHT_LEAP], rides: &[ROOK_RIDE, BISHOP_RIDE] })
+            }
+            // A long knight, leaping (1, 3).
+            PieceType::Camel => Some(Movement { leaps: &[(1, 3)], rides: &[] }),
+            _ => None,
+        }
+    }
+
+    // Standard piece whose mesh stands in when a piece set has no model
+    // for a fairy piece.
+    pub fn fallback(&self) -> PieceType {
+        match self {
+            PieceType::Archbishop => PieceType::Bishop,
+            PieceType::Chancellor => PieceType::Rook,
+            PieceType::Amazon => PieceType::Queen,
+            PieceType::Camel => PieceType::Knight,
+            other => *other,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PieceType::King => "King",
+            PieceType::Queen => "Queen",
+            PieceType::Bishop => "Bishop",
+            PieceType::Knight => "Knight",
+            PieceType::Rook => "Rook",
+            PieceType::Pawn => "Pawn",
+            PieceType::Archbishop => "Archbishop",
+            PieceType::Chancellor => "Chancellor",
+            PieceType::Amazon => "Amazon",
+            PieceType::Camel => "Camel",
+        }
+    }
+}
+
+impl Movement {
+    // Whether a piece moving like this can get from `from` to `to`.
+    pub fn allows(
+        &self,
+        from: (u8, u8),
+        to: (u8, u8),
+        pieces: &Vec<Piece>,
+        size: BoardSize,
+    ) -> bool {
+        let dx = to.0 as i8 - from.0 as i8;
+        let dy = to.1 as i8 - from.1 as i8;
+
+        if self.leaps.iter().any(|leap| symmetries(*leap).contains(&(dx, dy))) {
+            return true;
+        }
+
+        for ride in self.rides {
+            for (step_x, step_y) in symmetries(*ride) {
+                // Walk along the direction until reaching `to` or a piece.
+                let (mut x, mut y) = (from.0 as i8 + step_x, from.1 as i8 + step_y);
+                while x >= 0 && y >= 0 && size.contains((x as u8, y as u8)) {
+                    if (x, y) == (to.0 as i8, to.1 as i8) {
+                        return true;
+                    }
+                    if square_color((x as u8, y as u8), pieces).is_some() {
+                        break;
+                    }
+                    x += step_x;
+                    y += step_y;
+                }
+            }
+        }
+
+        false
+    }
+}
+
+// All reflections and rotations of an offset.
+fn symmetries((a, b): (i8, i8)) -> Vec<(i8, i8)> {
+    let mut offsets = Vec::new();
+    for (x, y) in &[(a, b), (b, a)] {
+        for (sx, sy) in &[(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+            let offset = (x * sx, y * sy);
+            if !offsets.contains(&offset) {
+                offsets.push(offset);
+            }
+        }
+    }
+    offsets
 }
 
 #[derive(Clone, Copy)]
@@ -40,7 +205,15 @@ impl PartialEq<Square> for Piece {
 }
 
 impl Piece {
-    pub fn is_move_valid(&self, new_position: (u8, u8), pieces: Vec<Piece>) -> bool {
+    // Whether the piece may move to `new_position` among `pieces`. Pawns may
+    // also take onto `en_passant`, the square a pawn just skipped.
+    pub fn is_move_valid(
+        &self,
+        new_position: (u8, u8),
+        pieces: Vec<Piece>,
+        size: BoardSize,
+        en_passant: Option<(u8, u8)>,
+    ) -> bool {
         if square_color(new_position, &pieces) == Some(self.color) {
             return false;
         }
@@ -83,68 +256,83 @@ impl Piece {
             PieceType::Pawn => {
                 if self.color == PieceColor::White {
                     // Normal move
-                    if new_position.0 as i8 - self.x as i8 == 1 && (self.y == new_position.1) {
-                        if square_color(new_position, &pieces).is_none() {
-                            return true;
-                        }
+                    if new_position.0 as i8 - self.x as i8 == 1
+                        && (self.y == new_position.1)
+                        && square_color(new_position, &pieces).is_none()
+                    {
+                        return true;
                     }
 
                     // Move 2 squares
-                    if self.x == 1
+                    if self.x == size.pawn_rank(PieceColor::White)
+                        && size.pawn_double_step()
                         && new_position.0 as i8 - self.x as i8 == 2
                         && (self.y == new_position.1)
                         && is_path_empty((self.x, self.y), new_position, &pieces)
+                        && square_color(new_position, &pieces).is_none()
                     {
-                        if square_color(new_position, &pieces).is_none() {
-                            return true;
-                        }
+                        return true;
                     }
 
                     // Take piece
                     if new_position.0 as i8 - self.x as i8 == 1
                         && (self.y as i8 - new_position.1 as i8).abs() == 1
+                        && (square_color(new_position, &pieces) == Some(PieceColor::Black)
+                            || en_passant == Some(new_position))
                     {
-                        if square_color(new_position, &pieces) == Some(PieceColor::Black) {
-                            return true;
-                        }
+                        return true;
                     }
                 } else {
                     // Normal move
-                    if new_position.0 as i8 - self.x as i8 == -1 && (self.y == new_position.1) {
-                        if square_color(new_position, &pieces).is_none() {
-                            return true;
-                        }
+                    if new_position.0 as i8 - self.x as i8 == -1
+                        && (self.y == new_position.1)
+                        && square_color(new_position, &pieces).is_none()
+                    {
+                        return true;
                     }
 
                     // Move 2 squares
-                    if self.x == 6
+                    if self.x == size.pawn_rank(PieceColor::Black)
+                        && size.pawn_double_step()
                         && new_position.0 as i8 - self.x as i8 == -2
                         && (self.y == new_position.1)
                         && is_path_empty((self.x, self.y), new_position, &pieces)
+                        && square_color(new_position, &pieces).is_none()
                     {
-                        if square_color(new_position, &pieces).is_none() {
-                            return true;
-                        }
+                        return true;
                     }
 
                     // Take piece
                     if new_position.0 as i8 - self.x as i8 == -1
                         && (self.y as i8 - new_position.1 as i8).abs() == 1
+                        && (square_color(new_position, &pieces) == Some(PieceColor::White)
+                            || en_passant == Some(new_position))
                     {
-                        if square_color(new_position, &pieces) == Some(PieceColor::White) {
-                            return true;
-                        }
+                        return true;
                     }
                 }
 
                 false
             }
+            fairy => fairy.movement().is_some_and(|movement| {
+                movement.allows((self.x, self.y), new_position, &pieces, size)
+            }),
         }
     }
 }
 
-fn move_pieces(time: Res<Time>, mut query: Query<(&mut Transform, &Piece)>) {
-    for (mut transform, piece) in query.iter_mut() {
+fn move_pieces(
+    time: Res<Time>,
+    input: Res<InputState>,
+    mut query: Query<(Entity, &mut Transform, &Piece)>,
+) {
+    let dragged = input.dragged();
+    for (entity, mut transform, piece) in query.iter_mut() {
+        // A piece held with the mouse sits on the square it's over.
+        if let Some((_, (x, y))) = dragged.filter(|(held, _)| *held == entity) {
+            transform.translation = Vec3::new(x as f32, 0., y as f32);
+            continue;
+        }
         let direction = Vec3::new(piece.x as f32, 0., piece.y as f32) - transform.translation;
 
         if direction.length() > 0.1 {
@@ -210,19 +398,21 @@ fn is_path_empty(begin: (u8, u8), end: (u8, u8), pieces: &Vec<Piece>) -> bool {
     true
 }
 
-fn spawn_single_mesh(
+// Spawns a piece at `position`, built from the current piece set's model.
+// Uses a parent entity with children to eliminate any translation present in
+// the meshes (and combine split meshes); parent tracks actual position, and
+// the children hold the meshes.
+pub fn spawn_piece(
     commands: &mut Commands,
-    material: Handle<StandardMaterial>,
+    asset_server: &AssetServer,
+    piece_set: &PieceSet,
+    piece_materials: &PieceMaterials,
     piece_color: PieceColor,
     piece_type: PieceType,
-    mesh: Handle<Mesh>,
     position: (u8, u8),
-    transform: Transform,
 ) {
-    // Creating the pieces from the meshes using the colors defined above.
-    // Uses a parent entity with children to eliminate any translation
-    // present in the meshes (and combine split meshes); parent tracks
-    // actual position, and the child tracks the mesh.
+    let meshes = piece_meshes(asset_server, piece_set, piece_materials, piece_color, piece_type);
+
     commands
         // Spawn parent entity (tracks actual position).
         .spawn(PbrBundle {
@@ -235,313 +425,269 @@ fn spawn_single_mesh(
         })
         .with(Piece {
             color: piece_color,
-            piece_type: piece_type,
+            piece_type,
             x: position.0,
             y: position.1,
         })
-        // Spawn children (holds mesh).
+        // Spawn children (combines and holds meshes).
         .with_children(|parent| {
-            parent.spawn(PbrBundle {
-                mesh, material: material.clone(), transform, ..Default::default()
-            });
+            for mesh in meshes {
+                parent.spawn(mesh);
+            }
         });
 }
 
-fn spawn_dual_mesh(
-    commands: &mut Commands,
-    material: Handle<StandardMaterial>,
+// The child meshes making up a piece. A fairy piece the set has no model for
+// borrows its stand-in's model, tinted and with a marker on top.
+fn piece_meshes(
+    asset_server: &AssetServer,
+    piece_set: &PieceSet,
+    piece_materials: &PieceMaterials,
     piece_color: PieceColor,
     piece_type: PieceType,
-    mesh_1: Handle<Mesh>,
-    mesh_2: Handle<Mesh>,
-    position: (u8, u8),
-    transform: Transform,
-) {
-    // Creating the pieces from the meshes using the colors defined above.
-    // Uses a parent entity with children to eliminate any translation
-    // present in the meshes (and combine split meshes); parent tracks
-    // actual position, and the child tracks the mesh.
-    commands
-        // Spawn parent entity (tracks actual position).
-        .spawn(PbrBundle {
-            transform: Transform::from_translation(Vec3::new(
-                position.0 as f32,
-                0.,
-                position.1 as f32,
-            )),
-            ..Default::default()
-        })
-        .with(Piece {
-            color: piece_color,
-            piece_type: piece_type,
-            x: position.0,
-            y: position.1,
+) -> Vec<PbrBundle> {
+    let (model, stand_in) = piece_set.model(piece_type);
+    let transform = model.transform();
+    let material = piece_materials.get(piece_color, stand_in);
+
+    let mut meshes: Vec<PbrBundle> = model
+        .meshes(asset_server)
+        .into_iter()
+        .map(|mesh| PbrBundle {
+            mesh, material: material.clone(), transform, ..Default::default()
         })
-        // Spawn children (combines and holds meshes).
-        .with_children(|parent| {
-            parent.spawn(PbrBundle {
-                mesh: mesh_1, material: material.clone(), transform, ..Default::default()
-            });
-            parent.spawn(PbrBundle {
-                mesh: mesh_2, material: material.clone(), transform, ..Default::default()
-            });
-
+        .collect();
+    if stand_in {
+        meshes.push(PbrBundle {
+            mesh: piece_materials.marker_mesh.clone(),
+            material,
+            transform: Transform::from_translation(Vec3::new(0., 1.1, 0.)),
+            ..Default::default()
         });
+    }
+    meshes
+}
+
+// The material for one side's pieces in `theme`, tinted for stand-in
+// fairy pieces.
+fn theme_material(
+    asset_server: &AssetServer,
+    theme: &Theme,
+    piece_material: &PieceMaterial,
+    stand_in: bool,
+) -> StandardMaterial {
+    let albedo = if stand_in {
+        tinted(piece_material.albedo, theme.fairy_tint)
+    } else {
+        rgb(piece_material.albedo)
+    };
+    StandardMaterial {
+        albedo,
+        albedo_texture: load_texture(asset_server, &piece_material.texture),
+        ..Default::default()
+    }
 }
 
-fn spawn_king(
+// Sets up the materials the pieces share, in the current theme;
+// `apply_piece_theme` keeps them in step with it from then on.
+fn create_piece_materials(
     commands: &mut Commands,
-    material: Handle<StandardMaterial>,
-    color: PieceColor,
-    mesh: Handle<Mesh>,
-    mesh_cross: Handle<Mesh>,
-    position: (u8, u8),
+    asset_server: Res<AssetServer>,
+    themes: Res<Themes>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
 ) {
-    let mut transform = Transform::from_translation(Vec3::new(-0.2, 0., -1.9));
-    transform.apply_non_uniform_scale(Vec3::new(0.2, 0.2, 0.2));
-    spawn_dual_mesh(
-        commands, material, color, PieceType::King, mesh, mesh_cross, position, transform
-    );
+    let theme = themes.current();
+    let mut material = |piece_material: &PieceMaterial, stand_in: bool| {
+        materials.add(theme_material(&asset_server, theme, piece_material, stand_in))
+    };
+    commands.insert_resource(PieceMaterials {
+        white: material(&theme.white_piece, false),
+        black: material(&theme.black_piece, false),
+        white_fairy: material(&theme.white_piece, true),
+        black_fairy: material(&theme.black_piece, true),
+        marker_mesh: meshes.add(Mesh::from(shape::Icosphere {
+            radius: 0.12,
+            subdivisions: 2,
+        })),
+    });
 }
 
-fn spawn_knight(
+fn create_pieces(
     commands: &mut Commands,
-    material: Handle<StandardMaterial>,
-    color: PieceColor,
-    mesh_1: Handle<Mesh>,
-    mesh_2: Handle<Mesh>,
-    position: (u8, u8),
+    asset_server: Res<AssetServer>,
+    piece_sets: Res<PieceSets>,
+    piece_materials: Res<PieceMaterials>,
+    setup: Res<GameSetup>,
+    mut turn: ResMut<PlayerTurn>,
+    mut board_state: ResMut<BoardState>,
 ) {
-    let mut transform = Transform::from_translation(Vec3::new(-0.2, 0., 0.9));
-    transform.apply_non_uniform_scale(Vec3::new(0.2, 0.2, 0.2));
-    spawn_dual_mesh(
-        commands, material, color, PieceType::Knight, mesh_1, mesh_2, position, transform
-    );
+    let piece_set = piece_sets.current();
+    let position = setup.start();
+    turn.0 = position.side_to_move;
+    *board_state = position.state.clone();
+
+    for piece in position.pieces {
+        spawn_piece(
+            commands,
+            &asset_server,
+            piece_set,
+            &piece_materials,
+            piece.color,
+            piece.piece_type,
+            (piece.x, piece.y),
+        );
+    }
 }
 
-fn spawn_queen(
+// Clears the pieces of the last game off the board.
+fn despawn_pieces(commands: &mut Commands, query: Query<Entity, With<Piece>>) {
+    for entity in query.iter() {
+        commands.despawn_recursive(entity);
+    }
+}
+
+// Rearranges the pieces on the board into `position`. Pieces already on the
+// board are moved where they can be, so `move_pieces` slides them into
+// place; the rest are removed or spawned.
+pub fn show_position(
     commands: &mut Commands,
-    material: Handle<StandardMaterial>,
-    color: PieceColor,
-    mesh: Handle<Mesh>,
-    position: (u8, u8),
+    asset_server: &AssetServer,
+    piece_set: &PieceSet,
+    piece_materials: &PieceMaterials,
+    position: &Position,
+    pieces_query: &mut Query<(Entity, &mut Piece)>,
 ) {
-    let mut transform = Transform::from_translation(Vec3::new(-0.2, 0., -0.95));
-    transform.apply_non_uniform_scale(Vec3::new(0.2, 0.2, 0.2));
-    spawn_single_mesh(
-        commands, material, color, PieceType::Queen, mesh, position, transform
-    );
+    let same_kind = |a: &Piece, b: &Piece| a.color == b.color && a.piece_type == b.piece_type;
+
+    // Pieces already where they belong stay put.
+    let mut missing = position.pieces.clone();
+    let mut spare = Vec::new();
+    for (entity, piece) in pieces_query.iter_mut() {
+        let in_place = missing.iter().position(|target| {
+            same_kind(target, &piece) && target.x == piece.x && target.y == piece.y
+        });
+        match in_place {
+            Some(idx) => {
+                missing.remove(idx);
+            }
+            None => spare.push((entity, *piece)),
+        }
+    }
+
+    // The others go to the nearest square still wanting a piece like them.
+    for (entity, piece) in spare {
+        let distance = |target: &Piece| {
+            (target.x as i32 - piece.x as i32).abs() + (target.y as i32 - piece.y as i32).abs()
+        };
+        let nearest = missing
+            .iter()
+            .enumerate()
+            .filter(|(_, target)| same_kind(target, &piece))
+            .min_by_key(|(_, target)| distance(target))
+            .map(|(idx, _)| idx);
+        match nearest {
+            Some(idx) => {
+                let target = missing.remove(idx);
+                if let Ok((_, mut piece)) = pieces_query.get_mut(entity) {
+                    piece.x = target.x;
+                    piece.y = target.y;
+                }
+            }
+            None => {
+                commands.despawn_recursive(entity);
+            }
+        }
+    }
+
+    for piece in missing {
+        spawn_piece(
+            commands,
+            asset_server,
+            piece_set,
+            piece_materials,
+            piece.color,
+            piece.piece_type,
+            (piece.x, piece.y),
+        );
+    }
 }
 
-fn spawn_bishop(
+// Rebuilds every piece's meshes when a different piece set is chosen.
+fn swap_piece_set(
     commands: &mut Commands,
-    material: Handle<StandardMaterial>,
-    color: PieceColor,
-    mesh: Handle<Mesh>,
-    position: (u8, u8),
+    piece_sets: ChangedRes<PieceSets>,
+    asset_server: Res<AssetServer>,
+    piece_materials: Res<PieceMaterials>,
+    mut applied: Local<Option<usize>>,
+    query: Query<(Entity, &Piece, &Children)>,
 ) {
-    let mut transform = Transform::from_translation(Vec3::new(-0.1, 0., 0.));
-    transform.apply_non_uniform_scale(Vec3::new(0.2, 0.2, 0.2));
-    spawn_single_mesh(
-        commands, material, color, PieceType::Bishop, mesh, position, transform
-    );
+    // The pieces were spawned with the first set we see.
+    if applied.replace(piece_sets.current).is_none_or(|prev| prev == piece_sets.current) {
+        return;
+    }
+    let piece_set = piece_sets.current();
+    for (entity, piece, children) in query.iter() {
+        rebuild_meshes(commands, &asset_server, piece_set, &piece_materials, entity, piece, children);
+    }
 }
 
-fn spawn_rook(
+// Gives promoted pieces the meshes of their new type.
+fn rebuild_promoted(
     commands: &mut Commands,
-    material: Handle<StandardMaterial>,
-    color: PieceColor,
-    mesh: Handle<Mesh>,
-    position: (u8, u8),
+    piece_sets: Res<PieceSets>,
+    asset_server: Res<AssetServer>,
+    piece_materials: Res<PieceMaterials>,
+    query: Query<(Entity, &Piece, &Children), With<Promoted>>,
 ) {
-    let mut transform = Transform::from_translation(Vec3::new(-0.1, 0., 1.8));
-    transform.apply_non_uniform_scale(Vec3::new(0.2, 0.2, 0.2));
-    spawn_single_mesh(
-        commands, material, color, PieceType::Rook, mesh, position, transform
-    );
+    let piece_set = piece_sets.current();
+    for (entity, piece, children) in query.iter() {
+        rebuild_meshes(commands, &asset_server, piece_set, &piece_materials, entity, piece, children);
+        commands.remove_one::<Promoted>(entity);
+    }
 }
 
-fn spawn_pawn(
+// Replaces the meshes under a piece's entity with those for its type in
+// `piece_set`.
+fn rebuild_meshes(
     commands: &mut Commands,
-    material: Handle<StandardMaterial>,
-    color: PieceColor,
-    mesh: Handle<Mesh>,
-    position: (u8, u8),
+    asset_server: &AssetServer,
+    piece_set: &PieceSet,
+    piece_materials: &PieceMaterials,
+    entity: Entity,
+    piece: &Piece,
+    children: &Children,
 ) {
-    let mut transform = Transform::from_translation(Vec3::new(-0.2, 0., 2.6));
-    transform.apply_non_uniform_scale(Vec3::new(0.2, 0.2, 0.2));
-    spawn_single_mesh(
-        commands, material, color, PieceType::Pawn, mesh, position, transform
-    );
+    for child in children.iter() {
+        commands.despawn(*child);
+    }
+
+    let mut new_children = Vec::new();
+    let meshes = piece_meshes(asset_server, piece_set, piece_materials, piece.color, piece.piece_type);
+    for mesh in meshes {
+        commands.spawn(mesh);
+        new_children.push(commands.current_entity().unwrap());
+    }
+    commands.push_children(entity, &new_children);
 }
 
-fn create_pieces(
-    commands: &mut Commands,
+// Updates the piece materials whenever the theme changes.
+fn apply_piece_theme(
+    themes: ChangedRes<Themes>,
     asset_server: Res<AssetServer>,
+    piece_materials: Res<PieceMaterials>,
     mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
-    // Load all the meshes using the AssetServer.
-    // Note: the king and the knight are both separated into 2 meshes.
-    let king_handle: Handle<Mesh> =
-        asset_server.load("models/chess_kit/pieces.glb#Mesh0/Primitive0");
-    let king_cross_handle: Handle<Mesh> =
-        asset_server.load("models/chess_kit/pieces.glb#Mesh1/Primitive0");
-    let pawn_handle: Handle<Mesh> =
-        asset_server.load("models/chess_kit/pieces.glb#Mesh2/Primitive0");
-    let knight_1_handle: Handle<Mesh> =
-        asset_server.load("models/chess_kit/pieces.glb#Mesh3/Primitive0");
-    let knight_2_handle: Handle<Mesh> =
-        asset_server.load("models/chess_kit/pieces.glb#Mesh4/Primitive0");
-    let rook_handle: Handle<Mesh> =
-        asset_server.load("models/chess_kit/pieces.glb#Mesh5/Primitive0");
-    let bishop_handle: Handle<Mesh> =
-        asset_server.load("models/chess_kit/pieces.glb#Mesh6/Primitive0");
-    let queen_handle: Handle<Mesh> =
-        asset_server.load("models/chess_kit/pieces.glb#Mesh7/Primitive0");
-
-    // Defining colors for white and black pieces.
-    let white_material: Handle<StandardMaterial> =
-        materials.add(Color::rgb(1., 0.8, 0.8).into());
-    let black_material: Handle<StandardMaterial> =
-        materials.add(Color::rgb(0., 0.2, 0.2).into());
-
-    // White pieces.
-    spawn_rook(
-        commands,
-        white_material.clone(),
-        PieceColor::White,
-        rook_handle.clone(),
-        (0, 0),
-    );
-    spawn_knight(
-        commands,
-        white_material.clone(),
-        PieceColor::White,
-        knight_1_handle.clone(),
-        knight_2_handle.clone(),
-        (0, 1),
-    );
-    spawn_bishop(
-        commands,
-        white_material.clone(),
-        PieceColor::White,
-        bishop_handle.clone(),
-        (0, 2),
-    );
-    spawn_queen(
-        commands,
-        white_material.clone(),
-        PieceColor::White,
-        queen_handle.clone(),
-        (0, 3),
-    );
-    spawn_king(
-        commands,
-        white_material.clone(),
-        PieceColor::White,
-        king_handle.clone(),
-        king_cross_handle.clone(),
-        (0, 4),
-    );
-    spawn_bishop(
-        commands,
-        white_material.clone(),
-        PieceColor::White,
-        bishop_handle.clone(),
-        (0, 5),
-    );
-    spawn_knight(
-        commands,
-        white_material.clone(),
-        PieceColor::White,
-        knight_1_handle.clone(),
-        knight_2_handle.clone(),
-        (0, 6),
-    );
-    spawn_rook(
-        commands,
-        white_material.clone(),
-        PieceColor::White,
-        rook_handle.clone(),
-        (0, 7),
-    );
-    for i in 0..8 {
-        spawn_pawn(
-            commands,
-            white_material.clone(),
-            PieceColor::White,
-            pawn_handle.clone(),
-            (1, i),
-        );
-    }
-
-    // Black pieces.
-    spawn_rook(
-        commands,
-        black_material.clone(),
-        PieceColor::Black,
-        rook_handle.clone(),
-        (7, 0),
-    );
-    spawn_knight(
-        commands,
-        black_material.clone(),
-        PieceColor::Black,
-        knight_1_handle.clone(),
-        knight_2_handle.clone(),
-        (7, 1),
-    );
-    spawn_bishop(
-        commands,
-        black_material.clone(),
-        PieceColor::Black,
-        bishop_handle.clone(),
-        (7, 2),
-    );
-    spawn_queen(
-        commands,
-        black_material.clone(),
-        PieceColor::Black,
-        queen_handle.clone(),
-        (7, 3),
-    );
-    spawn_king(
-        commands,
-        black_material.clone(),
-        PieceColor::Black,
-        king_handle.clone(),
-        king_cross_handle.clone(),
-        (7, 4),
-    );
-    spawn_bishop(
-        commands,
-        black_material.clone(),
-        PieceColor::Black,
-        bishop_handle.clone(),
-        (7, 5),
-    );
-    spawn_knight(
-        commands,
-        black_material.clone(),
-        PieceColor::Black,
-        knight_1_handle.clone(),
-        knight_2_handle.clone(),
-        (7, 6),
-    );
-    spawn_rook(
-        commands,
-        black_material.clone(),
-        PieceColor::Black,
-        rook_handle.clone(),
-        (7, 7),
-    );
-    for i in 0..8 {
-        spawn_pawn(
-            commands,
-            black_material.clone(),
-            PieceColor::Black,
-            pawn_handle.clone(),
-            (6, i),
-        );
+    let theme = themes.current();
+
+    for (handle, piece_material, stand_in) in &[
+        (&piece_materials.white, &theme.white_piece, false),
+        (&piece_materials.black, &theme.black_piece, false),
+        (&piece_materials.white_fairy, &theme.white_piece, true),
+        (&piece_materials.black_fairy, &theme.black_piece, true),
+    ] {
+        if let Some(material) = materials.get_mut(*handle) {
+            *material = theme_material(&asset_server, theme, piece_material, *stand_in);
+        }
     }
 }
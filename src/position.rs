@@ -0,0 +1,647 @@
+use crate::{pieces::*, variant::VariantKind};
+
+// Files of the rooks each side may still castle with.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct CastlingRights {
+    pub white: SideRights,
+    pub black: SideRights,
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct SideRights {
+    pub queenside: Option<u8>,
+    pub kingside: Option<u8>,
+}
+
+impl CastlingRights {
+    pub fn get(&self, color: PieceColor) -> SideRights {
+        match color {
+            PieceColor::White => self.white,
+            PieceColor::Black => self.black,
+        }
+    }
+
+    pub fn get_mut(&mut self, color: PieceColor) -> &mut SideRights {
+        match color {
+            PieceColor::White => &mut self.white,
+            PieceColor::Black => &mut self.black,
+        }
+    }
+}
+
+impl SideRights {
+    pub fn rook_file(&self, side: CastleSide) -> Option<u8> {
+        match side {
+            CastleSide::Queenside => self.queenside,
+            CastleSide::Kingside => self.kingside,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CastleSide {
+    Queenside,
+    Kingside,
+}
+
+impl CastleSide {
+    // Files the king and rook end up on, the same in Chess960 as in chess.
+    // On wider boards kingside castling lands as far from the h-file as it
+    // would on an 8x8 board, as in Capablanca chess.
+    pub fn king_file(&self, size: BoardSize) -> u8 {
+        match self {
+            CastleSide::Queenside => 2,
+            CastleSide::Kingside => size.files - 2,
+        }
+    }
+
+    pub fn rook_file(&self, size: BoardSize) -> u8 {
+        match self {
+            CastleSide::Queenside => 3,
+            CastleSide::Kingside => size.files - 3,
+        }
+    }
+}
+
+// Dimensions of the board: ranks run from white's side to black's, files
+// from the a-file across.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BoardSize {
+    pub ranks: u8,
+    pub files: u8,
+}
+
+impl BoardSize {
+    pub const STANDARD: BoardSize = BoardSize { ranks: 8, files: 8 };
+    // Largest supported in either direction, so files run from a to p.
+    pub const MAX: u8 = 16;
+
+    pub fn contains(&self, pos: (u8, u8)) -> bool {
+        pos.0 < self.ranks && pos.1 < self.files
+    }
+
+    // Every square on the board, rank by rank.
+    pub fn squares(&self) -> impl Iterator<Item = (u8, u8)> {
+        let files = self.files;
+        (0..self.ranks).flat_map(move |x| (0..files).map(move |y| (x, y)))
+    }
+
+    pub fn back_rank(&self, color: PieceColor) -> u8 {
+        match color {
+            PieceColor::White => 0,
+            PieceColor::Black => self.ranks - 1,
+        }
+    }
+
+    // Rank the pawns of `color` start on.
+    pub fn pawn_rank(&self, color: PieceColor) -> u8 {
+        match color {
+            PieceColor::White => 1,
+            PieceColor::Black => self.ranks - 2,
+        }
+    }
+
+    // Rank on which pawns of `color` promote.
+    pub fn promotion_rank(&self, color: PieceColor) -> u8 {
+        self.back_rank(opponent(color))
+    }
+
+    // Minichess boards are too short for the pawn's double step.
+    pub fn pawn_double_step(&self) -> bool {
+        self.ranks >= 8
+    }
+
+    // Centre of the board in (rank, file) coordinates.
+    pub fn centre(&self) -> (f32, f32) {
+        ((self.ranks - 1) as f32 / 2., (self.files - 1) as f32 / 2.)
+    }
+}
+
+impl Default for BoardSize {
+    fn default() -> Self {
+        BoardSize::STANDARD
+    }
+}
+
+// Everything about a position that can't be seen from the pieces alone.
+#[derive(Clone, Default)]
+pub struct BoardState {
+    pub size: BoardSize,
+    pub castling: CastlingRights,
+    pub en_passant: Option<(u8, u8)>,
+    pub halfmove_clock: u32,
+    pub fullmove_number: u32,
+    // Checks given by white and black, for Three-check.
+    pub checks_given: (u8, u8),
+}
+
+// A complete position: the pieces, whose turn it is and the board state.
+#[derive(Clone)]
+pub struct Position {
+    pub pieces: Vec<Piece>,
+    pub side_to_move: PieceColor,
+    pub state: BoardState,
+}
+
+// What a move did beyond moving its piece, so the scene can follow along.
+#[derive(Clone, Default)]
+pub struct MoveEffects {
+    // Where the moving piece ended up; for castling this is the king's
+    // castled square rather than the square clicked.
+    pub destination: (u8, u8),
+    // Squares, as they were before the move, of the pieces taken off the
+    // board. Usually just the captured piece; variants may remove more.
+    pub captured: Vec<(u8, u8)>,
+    // The rook's move when castling.
+    pub castling_rook: Option<((u8, u8), (u8, u8))>,
+    // What a pawn reaching the last rank turned into.
+    pub promotion: Option<PieceType>,
+}
+
+pub fn opponent(color: PieceColor) -> PieceColor {
+    match color {
+        PieceColor::White => PieceColor::Black,
+        PieceColor::Black => PieceColor::White,
+    }
+}
+
+impl Position {
+    pub fn piece_at(&self, pos: (u8, u8)) -> Option<&Piece> {
+        self.pieces.iter().find(|piece| (piece.x, piece.y) == pos)
+    }
+
+    // Whether moving the piece on `from` to `to` takes something, en passant
+    // included. The move itself isn't checked.
+    pub fn is_capture(&self, from: (u8, u8), to: (u8, u8)) -> bool {
+        let piece = match self.piece_at(from) {
+            Some(piece) => piece,
+            None => return false,
+        };
+        match self.piece_at(to) {
+            Some(target) => target.color != piece.color,
+            None => piece.piece_type == PieceType::Pawn && self.state.en_passant == Some(to),
+        }
+    }
+
+    pub fn king(&self, color: PieceColor) -> Option<&Piece> {
+        self.pieces
+            .iter()
+            .find(|piece| piece.color == color && piece.piece_type == PieceType::King)
+    }
+
+    // Whether any piece of `by` attacks `square`.
+    pub fn is_attacked(&self, square: (u8, u8), by: PieceColor) -> bool {
+        attacked(&self.pieces, square, by, self.state.size)
+    }
+
+    pub fn in_check(&self, color: PieceColor) -> bool {
+        match self.king(color) {
+            Some(king) => self.is_attacked((king.x, king.y), opponent(color)),
+            None => false,
+        }
+    }
+
+    // File of the outermost rook of `color` on its back rank on `side` of its
+    // king, which is the rook plain "K"/"Q" castling rights refer to.
+    pub fn outermost_rook(&self, color: PieceColor, side: CastleSide) -> Option<u8> {
+        let rank = self.state.size.back_rank(color);
+        let king = self.king(color).filter(|king| king.x == rank)?;
+        let rooks = self.pieces.iter().filter(|piece| {
+            piece.color == color && piece.piece_type == PieceType::Rook && piece.x == rank
+        });
+        match side {
+            CastleSide::Kingside => rooks.filter(|rook| rook.y > king.y).map(|rook| rook.y).max(),
+            CastleSide::Queenside => rooks.filter(|rook| rook.y < king.y).map(|rook| rook.y).min(),
+        }
+    }
+
+    // Checks the position could come up in a game of `variant`: one king
+    // each, no pawns on the first or last rank, the side that just moved not
+    // left in check, and an en passant square just behind a pawn that could
+    // have double stepped there. Horde's white side has no king and starts
+    // with pawns on its first rank, and antichess kings are ordinary pieces.
+    // Says what's wrong if not.
+    pub fn validate(&self, variant: VariantKind) -> Result<(), String> {
+        let size = self.state.size;
+        for color in &[PieceColor::White, PieceColor::Black] {
+            let kings = self
+                .pieces
+                .iter()
+                .filter(|piece| piece.color == *color && piece.piece_type == PieceType::King)
+                .count();
+            match (variant, color) {
+                (VariantKind::Antichess, _) => {}
+                (VariantKind::Horde, PieceColor::White) => {
+                    if kings != 0 {
+                        return Err(format!("{} can't have a king in Horde", color.name()));
+                    }
+                }
+                _ => {
+                    if kings != 1 {
+                        return Err(format!("{} needs exactly one king", color.name()));
+                    }
+                }
+            }
+        }
+        let first_rank_pawns = |color| variant == VariantKind::Horde && color == PieceColor::White;
+        if self.pieces.iter().any(|piece| {
+            piece.piece_type == PieceType::Pawn
+                && (piece.x == size.promotion_rank(piece.color)
+                    || (piece.x == size.back_rank(piece.color) && !first_rank_pawns(piece.color)))
+        }) {
+            return Err("Pawns can't stand on the first or last rank".to_string());
+        }
+
+        let waiting = opponent(self.side_to_move);
+        if variant != VariantKind::Antichess && self.in_check(waiting) {
+            return Err(format!("{} is in check but not to move", waiting.name()));
+        }
+
+        if let Some(square) = self.state.en_passant {
+            if !self.en_passant_squares().contains(&square) {
+                return Err("No pawn just double stepped past the en passant square".to_string());
+            }
+        }
+
+        Ok(())
+    }
+
+    // Squares the side to move could have as an en passant square: those a
+    // pawn of the other side just skipped with a double step.
+    pub fn en_passant_squares(&self) -> Vec<(u8, u8)> {
+        let size = self.state.size;
+        if !size.pawn_double_step() {
+            return Vec::new();
+        }
+        let mover = opponent(self.side_to_move);
+        let (start, skipped, landed) = match mover {
+            PieceColor::White => (1, 2, 3),
+            PieceColor::Black => (size.ranks - 2, size.ranks - 3, size.ranks - 4),
+        };
+        (0..size.files)
+            .filter(|file| {
+                let pawn_landed = self.piece_at((landed, *file)).is_some_and(|piece| {
+                    piece.color == mover && piece.piece_type == PieceType::Pawn
+                });
+                pawn_landed
+                    && self.piece_at((skipped, *file)).is_none()
+                    && self.piece_at((start, *file)).is_none()
+            })
+            .map(|file| (skipped, file))
+            .collect()
+    }
+
+    // The castling a king move from `from` to `to` stands for, if any: either
+    // the king moving two or more files onto its castled square, or (as
+    // Chess960 needs when that's ambiguous) the king moving onto its own rook.
+    pub fn castling_side(&self, from: (u8, u8), to: (u8, u8)) -> Option<CastleSide> {
+        let king = self.piece_at(from)?;
+        let size = self.state.size;
+        if king.piece_type != PieceType::King
+            || from.0 != size.back_rank(king.color)
+            || to.0 != from.0
+        {
+            return None;
+        }
+
+        let rights = self.state.castling.get(king.color);
+        for side in &[CastleSide::Queenside, CastleSide::Kingside] {
+            let onto_rook = rights.rook_file(*side) == Some(to.1);
+            let two_files = to.1 == side.king_file(size) && (to.1 as i8 - from.1 as i8).abs() >= 2;
+            if onto_rook || two_files {
+                return Some(*side);
+            }
+        }
+        None
+    }
+
+    // Whether `color` may castle on `side` right now (Chess960 rules, which
+    // include the classical ones).
+    pub fn can_castle(&self, color: PieceColor, side: CastleSide) -> bool {
+        let size = self.state.size;
+        let rank = size.back_rank(color);
+        let rook_file = match self.state.castling.get(color).rook_file(side) {
+            Some(file) => file,
+            None => return false,
+        };
+        let king = match self.king(color) {
+            Some(king) if king.x == rank => *king,
+            _ => return false,
+        };
+        match self.piece_at((rank, rook_file)) {
+            Some(rook) if rook.color == color && rook.piece_type == PieceType::Rook => {}
+            _ => return false,
+        }
+
+        // Every square either piece crosses or lands on must be empty, bar
+        // the king and the castling rook themselves.
+        let files = [king.y, side.king_file(size), rook_file, side.rook_file(size)];
+        let (low, high) = (*files.iter().min().unwrap(), *files.iter().max().unwrap());
+        for file in low..=high {
+            if file == king.y || file == rook_file {
+                continue;
+            }
+            if self.piece_at((rank, file)).is_some() {
+                return false;
+            }
+        }
+
+        // The king may not castle out of, through, or into check. The king
+        // and rook are lifted off so they don't shield any of those squares.
+        let lifted: Vec<Piece> = self
+            .pieces
+            .iter()
+            .filter(|piece| (piece.x, piece.y) != (rank, king.y) && (piece.x, piece.y) != (rank, rook_file))
+            .copied()
+            .collect();
+        let (low, high) = (king.y.min(side.king_file(size)), king.y.max(side.king_file(size)));
+        for file in low..=high {
+            if attacked(&lifted, (rank, file), opponent(color), size) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    // Whether the piece on `from` may move to `to`, castling included.
+    pub fn is_move_valid(&self, from: (u8, u8), to: (u8, u8)) -> bool {
+        let piece = match self.piece_at(from) {
+            Some(piece) => *piece,
+            None => return false,
+        };
+        if !self.state.size.contains(to) {
+            return false;
+        }
+        if let Some(side) = self.castling_side(from, to) {
+            return self.can_castle(piece.color, side);
+        }
+        piece.is_move_valid(to, self.pieces.clone(), self.state.size, self.state.en_passant)
+    }
+
+    // Whether moving the piece on `from` to `to` promotes it.
+    pub fn is_promotion(&self, from: (u8, u8), to: (u8, u8)) -> bool {
+        self.piece_at(from).is_some_and(|piece| {
+            piece.piece_type == PieceType::Pawn && to.0 == self.state.size.promotion_rank(piece.color)
+        })
+    }
+
+    // Turns the piece a pawn just promoted to on `square` into `piece_type`;
+    // `make_move` always makes a queen.
+    pub fn promote(&mut self, square: (u8, u8), piece_type: PieceType) {
+        if let Some(piece) = self.pieces.iter_mut().find(|piece| (piece.x, piece.y) == square) {
+            piece.piece_type = piece_type;
+        }
+    }
+
+    // Plays a move, assumed valid, updating the board state as it goes.
+    pub fn make_move(&mut self, from: (u8, u8), to: (u8, u8)) -> MoveEffects {
+        let mut effects = MoveEffects::default();
+        let piece = match self.piece_at(from) {
+            Some(piece) => *piece,
+            None => return effects,
+        };
+        let size = self.state.size;
+        let rank = size.back_rank(piece.color);
+
+        let castling = self.castling_side(from, to);
+        let king_to = match castling {
+            Some(side) => {
+                let rook_file = self.state.castling.get(piece.color).rook_file(side).unwrap();
+                let rook_to = (rank, side.rook_file(size));
+                effects.castling_rook = Some(((rank, rook_file), rook_to));
+                (rank, side.king_file(size))
+            }
+            None => to,
+        };
+
+        effects.destination = king_to;
+        if castling.is_none() && self.piece_at(to).is_some() {
+            effects.captured.push(to);
+        }
+        // Taking en passant removes the pawn that was passed, beside `from`.
+        let en_passant = piece.piece_type == PieceType::Pawn && self.state.en_passant == Some(to);
+        if en_passant && self.piece_at(to).is_none() {
+            effects.captured.push((from.0, to.1));
+        }
+
+        // Castling rights are lost by moving the king, moving a castling
+        // rook, or having it captured.
+        if piece.piece_type == PieceType::King {
+            *self.state.castling.get_mut(piece.color) = SideRights::default();
+        }
+        for square in &[from, to] {
+            for color in &[PieceColor::White, PieceColor::Black] {
+                if square.0 != size.back_rank(*color) {
+                    continue;
+                }
+                let rights = self.state.castling.get_mut(*color);
+                if rights.queenside == Some(square.1) {
+                    rights.queenside = None;
+                }
+                if rights.kingside == Some(square.1) {
+                    rights.kingside = None;
+                }
+            }
+        }
+
+        // Lift off everything involved, then put the movers back down, so
+        // castling works even when the king and rook swap squares.
+        let rook_move = effects.castling_rook;
+        let captured = &effects.captured;
+        self.pieces.retain(|other| {
+            let pos = (other.x, other.y);
+            pos != from
+                && !captured.contains(&pos)
+                && rook_move.is_none_or(|(rook_from, _)| pos != rook_from)
+        });
+        // Pawns reaching the last rank become queens.
+        let mut moved = Piece { x: king_to.0, y: king_to.1, ..piece };
+        if piece.piece_type == PieceType::Pawn && king_to.0 == size.promotion_rank(piece.color) {
+            moved.piece_type = PieceType::Queen;
+            effects.promotion = Some(PieceType::Queen);
+        }
+        self.pieces.push(moved);
+        if let Some((_, rook_to)) = rook_move {
+            self.pieces.push(Piece {
+                color: piece.color,
+                piece_type: PieceType::Rook,
+                x: rook_to.0,
+                y: rook_to.1,
+            });
+        }
+
+        // A pawn's double step leaves the square it skipped open to en passant.
+        self.state.en_passant = None;
+        if piece.piece_type == PieceType::Pawn && (to.0 as i8 - from.0 as i8).abs() == 2 {
+            self.state.en_passant = Some(((from.0 + to.0) / 2, from.1));
+        }
+        if piece.piece_type == PieceType::Pawn || !effects.captured.is_empty() {
+            self.state.halfmove_clock = 0;
+        } else {
+            self.state.halfmove_clock += 1;
+        }
+        if piece.color == PieceColor::Black {
+            self.state.fullmove_number += 1;
+        }
+        self.side_to_move = opponent(piece.color);
+
+        effects
+    }
+}
+
+// Whether any piece of `by` among `pieces` attacks `square`.
+pub fn attacked(pieces: &[Piece], square: (u8, u8), by: PieceColor, size: BoardSize) -> bool {
+    // Pretend a defending piece stands on the square, so the attackers'
+    // capture rules apply to it.
+    let mut board: Vec<Piece> = pieces
+        .iter()
+        .filter(|piece| (piece.x, piece.y) != square)
+        .copied()
+        .collect();
+    board.push(Piece {
+        color: opponent(by),
+        piece_type: PieceType::Pawn,
+        x: square.0,
+        y: square.1,
+    });
+
+    pieces
+        .iter()
+        .filter(|piece| piece.color == by && (piece.x, piece.y) != square)
+        .any(|piece| piece.is_move_valid(square, board.clone(), size, None))
+}
+
+// The back rank of Chess960 starting position number `index` (0-959), using
+// Scharnagl's numbering, where 518 is the classical setup.
+pub fn chess960_back_rank(index: u16) -> [PieceType; 8] {
+    let mut rank: [Option<PieceType>; 8] = [None; 8];
+    let mut n = index % 960;
+
+    // Bishops go on opposite colours: first the light-squared one (b, d, f
+    // or h file), then the dark-squared one (a, c, e or g file).
+    rank[(n % 4 * 2 + 1) as usize] = Some(PieceType::Bishop);
+    n /= 4;
+    rank[(n % 4 * 2) as usize] = Some(PieceType::Bishop);
+    n /= 4;
+
+    // Everything else fills in whichever files are still free.
+    let place = |rank: &mut [Option<PieceType>; 8], nth_free: u16, piece_type: PieceType| {
+        let file = (0..8).filter(|file| rank[*file].is_none()).nth(nth_free as usize).unwrap();
+        rank[file] = Some(piece_type);
+    };
+
+    place(&mut rank, n % 6, PieceType::Queen);
+    n /= 6;
+
+    // The ten ways of placing two knights on the five free files.
+    const KNIGHTS: [(u16, u16); 10] =
+        [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)];
+    let (first, second) = KNIGHTS[n as usize];
+    // Placing the first knight shifts the free files left by one.
+    place(&mut rank, first, PieceType::Knight);
+    place(&mut rank, second - 1, PieceType::Knight);
+
+    // Rook, king, rook in the remaining three, so the king is between them.
+    place(&mut rank, 0, PieceType::Rook);
+    place(&mut rank, 0, PieceType::King);
+    place(&mut rank, 0, PieceType::Rook);
+
+    let mut back_rank = [PieceType::Pawn; 8];
+    for (file, piece_type) in rank.iter().enumerate() {
+        back_rank[file] = piece_type.unwrap();
+    }
+    back_rank
+}
+
+// Starting position number `index` of Chess960.
+pub fn chess960_position(index: u16) -> Position {
+    let back = chess960_back_rank(index);
+    let mut pieces = Vec::new();
+    for color in &[PieceColor::White, PieceColor::Black] {
+        let (rank, pawn_rank) = match color {
+            PieceColor::White => (0, 1),
+            PieceColor::Black => (7, 6),
+        };
+        for file in 0..8 {
+            pieces.push(Piece { color: *color, piece_type: back[file as usize], x: rank, y: file });
+            pieces.push(Piece { color: *color, piece_type: PieceType::Pawn, x: pawn_rank, y: file });
+        }
+    }
+
+    let rooks: Vec<u8> = (0..8).filter(|file| back[*file as usize] == PieceType::Rook).collect();
+    let rights = SideRights { queenside: Some(rooks[0]), kingside: Some(rooks[1]) };
+
+    Position {
+        pieces,
+        side_to_move: PieceColor::White,
+        state: BoardState {
+            size: BoardSize::STANDARD,
+            castling: CastlingRights { white: rights, black: rights },
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            checks_given: (0, 0),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classical_chess960_index() {
+        use PieceType::*;
+        assert!(chess960_back_rank(518) == [Rook, Knight, Bishop, Queen, King, Bishop, Knight, Rook]);
+    }
+
+    #[test]
+    fn chess960_positions() {
+        let mut back_ranks = Vec::new();
+        for index in 0..960 {
+            let back = chess960_back_rank(index);
+            let files = |piece_type: PieceType| -> Vec<usize> {
+                (0..8).filter(|file| back[*file] == piece_type).collect()
+            };
+            let (bishops, rooks, king) = (files(PieceType::Bishop), files(PieceType::Rook), files(PieceType::King));
+            assert_eq!(bishops.len(), 2);
+            assert_ne!(bishops[0] % 2, bishops[1] % 2, "bishops on one colour in {}", index);
+            assert_eq!(king.len(), 1);
+            assert!(rooks[0] < king[0] && king[0] < rooks[1], "king outside its rooks in {}", index);
+            assert_eq!(files(PieceType::Queen).len(), 1);
+            assert_eq!(files(PieceType::Knight).len(), 2);
+
+            assert!(chess960_position(index).validate(VariantKind::Standard).is_ok());
+            back_ranks.push(back.iter().map(|piece_type| *piece_type as u8).collect::<Vec<_>>());
+        }
+        back_ranks.sort();
+        back_ranks.dedup();
+        assert_eq!(back_ranks.len(), 960);
+    }
+
+    #[test]
+    fn en_passant() {
+        use crate::notation::{parse_fen, parse_square, to_fen, STANDARD_FEN};
+        let square = |name: &str| parse_square(name).unwrap();
+
+        let mut position = parse_fen(STANDARD_FEN).unwrap();
+        for (from, to) in &[("e2", "e4"), ("d7", "d5"), ("e4", "e5"), ("f7", "f5")] {
+            assert!(position.is_move_valid(square(from), square(to)));
+            position.make_move(square(from), square(to));
+        }
+        let fen = "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3";
+        assert_eq!(to_fen(&position, false), fen);
+
+        // Only the pawn that just double stepped can be taken en passant.
+        let mut position = parse_fen(fen).unwrap();
+        assert!(position.is_move_valid(square("e5"), square("f6")));
+        assert!(!position.is_move_valid(square("e5"), square("d6")));
+        let effects = position.make_move(square("e5"), square("f6"));
+        assert_eq!(effects.captured, vec![square("f5")]);
+
+        let fen = to_fen(&position, false);
+        assert_eq!(fen, "rnbqkbnr/ppp1p1pp/5P2/3p4/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 3");
+        assert_eq!(to_fen(&parse_fen(&fen).unwrap(), false), fen);
+    }
+}
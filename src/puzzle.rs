@@ -0,0 +1,416 @@
+use crate::{
+    ai::Move,
+    moves::play_move,
+    notation::{parse_fen, parse_promotion, parse_uci},
+    pieces::{PieceColor, PieceType},
+    position::{opponent, Position},
+    variant::Variant,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+// Puzzles in the Lichess CSV format, or any subset of its columns with a
+// header naming them (PuzzleId, FEN, Moves, Rating).
+const PUZZLES_FILE: &str = "assets/puzzles/puzzles.csv";
+// The solver's rating, streaks and the puzzles already seen, kept in the
+// user's config directory (see `stats_path`).
+const STATS_FILE: &str = "puzzle_stats.ron";
+const CONFIG_DIR: &str = "bevy-chess";
+
+// Rating a new solver starts from, and how far one puzzle can move it.
+const START_RATING: f32 = 1500.;
+const RATING_K: f32 = 32.;
+
+// A puzzle as Lichess publishes it: the position before the opponent's
+// move, then the whole line in UCI, starting with that move.
+pub struct Puzzle {
+    pub id: String,
+    pub fen: String,
+    pub moves: Vec<String>,
+    pub rating: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PuzzleStats {
+    pub rating: f32,
+    pub streak: u32,
+    pub best_streak: u32,
+    pub solved: u32,
+    pub failed: u32,
+    // Ids of the puzzles already attempted, so they don't come up again.
+    #[serde(default)]
+    pub seen: Vec<String>,
+}
+
+impl Default for PuzzleStats {
+    fn default() -> Self {
+        Self {
+            rating: START_RATING,
+            streak: 0,
+            best_streak: 0,
+            solved: 0,
+            failed: 0,
+            seen: Vec::new(),
+        }
+    }
+}
+
+// Where a puzzle stands after a move.
+pub enum PuzzleProgress {
+    Continue,
+    Solved,
+    Failed,
+}
+
+// The puzzles, the solver's stats and the puzzle being solved.
+pub struct PuzzleTrainer {
+    pub puzzles: Vec<Puzzle>,
+    pub stats: PuzzleStats,
+    pub current: Option<usize>,
+    // The puzzle's line as played so far, and how many of its moves that is.
+    line: Option<Position>,
+    progress: usize,
+    // The side solving the puzzle: the one not making the first move.
+    solver: PieceColor,
+    // How the last puzzle went: whether it was solved, and the rating change.
+    pub last_result: Option<(bool, f32)>,
+    // Where the stats are saved after each puzzle, if anywhere.
+    stats_path: Option<PathBuf>,
+}
+
+impl Default for PuzzleTrainer {
+    fn default() -> Self {
+        let puzzles = match fs::read_to_string(PUZZLES_FILE) {
+            Ok(contents) => parse_puzzles(&contents),
+            Err(_) => Vec::new(),
+        };
+        let path = stats_path();
+        // Stats that can't be read start over.
+        let stats = fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| ron::de::from_str(&contents).ok())
+            .unwrap_or_default();
+
+        Self {
+            puzzles,
+            stats,
+            current: None,
+            line: None,
+            progress: 0,
+            solver: PieceColor::White,
+            last_result: None,
+            stats_path: Some(path),
+        }
+    }
+}
+
+impl PuzzleTrainer {
+    // Sets up the unseen puzzle rated closest to the solver (or the closest
+    // of all once every one has been seen), returning its FEN.
+    pub fn next_puzzle(&mut self) -> Option<String> {
+        let rating = self.stats.rating;
+        let seen = &self.stats.seen;
+        let puzzles = &self.puzzles;
+        let distance = |puzzle: &Puzzle| (puzzle.rating as f32 - rating).abs() as u32;
+        let unseen = puzzles
+            .iter()
+            .enumerate()
+            .filter(|(_, puzzle)| !seen.contains(&puzzle.id))
+            .min_by_key(|(_, puzzle)| distance(puzzle));
+        let (idx, puzzle) = unseen
+            .or_else(|| puzzles.iter().enumerate().min_by_key(|(_, puzzle)| distance(puzzle)))?;
+
+        let line = parse_fen(&puzzle.fen)?;
+        self.solver = opponent(line.side_to_move);
+        self.current = Some(idx);
+        self.line = Some(line);
+        self.progress = 0;
+        self.last_result = None;
+        Some(puzzle.fen.clone())
+    }
+
+    pub fn solver(&self) -> PieceColor {
+        self.solver
+    }
+
+    // The solution's next move, and what it promotes to, when it's the
+    // opponent's to make.
+    pub fn reply(&self) -> Option<(Move, Option<PieceType>)> {
+        if self.line.as_ref()?.side_to_move == self.solver {
+            return None;
+        }
+        self.next_move()
+    }
+
+    fn next_move(&self) -> Option<(Move, Option<PieceType>)> {
+        let puzzle = &self.puzzles[self.current?];
+        let mv = puzzle.moves.get(self.progress)?;
+        Some((parse_uci(mv)?, parse_promotion(mv)))
+    }
+
+    // How many of the solution's moves have been played, or `None` once the
+    // puzzle is over.
+    pub fn followed(&self) -> Option<usize> {
+        match (self.current, self.last_result) {
+            (Some(_), None) => Some(self.progress),
+            _ => None,
+        }
+    }
+
+    // Follows a move played on the board, and what it promoted to, which
+    // for the solver has to be the solution's, or any mate on the
+    // solution's last move.
+    pub fn record_move(
+        &mut self,
+        variant: &dyn Variant,
+        mv: Move,
+        promotion: Option<PieceType>,
+    ) -> PuzzleProgress {
+        let ((expected, expected_promotion), line) = match (self.next_move(), self.line.as_mut()) {
+            (Some(expected), Some(line)) => (expected, line),
+            _ => return PuzzleProgress::Continue,
+        };
+        let solver_moved = line.side_to_move == self.solver;
+        let moves = self.puzzles[self.current.unwrap()].moves.len();
+        let last_move = self.progress + 1 >= moves;
+
+        let mut next = line.clone();
+        let effects = play_move(variant, &mut next, expected.0, expected.1, expected_promotion)
+            .unwrap_or_default();
+        // Compare where the piece ended up, so castling by moving the king
+        // onto its rook counts as well, and what a pawn became.
+        let mut after_played = line.clone();
+        let played = play_move(variant, &mut after_played, mv.0, mv.1, promotion).unwrap_or_default();
+        let same = mv.0 == expected.0
+            && played.destination == effects.destination
+            && played.promotion == effects.promotion;
+        if solver_moved && !same {
+            if !(last_move && is_mate(variant, &after_played)) {
+                self.finish(false);
+                return PuzzleProgress::Failed;
+            }
+            next = after_played;
+        }
+
+        *line = next;
+        self.progress += 1;
+        if self.progress >= moves {
+            self.finish(true);
+            return PuzzleProgress::Solved;
+        }
+        PuzzleProgress::Continue
+    }
+
+    // Updates the stats with the puzzle's result and saves them.
+    fn finish(&mut self, solved: bool) {
+        self.update_stats(solved);
+        if let Some(path) = &self.stats_path {
+            // Stats that can't be saved are kept for this session only.
+            let _ = save_stats(&self.stats, path);
+        }
+    }
+
+    fn update_stats(&mut self, solved: bool) {
+        let puzzle = &self.puzzles[self.current.unwrap()];
+        let stats = &mut self.stats;
+
+        // Elo, with the puzzle as the opponent.
+        let expected = 1. / (1. + 10f32.powf((puzzle.rating as f32 - stats.rating) / 400.));
+        let score = if solved { 1. } else { 0. };
+        let change = RATING_K * (score - expected);
+        stats.rating += change;
+
+        if solved {
+            stats.solved += 1;
+            stats.streak += 1;
+            stats.best_streak = stats.best_streak.max(stats.streak);
+        } else {
+            stats.failed += 1;
+            stats.streak = 0;
+        }
+        if !stats.seen.contains(&puzzle.id) {
+            stats.seen.push(puzzle.id.clone());
+        }
+        self.last_result = Some((solved, change));
+    }
+
+    // One line on the solver's standing, e.g. for the menu.
+    pub fn describe_stats(&self) -> String {
+        format!(
+            "Puzzle rating {:.0}, streak {} (best {})",
+            self.stats.rating, self.stats.streak, self.stats.best_streak
+        )
+    }
+}
+
+// Where the stats are saved: the user's config directory, so the same stats
+// are found whichever directory the game is started from, or the working
+// directory when there's no telling where that is.
+fn stats_path() -> PathBuf {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .or_else(|| env::var_os("APPDATA"))
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+    match config {
+        Some(dir) => dir.join(CONFIG_DIR).join(STATS_FILE),
+        None => PathBuf::from(STATS_FILE),
+    }
+}
+
+fn save_stats(stats: &PuzzleStats, path: &Path) -> Result<(), String> {
+    let contents = ron::ser::to_string_pretty(stats, ron::ser::PrettyConfig::default())
+        .map_err(|err| err.to_string())?;
+    fs::create_dir_all(path.parent().unwrap_or_else(|| Path::new(".")))
+        .and_then(|_| fs::write(path, contents))
+        .map_err(|err| err.to_string())
+}
+
+// Whether the side to move is mated: in check, and still in check after
+// each of its moves.
+fn is_mate(variant: &dyn Variant, position: &Position) -> bool {
+    let side = position.side_to_move;
+    position.in_check(side)
+        && variant.legal_moves(position).into_iter().all(|(from, to)| {
+            let mut next = position.clone();
+            variant.make_move(&mut next, from, to);
+            next.in_check(side)
+        })
+}
+
+// Reads puzzles from CSV. A header row picks out the columns by name;
+// without one they're taken to be in Lichess's order. A header missing one
+// of the columns gives no puzzles, and lines that aren't puzzles are
+// skipped.
+fn parse_puzzles(contents: &str) -> Vec<Puzzle> {
+    const COLUMNS: [&str; 4] = ["PuzzleId", "FEN", "Moves", "Rating"];
+    let mut lines = contents.lines().filter(|line| !line.trim().is_empty()).peekable();
+    let mut columns = [0, 1, 2, 3];
+
+    if let Some(header) = lines.peek() {
+        let names: Vec<&str> = header.split(',').map(|name| name.trim()).collect();
+        if names.contains(&"FEN") {
+            for (column, name) in columns.iter_mut().zip(&COLUMNS) {
+                match names.iter().position(|header| header == name) {
+                    Some(idx) => *column = idx,
+                    None => return Vec::new(),
+                }
+            }
+            lines.next();
+        }
+    }
+
+    let mut puzzles = Vec::new();
+    for line in lines {
+        let fields: Vec<&str> = line.split(',').map(|field| field.trim()).collect();
+        let field = |idx: usize| fields.get(columns[idx]).copied();
+        let puzzle = (|| {
+            let fen = field(1)?;
+            parse_fen(fen)?;
+            Some(Puzzle {
+                id: field(0)?.to_string(),
+                fen: fen.to_string(),
+                moves: field(2)?.split_whitespace().map(|mv| mv.to_string()).collect(),
+                rating: field(3)?.parse().ok()?,
+            })
+        })();
+        if let Some(puzzle) = puzzle.filter(|puzzle| puzzle.moves.len() >= 2) {
+            puzzles.push(puzzle);
+        }
+    }
+    puzzles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variant::VariantKind;
+
+    // Black's king steps aside, then only a knight promotion will do.
+    const FEN: &str = "8/4P1k1/8/8/8/8/8/K7 b - - 0 1";
+
+    fn mv(uci: &str) -> Move {
+        parse_uci(uci).unwrap()
+    }
+
+    fn trainer(fen: &str, moves: &str) -> PuzzleTrainer {
+        let mut trainer = PuzzleTrainer {
+            puzzles: parse_puzzles(&format!("PuzzleId,FEN,Moves,Rating\ntest,{},{},1500", fen, moves)),
+            stats: PuzzleStats::default(),
+            current: None,
+            line: None,
+            progress: 0,
+            solver: PieceColor::White,
+            last_result: None,
+            stats_path: None,
+        };
+        assert_eq!(trainer.next_puzzle(), Some(fen.to_string()));
+        trainer
+    }
+
+    #[test]
+    fn puzzle_files() {
+        let puzzles = parse_puzzles(&format!(
+            "PuzzleId,Rating,FEN,Moves\n\
+             a,1200,{fen},g7f7 e7e8n\n\
+             b,1300,not a fen,g7f7 e7e8n\n\
+             c,1400,{fen},g7f7\n\
+             d,high,{fen},g7f7 e7e8n",
+            fen = FEN
+        ));
+        assert_eq!(puzzles.len(), 1);
+        assert_eq!(puzzles[0].id, "a");
+        assert_eq!(puzzles[0].rating, 1200);
+        assert_eq!(puzzles[0].moves, vec!["g7f7", "e7e8n"]);
+
+        // Without a header the columns are Lichess's.
+        let puzzles = parse_puzzles(&format!("x,{},g7f7 e7e8n,1600,80", FEN));
+        assert_eq!(puzzles.len(), 1);
+        assert_eq!(puzzles[0].rating, 1600);
+
+        assert!(parse_puzzles(&format!("PuzzleId,FEN,Moves\nx,{},g7f7 e7e8n", FEN)).is_empty());
+    }
+
+    #[test]
+    fn solving() {
+        let variant = VariantKind::Standard.rules();
+        let mut trainer = trainer(FEN, "g7f7 e7e8n");
+        assert!(trainer.solver() == PieceColor::White);
+        assert!(trainer.reply() == Some((mv("g7f7"), None)));
+        assert!(matches!(trainer.record_move(variant, mv("g7f7"), None), PuzzleProgress::Continue));
+        assert!(trainer.reply().is_none());
+
+        let progress = trainer.record_move(variant, mv("e7e8"), Some(PieceType::Knight));
+        assert!(matches!(progress, PuzzleProgress::Solved));
+        assert_eq!(trainer.stats.solved, 1);
+        assert_eq!(trainer.stats.streak, 1);
+        // Solving a puzzle rated the same as the solver gains half of K.
+        assert!((trainer.stats.rating - (START_RATING + RATING_K / 2.)).abs() < 0.01);
+        assert_eq!(trainer.stats.seen, vec!["test"]);
+    }
+
+    #[test]
+    fn wrong_promotion() {
+        let variant = VariantKind::Standard.rules();
+        let mut trainer = trainer(FEN, "g7f7 e7e8n");
+        trainer.record_move(variant, mv("g7f7"), None);
+
+        // A queen isn't the knight the solution needs, and doesn't mate.
+        let progress = trainer.record_move(variant, mv("e7e8"), Some(PieceType::Queen));
+        assert!(matches!(progress, PuzzleProgress::Failed));
+        assert_eq!(trainer.stats.failed, 1);
+        assert_eq!(trainer.stats.streak, 0);
+        assert!((trainer.stats.rating - (START_RATING - RATING_K / 2.)).abs() < 0.01);
+        assert!(trainer.followed().is_none());
+    }
+
+    #[test]
+    fn underpromoting_reply() {
+        // Here the opponent is the one promoting to a knight.
+        let trainer = trainer("8/4P1k1/8/8/8/8/8/K7 w - - 0 1", "e7e8n g7f7");
+        assert!(trainer.solver() == PieceColor::Black);
+        assert!(trainer.reply() == Some((mv("e7e8n"), Some(PieceType::Knight))));
+    }
+}
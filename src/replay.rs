@@ -0,0 +1,69 @@
+use crate::{
+    diagram::{Canvas, Diagram, DiagramOptions},
+    history::MoveHistory,
+    theme::Theme,
+};
+use std::{fs, fs::File, io::BufWriter};
+
+// Where the game-over screen saves the game's replay.
+pub const GIF_FILE: &str = "last_game.gif";
+pub const FRAMES_DIR: &str = "last_game_frames";
+
+// How long each position is shown in the GIF, in hundredths of a second.
+const FRAME_DELAY: u16 = 100;
+// The final position is held this many times as long before looping.
+const LAST_FRAME_HOLD: u16 = 4;
+
+// A diagram of every position of the game, from the start, each with the
+// move leading to it highlighted.
+pub fn replay_frames(history: &MoveHistory, theme: &Theme) -> Vec<Canvas> {
+    history
+        .positions()
+        .iter()
+        .enumerate()
+        .map(|(ply, position)| {
+            let options = DiagramOptions {
+                last_move: ply.checked_sub(1).map(|idx| history.moves[idx].mv),
+                ..Default::default()
+            };
+            Diagram::new(position, theme, &options).to_canvas()
+        })
+        .collect()
+}
+
+// Writes the frames as a looping animated GIF.
+pub fn save_gif(frames: &mut [Canvas], path: &str) -> Result<(), String> {
+    let (width, height) = match frames.first() {
+        Some(frame) => (frame.width as u16, frame.height as u16),
+        None => return Err("no positions to save".to_string()),
+    };
+    let file = File::create(path).map_err(|err| err.to_string())?;
+    let mut encoder = gif::Encoder::new(BufWriter::new(file), width, height, &[])
+        .map_err(|err| err.to_string())?;
+    encoder.set_repeat(gif::Repeat::Infinite).map_err(|err| err.to_string())?;
+
+    let count = frames.len();
+    for (idx, canvas) in frames.iter_mut().enumerate() {
+        let mut frame = gif::Frame::from_rgba_speed(width, height, &mut canvas.pixels, 10);
+        frame.delay = if idx + 1 == count { FRAME_DELAY * LAST_FRAME_HOLD } else { FRAME_DELAY };
+        encoder.write_frame(&frame).map_err(|err| err.to_string())?;
+    }
+    Ok(())
+}
+
+// Writes the frames as numbered PNG files in `dir`, clearing out frames
+// left there by a longer game.
+pub fn save_frames(frames: &[Canvas], dir: &str) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+    let entries = fs::read_dir(dir).map_err(|err| err.to_string())?;
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with("ply_") && name.ends_with(".png") {
+            fs::remove_file(entry.path()).map_err(|err| err.to_string())?;
+        }
+    }
+    for (ply, frame) in frames.iter().enumerate() {
+        frame.save_png(&format!("{}/ply_{:03}.png", dir, ply))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,573 @@
+use crate::{
+    ai::{search, search_moves, Move},
+    game::*,
+    history::{Annotation, MoveHistory},
+    notation::{to_fen, to_san},
+    pieces::PieceColor,
+    position::Position,
+    replay::{replay_frames, save_frames, save_gif, FRAMES_DIR, GIF_FILE},
+    theme::Themes,
+    ui::{despawn_screen, spawn_button, ButtonMaterials},
+    variant::Variant,
+};
+use bevy::prelude::*;
+use std::{
+    fs,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+};
+
+// Plies searched for each position of the game under review.
+const REVIEW_DEPTH: u32 = 3;
+// Evaluations are capped at this many centipawns, so once a game is won a
+// move that wins a little more slowly doesn't count as a blunder.
+const EVAL_CAP: i32 = 1000;
+// Where "Save PGN" writes the reviewed game.
+const PGN_FILE: &str = "last_game.pgn";
+
+pub struct ReviewPlugin;
+impl Plugin for ReviewPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<GameReview>()
+            .on_state_enter(STATE_STAGE, AppState::GameOver, start_review.system())
+            .on_state_update(STATE_STAGE, AppState::GameOver, receive_review.system())
+            .on_state_update(STATE_STAGE, AppState::GameOver, review_panel_update.system())
+            .on_state_update(STATE_STAGE, AppState::GameOver, review_buttons.system())
+            .on_state_exit(STATE_STAGE, AppState::GameOver, stop_review.system())
+            .on_state_exit(STATE_STAGE, AppState::GameOver, despawn_screen::<ReviewPanel>.system());
+    }
+}
+
+// How good a move was next to the best the search found.
+#[derive(Clone, Copy, PartialEq)]
+pub enum MoveClass {
+    // The best move, when every other loses enough to be a mistake.
+    Only,
+    Best,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl MoveClass {
+    // Classifies a move losing `loss` centipawns on the best move.
+    fn from_loss(loss: i32) -> Self {
+        match loss {
+            0 => MoveClass::Best,
+            1..=49 => MoveClass::Good,
+            50..=99 => MoveClass::Inaccuracy,
+            100..=299 => MoveClass::Mistake,
+            _ => MoveClass::Blunder,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MoveClass::Only => "only move",
+            MoveClass::Best => "best",
+            MoveClass::Good => "good",
+            MoveClass::Inaccuracy => "inaccuracy",
+            MoveClass::Mistake => "mistake",
+            MoveClass::Blunder => "blunder",
+        }
+    }
+
+    // The move's suffix in PGN.
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            MoveClass::Only => "!",
+            MoveClass::Best => "",
+            MoveClass::Good => "",
+            MoveClass::Inaccuracy => "?!",
+            MoveClass::Mistake => "?",
+            MoveClass::Blunder => "??",
+        }
+    }
+}
+
+pub struct MoveReview {
+    // Centipawns lost on the best move, from the mover's point of view.
+    pub loss: i32,
+    pub class: MoveClass,
+    // The search's choice in SAN, when the move played wasn't it.
+    pub best: Option<String>,
+}
+
+impl MoveReview {
+    fn annotation(&self) -> Annotation {
+        let comment = match (self.class, &self.best) {
+            (MoveClass::Inaccuracy, Some(best))
+            | (MoveClass::Mistake, Some(best))
+            | (MoveClass::Blunder, Some(best)) => {
+                let mut class = self.class.name().to_string();
+                class[..1].make_ascii_uppercase();
+                Some(format!("{}. {} was best.", class, best))
+            }
+            _ => None,
+        };
+        Annotation {
+            suffix: self.class.suffix(),
+            comment,
+        }
+    }
+}
+
+// Analysis of the game just finished, worked out move by move in the
+// background while the game-over screen is up.
+#[derive(Default)]
+pub struct GameReview {
+    // Evaluation of each position of the game in turn, in centipawns from
+    // white's point of view, and the review of the move out of each.
+    pub evals: Vec<i32>,
+    pub moves: Vec<MoveReview>,
+    // Number of positions in the game.
+    pub positions: usize,
+    // How saving the game last went, shown under the save buttons.
+    pub saved: Option<String>,
+    cancel: Arc<AtomicBool>,
+    updates: Option<Mutex<mpsc::Receiver<ReviewUpdate>>>,
+    // The replay being saved in the background, which sends back how that
+    // went.
+    saving: Option<Mutex<mpsc::Receiver<String>>>,
+}
+
+// A position's evaluation and the review of the move out of it, as the
+// review sends them back.
+type ReviewUpdate = (i32, Option<MoveReview>);
+
+impl GameReview {
+    fn start(positions: Vec<Position>, variant: &'static dyn Variant) -> Self {
+        let (sender, updates) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let review = Self {
+            evals: Vec::new(),
+            moves: Vec::new(),
+            positions: positions.len(),
+            saved: None,
+            cancel: cancel.clone(),
+            updates: Some(Mutex::new(updates)),
+            saving: None,
+        };
+
+        thread::spawn(move || {
+            let mut previous: Option<(i32, Option<Position>, String, bool)> = None;
+            for (idx, position) in positions.iter().enumerate() {
+                if cancel.load(Ordering::SeqCst) {
+                    return;
+                }
+                let (eval, best, only) = evaluate(position, variant);
+
+                // Now the move into this position can be judged against the
+                // best move from the last.
+                let review = previous.map(|(previous_eval, best_after, best_san, only)| {
+                    let mover = positions[idx - 1].side_to_move;
+                    let found_best = best_after.is_some_and(|best_after| {
+                        to_fen(&best_after, false) == to_fen(position, false)
+                    });
+                    let loss = if found_best {
+                        0
+                    } else {
+                        let loss = match mover {
+                            PieceColor::White => previous_eval - eval,
+                            PieceColor::Black => eval - previous_eval,
+                        };
+                        // Never call a move the best that the search didn't pick.
+                        loss.max(1)
+                    };
+                    MoveReview {
+                        loss,
+                        class: if found_best && only {
+                            MoveClass::Only
+                        } else {
+                            MoveClass::from_loss(loss)
+                        },
+                        best: if found_best { None } else { Some(best_san) },
+                    }
+                });
+
+                previous = Some(match best {
+                    Some(best) => {
+                        let mut best_after = position.clone();
+                        variant.make_move(&mut best_after, best.0, best.1);
+                        (eval, Some(best_after), to_san(position, best), only)
+                    }
+                    None => (eval, None, String::new(), false),
+                });
+                if sender.send((eval, review)).is_err() {
+                    return;
+                }
+            }
+        });
+
+        review
+    }
+
+    fn finished(&self) -> bool {
+        self.evals.len() == self.positions
+    }
+
+    // Inaccuracies, mistakes, blunders and average centipawn loss of
+    // `color`'s moves so far, e.g. "White: 2 inaccuracies, ...".
+    fn summary(&self, history: &MoveHistory, color: PieceColor) -> String {
+        let mut counts = [0; 3];
+        let (mut total_loss, mut moves) = (0, 0);
+        for (idx, review) in self.moves.iter().enumerate() {
+            let mover = history.position(idx).map(|position| position.side_to_move);
+            if mover != Some(color) {
+                continue;
+            }
+            match review.class {
+                MoveClass::Inaccuracy => counts[0] += 1,
+                MoveClass::Mistake => counts[1] += 1,
+                MoveClass::Blunder => counts[2] += 1,
+                MoveClass::Only | MoveClass::Best | MoveClass::Good => {}
+            }
+            total_loss += review.loss;
+            moves += 1;
+        }
+
+        let plural = |count: i32, one: &str, many: &str| {
+            format!("{} {}", count, if count == 1 { one } else { many })
+        };
+        format!(
+            "{}: {}, {}, {}, average loss {}",
+            color.name(),
+            plural(counts[0], "inaccuracy", "inaccuracies"),
+            plural(counts[1], "mistake", "mistakes"),
+            plural(counts[2], "blunder", "blunders"),
+            if moves > 0 { total_loss / moves } else { 0 },
+        )
+    }
+}
+
+// The evaluation of `position` in centipawns from white's point of view,
+// capped at `EVAL_CAP`, the best move in it, and whether that's the only
+// move: every other loses at least a mistake's worth on it.
+fn evaluate(position: &Position, variant: &dyn Variant) -> (i32, Option<Move>, bool) {
+    let white_view = |score: i32| match position.side_to_move {
+        PieceColor::White => score.clamp(-EVAL_CAP, EVAL_CAP),
+        PieceColor::Black => -score.clamp(-EVAL_CAP, EVAL_CAP),
+    };
+
+    if let Some(result) = variant.outcome(position) {
+        let eval = match result.winner {
+            Some(PieceColor::White) => EVAL_CAP,
+            Some(PieceColor::Black) => -EVAL_CAP,
+            None => 0,
+        };
+        return (eval, None, false);
+    }
+    match search(position, variant, REVIEW_DEPTH) {
+        Some((mv, score)) => {
+            let others = variant.legal_moves(position).into_iter().filter(|other| *other != mv);
+            let only = search_moves(position, variant, REVIEW_DEPTH, others.collect())
+                .is_some_and(|(_, second)| {
+                    let cap = |score: i32| score.clamp(-EVAL_CAP, EVAL_CAP);
+                    matches!(
+                        MoveClass::from_loss(cap(score) - cap(second)),
+                        MoveClass::Mistake | MoveClass::Blunder
+                    )
+                });
+            (white_view(score), Some(mv), only)
+        }
+        None => (white_view(variant.evaluate(position, position.side_to_move)), None, false),
+    }
+}
+
+// Reviews the game just finished. Puzzles are too short to need it.
+fn start_review(
+    commands: &mut Commands,
+    asset_server: Res<AssetServer>,
+    setup: Res<GameSetup>,
+    history: Res<MoveHistory>,
+    button_materials: Res<ButtonMaterials>,
+    mut review: ResMut<GameReview>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+) {
+    if setup.mode == GameMode::Puzzles || history.moves.is_empty() {
+        return;
+    }
+    *review = GameReview::start(history.positions(), setup.variant.rules());
+    spawn_review_panel(
+        commands,
+        &asset_server,
+        &button_materials,
+        &mut color_materials,
+        history.moves.len() + 1,
+    );
+}
+
+fn stop_review(mut review: ResMut<GameReview>) {
+    review.cancel.store(true, Ordering::SeqCst);
+    *review = GameReview::default();
+}
+
+// Takes in the moves reviewed since last time, and how saving the replay
+// went once it's done.
+fn receive_review(mut review: ResMut<GameReview>) {
+    let saved = review.saving.as_ref().and_then(|saving| saving.lock().unwrap().try_recv().ok());
+    if saved.is_some() {
+        review.saved = saved;
+        review.saving = None;
+    }
+
+    let received: Vec<ReviewUpdate> = match &review.updates {
+        Some(updates) => updates.lock().unwrap().try_iter().collect(),
+        None => return,
+    };
+    for (eval, move_review) in received {
+        review.evals.push(eval);
+        review.moves.extend(move_review);
+    }
+}
+
+// Root node of the review panel.
+struct ReviewPanel;
+// Shows the position this many moves into the game when clicked; its child
+// fills it from the bottom as far as white is ahead there.
+struct EvalGraphBar(usize);
+struct EvalGraphFill(usize);
+// Text about the move shown on the board.
+struct ReviewMoveText;
+struct ReviewSummaryText;
+// Where the game was saved, or why it couldn't be.
+struct ReviewSaveText;
+struct SavePgnButton;
+struct SaveGifButton;
+struct SaveFramesButton;
+
+// The panel along the bottom of the game-over screen: the evaluation graph,
+// a line on the move shown and a summary for each side.
+fn spawn_review_panel(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    button_materials: &ButtonMaterials,
+    color_materials: &mut Assets<ColorMaterial>,
+    positions: usize,
+) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let text = |font_size: f32| Text {
+        value: String::new(),
+        font: font.clone(),
+        style: TextStyle {
+            font_size,
+            color: Color::rgb(0.9, 0.9, 0.9),
+            ..Default::default()
+        },
+    };
+    let fill_material = color_materials.add(Color::rgb(0.9, 0.9, 0.9).into());
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.),
+                    right: Val::Px(10.),
+                    bottom: Val::Px(10.),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: color_materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .with(ReviewPanel)
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle {
+                    text: text(20.0),
+                    ..Default::default()
+                })
+                .with(ReviewSummaryText);
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(100.), Val::Px(80.)),
+                        ..Default::default()
+                    },
+                    material: color_materials.add(Color::rgb(0.15, 0.15, 0.15).into()),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for ply in 0..positions {
+                        parent
+                            .spawn(ButtonBundle {
+                                style: Style {
+                                    size: Size::new(
+                                        Val::Percent(100. / positions as f32),
+                                        Val::Percent(100.),
+                                    ),
+                                    flex_direction: FlexDirection::Column,
+                                    ..Default::default()
+                                },
+                                material: button_materials.normal.clone(),
+                                ..Default::default()
+                            })
+                            .with(EvalGraphBar(ply))
+                            .with_children(|parent| {
+                                parent
+                                    .spawn(NodeBundle {
+                                        style: Style {
+                                            size: Size::new(Val::Percent(100.), Val::Percent(50.)),
+                                            ..Default::default()
+                                        },
+                                        material: fill_material.clone(),
+                                        ..Default::default()
+                                    })
+                                    .with(EvalGraphFill(ply));
+                            });
+                    }
+                });
+            parent
+                .spawn(TextBundle {
+                    text: text(22.0),
+                    ..Default::default()
+                })
+                .with(ReviewMoveText);
+            parent
+                .spawn(TextBundle {
+                    text: text(20.0),
+                    ..Default::default()
+                })
+                .with(ReviewSaveText);
+            spawn_button(parent, "Save PGN", font.clone(), button_materials).with(SavePgnButton);
+            spawn_button(parent, "Save GIF", font.clone(), button_materials).with(SaveGifButton);
+            spawn_button(parent, "Save frames", font.clone(), button_materials)
+                .with(SaveFramesButton);
+        });
+}
+
+// Keeps the graph and texts up with the review and the move shown.
+fn review_panel_update(
+    review: Res<GameReview>,
+    history: Res<MoveHistory>,
+    mut fill_query: Query<(&mut Style, &EvalGraphFill)>,
+    mut text_queries: QuerySet<(
+        Query<&mut Text, With<ReviewMoveText>>,
+        Query<&mut Text, With<ReviewSummaryText>>,
+        Query<&mut Text, With<ReviewSaveText>>,
+    )>,
+) {
+    for (mut style, fill) in fill_query.iter_mut() {
+        if let Some(eval) = review.evals.get(fill.0) {
+            let share = (*eval + EVAL_CAP) as f32 / (2 * EVAL_CAP) as f32;
+            style.size.height = Val::Percent(share * 100.);
+        }
+    }
+
+    let ply = history.shown.unwrap_or_else(|| history.moves.len());
+    let move_line = match (ply.checked_sub(1).and_then(|idx| history.moves.get(idx)), ply) {
+        (_, 0) => "Start of the game".to_string(),
+        (Some(played), _) => {
+            let mut line = format!("{} {}", history.move_number(ply), played.san);
+            if let Some(move_review) = review.moves.get(ply - 1) {
+                line.push_str(move_review.class.suffix());
+                line.push_str(&format!(" {}", move_review.class.name()));
+                if let Some(best) = &move_review.best {
+                    line.push_str(&format!(", {} was best", best));
+                }
+            }
+            if let Some(eval) = review.evals.get(ply) {
+                line.push_str(&format!(" ({:+.2})", *eval as f32 / 100.));
+            }
+            line
+        }
+        (None, _) => String::new(),
+    };
+    for mut text in text_queries.q0_mut().iter_mut() {
+        if text.value != move_line {
+            text.value = move_line.clone();
+        }
+    }
+
+    let summary = if review.finished() {
+        format!(
+            "{}    {}",
+            review.summary(&history, PieceColor::White),
+            review.summary(&history, PieceColor::Black)
+        )
+    } else {
+        format!("Reviewing the game... {}/{}", review.evals.len(), review.positions)
+    };
+    for mut text in text_queries.q1_mut().iter_mut() {
+        if text.value != summary {
+            text.value = summary.clone();
+        }
+    }
+
+    let saved = review.saved.clone().unwrap_or_default();
+    for mut text in text_queries.q2_mut().iter_mut() {
+        if text.value != saved {
+            text.value = saved.clone();
+        }
+    }
+}
+
+// Clicking the graph shows that position on the board; "Save PGN" writes the
+// game out with the review's annotations, and "Save GIF" and "Save frames"
+// write a diagram of each of its positions.
+fn review_buttons(
+    setup: Res<GameSetup>,
+    result: Res<GameResult>,
+    mut review: ResMut<GameReview>,
+    themes: Res<Themes>,
+    mut history: ResMut<MoveHistory>,
+    bar_query: Query<(&Interaction, &EvalGraphBar), Mutated<Interaction>>,
+    save_query: Query<&Interaction, (Mutated<Interaction>, With<SavePgnButton>)>,
+    gif_query: Query<&Interaction, (Mutated<Interaction>, With<SaveGifButton>)>,
+    frames_query: Query<&Interaction, (Mutated<Interaction>, With<SaveFramesButton>)>,
+) {
+    for (interaction, bar) in bar_query.iter() {
+        if *interaction == Interaction::Clicked {
+            history.shown = if bar.0 == history.moves.len() { None } else { Some(bar.0) };
+        }
+    }
+
+    if save_query.iter().any(|interaction| *interaction == Interaction::Clicked) {
+        let annotations: Vec<Annotation> =
+            review.moves.iter().map(MoveReview::annotation).collect();
+        let pgn = history.to_pgn(&setup, &result, &annotations);
+        review.saved = Some(match fs::write(PGN_FILE, pgn) {
+            Ok(()) => format!("Saved the game to {}", PGN_FILE),
+            Err(err) => format!("Couldn't save the game to {}: {}", PGN_FILE, err),
+        });
+    }
+
+    let clicked = |interaction: &Interaction| *interaction == Interaction::Clicked;
+    let gif = gif_query.iter().any(clicked);
+    let pngs = frames_query.iter().any(clicked);
+    if (gif || pngs) && review.saving.is_none() {
+        // Drawing and encoding every position takes a while, so it's done
+        // off the main thread.
+        let (sender, saving) = mpsc::channel();
+        let history = history.clone();
+        let theme = themes.current().clone();
+        thread::spawn(move || {
+            let mut frames = replay_frames(&history, &theme);
+            let mut results = Vec::new();
+            // The PNGs go first: encoding the GIF changes the frames' pixels.
+            if pngs {
+                results.push(match save_frames(&frames, FRAMES_DIR) {
+                    Ok(()) => format!("Saved {} frames to {}", frames.len(), FRAMES_DIR),
+                    Err(err) => format!("Couldn't save the frames to {}: {}", FRAMES_DIR, err),
+                });
+            }
+            if gif {
+                results.push(match save_gif(&mut frames, GIF_FILE) {
+                    Ok(()) => format!("Saved the replay to {}", GIF_FILE),
+                    Err(err) => format!("Couldn't save the replay to {}: {}", GIF_FILE, err),
+                });
+            }
+            let _ = sender.send(results.join(", "));
+        });
+        review.saving = Some(Mutex::new(saving));
+        review.saved = Some("Saving the replay...".to_string());
+    }
+}
@@ -0,0 +1,456 @@
+use crate::{
+    game::*,
+    moves::*,
+    pieces::PieceColor,
+    ui::{spawn_button, ButtonMaterials},
+};
+use bevy::{
+    audio::{Audio, AudioSource},
+    prelude::*,
+    window::CursorMoved,
+};
+use std::{collections::HashMap, fs};
+
+const SOUNDS_DIR: &str = "assets/sounds";
+
+// Seconds left on a clock when it warns that time is running out.
+const LOW_TIME: f32 = 10.;
+
+// Volume levels a sound can be mixed at; each effect is mixed once per
+// level it's played at.
+const VOLUME_STEPS: f32 = 20.;
+
+pub struct SoundPlugin;
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // Runs without Bevy's audio, such as headless ones, still pick their
+        // sounds but play them nowhere.
+        if app.resources().contains::<Audio>() {
+            app.add_resource(SoundBackend::Audio(SoundLibrary::load()))
+                .add_system(play_sounds.system());
+        } else {
+            app.add_resource(SoundBackend::Silent)
+                .add_system(skip_sounds.system());
+        }
+        app.init_resource::<SoundSettings>()
+            .add_event::<PlaySound>()
+            // Game-ending events may come just as the game moves on to
+            // `GameOver`, so these listen whatever the state.
+            .add_system(move_sounds.system())
+            .add_system(low_time_sounds.system())
+            .add_system(volume_slider.system())
+            .add_system(mute_button.system())
+            .add_system(sound_settings_update.system());
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SoundEffect {
+    Move,
+    Capture,
+    Castle,
+    Check,
+    LowTime,
+    GameOver,
+}
+
+impl SoundEffect {
+    const ALL: [SoundEffect; 6] = [
+        SoundEffect::Move,
+        SoundEffect::Capture,
+        SoundEffect::Castle,
+        SoundEffect::Check,
+        SoundEffect::LowTime,
+        SoundEffect::GameOver,
+    ];
+
+    // Its file in `SOUNDS_DIR`.
+    fn file_name(&self) -> &'static str {
+        match self {
+            SoundEffect::Move => "move.wav",
+            SoundEffect::Capture => "capture.wav",
+            SoundEffect::Castle => "castle.wav",
+            SoundEffect::Check => "check.wav",
+            SoundEffect::LowTime => "low_time.wav",
+            SoundEffect::GameOver => "game_over.wav",
+        }
+    }
+}
+
+// Asks for a sound effect to be played.
+pub struct PlaySound(pub SoundEffect);
+
+// Set from the settings menu.
+pub struct SoundSettings {
+    // From 0 (silent) to 1.
+    pub volume: f32,
+    pub muted: bool,
+}
+
+impl Default for SoundSettings {
+    fn default() -> Self {
+        Self {
+            volume: 0.7,
+            muted: false,
+        }
+    }
+}
+
+impl SoundSettings {
+    fn level(&self) -> f32 {
+        if self.muted {
+            0.
+        } else {
+            self.volume
+        }
+    }
+}
+
+// Where sound effects are played, picked once when the plugin is built.
+pub enum SoundBackend {
+    // Out loud, through Bevy's audio.
+    Audio(SoundLibrary),
+    // Nowhere. Runs without Bevy's audio, such as headless ones, get this.
+    Silent,
+}
+
+// A sound effect's samples, kept to be mixed at other volumes: a 16-bit PCM
+// WAV file, split into everything up to its samples and the samples.
+struct Clip {
+    header: Vec<u8>,
+    samples: Vec<i16>,
+}
+
+impl Clip {
+    // Reads a WAV file whose samples are its last chunk, as written by most
+    // tools; anything else isn't understood.
+    fn parse(bytes: &[u8]) -> Option<Clip> {
+        if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+            return None;
+        }
+        let read_u16 = |at: usize| Some(u16::from_le_bytes([*bytes.get(at)?, *bytes.get(at + 1)?]));
+        let read_u32 = |at: usize| Some(read_u16(at)? as u32 | (read_u16(at + 2)? as u32) << 16);
+
+        let mut at = 12;
+        let mut pcm16 = false;
+        while at + 8 <= bytes.len() {
+            let id = &bytes[at..at + 4];
+            let size = read_u32(at + 4)? as usize;
+            let body = at + 8;
+            if id == b"fmt " {
+                // Format 1 is PCM.
+                pcm16 = read_u16(body)? == 1 && read_u16(body + 14)? == 16;
+            } else if id == b"data" {
+                if !pcm16 {
+                    return None;
+                }
+                let end = (body + size).min(bytes.len());
+                let samples = bytes[body..end]
+                    .chunks_exact(2)
+                    .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+                    .collect();
+                return Some(Clip {
+                    header: bytes[..body].to_vec(),
+                    samples,
+                });
+            }
+            // Chunks are padded to an even length.
+            at = body + size + size % 2;
+        }
+        None
+    }
+
+    // The clip as a WAV file, at `volume` times its own.
+    fn mix(&self, volume: f32) -> AudioSource {
+        let mut bytes = self.header.clone();
+        for sample in &self.samples {
+            let scaled = (*sample as f32 * volume) as i16;
+            bytes.extend_from_slice(&scaled.to_le_bytes());
+        }
+        AudioSource {
+            bytes: bytes.into(),
+        }
+    }
+}
+
+// The sound effects in `SOUNDS_DIR`, and their mixes so far.
+pub struct SoundLibrary {
+    clips: HashMap<SoundEffect, Clip>,
+    mixed: HashMap<(SoundEffect, u8), Handle<AudioSource>>,
+}
+
+impl SoundLibrary {
+    fn load() -> Self {
+        let mut clips = HashMap::new();
+        for effect in SoundEffect::ALL.iter() {
+            let path = format!("{}/{}", SOUNDS_DIR, effect.file_name());
+            // An effect whose file can't be read is left silent.
+            if let Some(clip) = fs::read(&path).ok().and_then(|bytes| Clip::parse(&bytes)) {
+                clips.insert(*effect, clip);
+            }
+        }
+        Self {
+            clips,
+            mixed: HashMap::new(),
+        }
+    }
+
+    // `effect` at `volume`, mixed the first time it's asked for.
+    fn source(
+        &mut self,
+        effect: SoundEffect,
+        volume: f32,
+        sources: &mut Assets<AudioSource>,
+    ) -> Option<Handle<AudioSource>> {
+        let step = (volume * VOLUME_STEPS).round() as u8;
+        if step == 0 {
+            return None;
+        }
+        let clip = self.clips.get(&effect)?;
+        let handle = self
+            .mixed
+            .entry((effect, step))
+            .or_insert_with(|| sources.add(clip.mix(step as f32 / VOLUME_STEPS)));
+        Some(handle.clone())
+    }
+}
+
+// One sound for each move played, the most important thing about it: the
+// game ending, then check, a capture, castling or just the move.
+fn move_sounds(
+    mut applied_reader: Local<EventReader<MoveApplied>>,
+    applied_events: Res<Events<MoveApplied>>,
+    mut captured_reader: Local<EventReader<PieceCaptured>>,
+    captured_events: Res<Events<PieceCaptured>>,
+    mut check_reader: Local<EventReader<Check>>,
+    check_events: Res<Events<Check>>,
+    mut ended_reader: Local<EventReader<GameEnded>>,
+    ended_events: Res<Events<GameEnded>>,
+    mut play_events: ResMut<Events<PlaySound>>,
+) {
+    let applied = applied_reader.iter(&applied_events).last();
+    let captured = captured_reader.iter(&captured_events).next().is_some();
+    let check = check_reader.iter(&check_events).next().is_some();
+    let ended = ended_reader.iter(&ended_events).next().is_some();
+
+    let effect = if ended {
+        SoundEffect::GameOver
+    } else if check {
+        SoundEffect::Check
+    } else if captured {
+        SoundEffect::Capture
+    } else if let Some(applied) = applied {
+        match applied.effects.castling_rook {
+            Some(_) => SoundEffect::Castle,
+            None => SoundEffect::Move,
+        }
+    } else {
+        return;
+    };
+    play_events.send(PlaySound(effect));
+}
+
+// Warns once as each side's clock runs low.
+fn low_time_sounds(
+    clocks: Res<Clocks>,
+    mut low: Local<[bool; 2]>,
+    mut play_events: ResMut<Events<PlaySound>>,
+) {
+    for (idx, color) in [PieceColor::White, PieceColor::Black].iter().enumerate() {
+        let now_low = clocks.running && clocks.remaining(*color) < LOW_TIME;
+        if now_low && !low[idx] {
+            play_events.send(PlaySound(SoundEffect::LowTime));
+        }
+        low[idx] = now_low;
+    }
+}
+
+fn play_sounds(
+    mut play_reader: Local<EventReader<PlaySound>>,
+    play_events: Res<Events<PlaySound>>,
+    settings: Res<SoundSettings>,
+    mut backend: ResMut<SoundBackend>,
+    audio: Res<Audio>,
+    mut sources: ResMut<Assets<AudioSource>>,
+) {
+    let library = match &mut *backend {
+        SoundBackend::Audio(library) => library,
+        SoundBackend::Silent => return,
+    };
+    for play in play_reader.iter(&play_events) {
+        if let Some(source) = library.source(play.0, settings.level(), &mut sources) {
+            audio.play(source);
+        }
+    }
+}
+
+// Takes in the sounds asked for when there's nothing to play them on.
+fn skip_sounds(mut play_reader: Local<EventReader<PlaySound>>, play_events: Res<Events<PlaySound>>) {
+    for _ in play_reader.iter(&play_events) {}
+}
+
+// The volume slider in the settings menu; clicking or dragging along it
+// sets the volume.
+pub struct VolumeSlider;
+struct VolumeFill;
+pub struct MuteButton;
+
+fn mute_label(settings: &SoundSettings) -> &'static str {
+    if settings.muted {
+        "Unmute"
+    } else {
+        "Mute"
+    }
+}
+
+// Adds the sound settings to the settings menu.
+pub fn spawn_sound_settings(
+    parent: &mut ChildBuilder,
+    font: Handle<Font>,
+    settings: &SoundSettings,
+    button_materials: &ButtonMaterials,
+    color_materials: &mut Assets<ColorMaterial>,
+) {
+    parent.spawn(TextBundle {
+        text: Text {
+            value: "Sound".to_string(),
+            font: font.clone(),
+            style: TextStyle {
+                font_size: 20.0,
+                color: Color::rgb(0.8, 0.8, 0.8),
+                ..Default::default()
+            },
+        },
+        ..Default::default()
+    });
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Px(180.), Val::Px(16.)),
+                margin: Rect::all(Val::Px(3.)),
+                ..Default::default()
+            },
+            material: color_materials.add(Color::rgb(0.15, 0.15, 0.15).into()),
+            ..Default::default()
+        })
+        .with(Interaction::default())
+        .with(VolumeSlider)
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(settings.level() * 100.), Val::Percent(100.)),
+                        ..Default::default()
+                    },
+                    material: color_materials.add(Color::rgb(0.35, 0.75, 0.35).into()),
+                    ..Default::default()
+                })
+                .with(VolumeFill);
+        });
+    spawn_button(parent, mute_label(settings), font, button_materials).with(MuteButton);
+}
+
+// Sets the volume from where the slider is held down.
+fn volume_slider(
+    mut cursor_reader: Local<EventReader<CursorMoved>>,
+    cursor_events: Res<Events<CursorMoved>>,
+    mut cursor: Local<Vec2>,
+    mut settings: ResMut<SoundSettings>,
+    query: Query<(&Interaction, &Node, &GlobalTransform), With<VolumeSlider>>,
+) {
+    if let Some(event) = cursor_reader.iter(&cursor_events).last() {
+        *cursor = event.position;
+    }
+    for (interaction, node, transform) in query.iter() {
+        if *interaction != Interaction::Clicked || node.size.x <= 0. {
+            continue;
+        }
+        let left = transform.translation.x - node.size.x / 2.;
+        let volume = ((cursor.x - left) / node.size.x).clamp(0., 1.);
+        if (settings.volume - volume).abs() > f32::EPSILON || settings.muted {
+            settings.volume = volume;
+            settings.muted = false;
+        }
+    }
+}
+
+fn mute_button(
+    mut settings: ResMut<SoundSettings>,
+    query: Query<&Interaction, (Mutated<Interaction>, With<MuteButton>)>,
+) {
+    if query
+        .iter()
+        .any(|interaction| *interaction == Interaction::Clicked)
+    {
+        settings.muted = !settings.muted;
+    }
+}
+
+// Keeps the slider and the mute button's label in step with the settings.
+fn sound_settings_update(
+    settings: ChangedRes<SoundSettings>,
+    mut fill_query: Query<&mut Style, With<VolumeFill>>,
+    mute_query: Query<&Children, With<MuteButton>>,
+    mut text_query: Query<&mut Text>,
+) {
+    for mut style in fill_query.iter_mut() {
+        style.size.width = Val::Percent(settings.level() * 100.);
+    }
+    for children in mute_query.iter() {
+        for child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(*child) {
+                text.value = mute_label(&settings).to_string();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notation::{parse_fen, parse_square};
+
+    // Without Bevy's audio the sound systems still take in the game's
+    // events and pick their effects, then play them nowhere.
+    #[test]
+    fn silent_without_audio() {
+        let mut builder = App::build();
+        builder
+            .add_event::<MoveApplied>()
+            .add_event::<PieceCaptured>()
+            .add_event::<Check>()
+            .add_event::<GameEnded>()
+            .add_event::<CursorMoved>()
+            .init_resource::<Clocks>()
+            .add_plugin(SoundPlugin);
+        let mut app = builder.app;
+        assert!(matches!(*app.resources.get::<SoundBackend>().unwrap(), SoundBackend::Silent));
+
+        let before = parse_fen("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1").unwrap();
+        let (from, to) = (parse_square("e4").unwrap(), parse_square("d5").unwrap());
+        let captured = *before.piece_at(to).unwrap();
+        let mut after = before.clone();
+        let effects = after.make_move(from, to);
+        app.resources.get_mut::<Events<MoveApplied>>().unwrap().send(MoveApplied {
+            color: PieceColor::White,
+            mv: (from, to),
+            san: "exd5".to_string(),
+            effects,
+            before,
+            after,
+        });
+        app.resources
+            .get_mut::<Events<PieceCaptured>>()
+            .unwrap()
+            .send(PieceCaptured { piece: captured });
+        app.update();
+
+        let play_events = app.resources.get::<Events<PlaySound>>().unwrap();
+        let played: Vec<SoundEffect> = play_events
+            .get_reader()
+            .iter(&play_events)
+            .map(|play| play.0)
+            .collect();
+        assert!(played == vec![SoundEffect::Capture]);
+    }
+}
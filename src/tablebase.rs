@@ -0,0 +1,175 @@
+use crate::{ai::Move, notation::to_fen, position::Position, variant::Variant};
+use shakmaty::{fen::Fen, CastlingMode, Chess};
+use shakmaty_syzygy::{Dtz, Tablebase, Wdl};
+use std::{env, path::PathBuf};
+
+// Directories of Syzygy tables (.rtbw/.rtbz) can be given in SYZYGY_PATH,
+// separated as in PATH; otherwise tables are looked for here.
+const DEFAULT_SYZYGY_DIR: &str = "assets/syzygy";
+
+// No Syzygy tables go beyond this many pieces, kings included.
+const MAX_PIECES: usize = 7;
+
+// The Syzygy endgame tables found at startup, if any.
+pub struct Tablebases {
+    tables: Option<Tablebase<Chess>>,
+}
+
+impl Default for Tablebases {
+    fn default() -> Self {
+        let dirs: Vec<PathBuf> = match env::var_os("SYZYGY_PATH") {
+            Some(paths) => env::split_paths(&paths).collect(),
+            None => vec![PathBuf::from(DEFAULT_SYZYGY_DIR)],
+        };
+        Tablebases::open(&dirs)
+    }
+}
+
+impl Tablebases {
+    // The tables in `dirs`; paths that aren't directories, or can't be
+    // read, are passed over.
+    pub fn open(dirs: &[PathBuf]) -> Self {
+        let mut tables = Tablebase::new();
+        let mut found = 0;
+        for dir in dirs.iter().filter(|dir| dir.is_dir()) {
+            found += tables.add_directory(dir).unwrap_or(0);
+        }
+
+        Self {
+            tables: if found > 0 { Some(tables) } else { None },
+        }
+    }
+
+    // Win/draw/loss and distance to zeroing (the next capture or pawn move,
+    // in plies) for the side to move, if the tables cover `position`.
+    pub fn probe(&self, position: &Position) -> Option<(Wdl, Dtz)> {
+        let tables = self.tables.as_ref()?;
+        let chess = to_chess(position)?;
+        let wdl = tables.probe_wdl(&chess).ok()?;
+        let dtz = tables.probe_dtz(&chess).ok()?;
+        Some((wdl, dtz))
+    }
+
+    // The tables' verdict on `position` for the side to move, e.g.
+    // "Win (DTZ 23)". The distance is in plies to the next capture or pawn
+    // move rather than to mate, which is all Syzygy tables know.
+    pub fn verdict(&self, position: &Position) -> Option<String> {
+        let (wdl, Dtz(dtz)) = self.probe(position)?;
+        Some(match wdl {
+            Wdl::Win => format!("Win (DTZ {})", dtz.abs()),
+            Wdl::Loss => format!("Loss (DTZ {})", dtz.abs()),
+            // Won or lost, but not before the fifty-move rule draws it.
+            Wdl::CursedWin | Wdl::BlessedLoss | Wdl::Draw => "Draw".to_string(),
+        })
+    }
+
+    // The move keeping the best result for the side to move: winning as
+    // quickly as possible, or losing as slowly as possible.
+    pub fn best_move(&self, variant: &dyn Variant, position: &Position) -> Option<Move> {
+        // Probe the root first so positions beyond the tables cost nothing more.
+        self.probe(position)?;
+
+        let mut best: Option<((i32, i32), Move)> = None;
+        for mv in variant.legal_moves(position) {
+            let mut next = position.clone();
+            variant.make_move(&mut next, mv.0, mv.1);
+            // Moves leaving the king in check don't make a position the
+            // tables accept, so they drop out here.
+            let (wdl, Dtz(dtz)) = match self.probe(&next) {
+                Some(probe) => probe,
+                None => continue,
+            };
+            // Scores are from the opponent's side, so lower is better for
+            // us; the closer the opponent's DTZ to zero the sooner a won
+            // ending converts, and the further from it a lost one drags on.
+            let score = (-wdl_score(wdl), dtz);
+            if best.is_none_or(|(best_score, _)| score > best_score) {
+                best = Some((score, mv));
+            }
+        }
+        best.map(|(_, mv)| mv)
+    }
+}
+
+fn wdl_score(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Loss => -2,
+        Wdl::BlessedLoss => -1,
+        Wdl::Draw => 0,
+        Wdl::CursedWin => 1,
+        Wdl::Win => 2,
+    }
+}
+
+// The position as shakmaty sees it, for standard chess positions with few
+// enough pieces to be in the tables.
+fn to_chess(position: &Position) -> Option<Chess> {
+    if position.pieces.len() > MAX_PIECES {
+        return None;
+    }
+    let fen: Fen = to_fen(position, false).parse().ok()?;
+    fen.position(CastlingMode::Standard).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        notation::{parse_fen, parse_square, STANDARD_FEN},
+        variant::VariantKind,
+    };
+
+    // KQvK and KRvK tables (.rtbw and .rtbz) for the ignored tests below,
+    // which aren't bundled; see the README there. Run those tests with
+    // `cargo test -- --ignored` once the tables are in place.
+    const TEST_TABLES: &str = "tests/syzygy";
+
+    fn test_tables() -> Tablebases {
+        let tablebases = Tablebases::open(&[PathBuf::from(TEST_TABLES)]);
+        assert!(tablebases.tables.is_some(), "no Syzygy tables in {}", TEST_TABLES);
+        tablebases
+    }
+
+    #[test]
+    fn positions_beyond_the_tables() {
+        let position = parse_fen("8/8/8/4k3/8/8/8/KQ6 w - - 0 1").unwrap();
+        assert!(Tablebases::open(&[]).probe(&position).is_none());
+        assert!(Tablebases::open(&[]).verdict(&position).is_none());
+        assert!(to_chess(&position).is_some());
+        assert!(to_chess(&parse_fen(STANDARD_FEN).unwrap()).is_none());
+    }
+
+    #[test]
+    #[ignore]
+    fn kqvk() {
+        let tablebases = test_tables();
+
+        let position = parse_fen("8/8/8/4k3/8/8/8/KQ6 w - - 0 1").unwrap();
+        let (wdl, Dtz(dtz)) = tablebases.probe(&position).unwrap();
+        assert_eq!(wdl, Wdl::Win);
+        assert!(dtz > 0);
+        assert_eq!(tablebases.verdict(&position), Some(format!("Win (DTZ {})", dtz)));
+
+        let position = parse_fen("8/8/8/4k3/8/8/8/KQ6 b - - 0 1").unwrap();
+        let (wdl, Dtz(dtz)) = tablebases.probe(&position).unwrap();
+        assert_eq!(wdl, Wdl::Loss);
+        assert!(dtz < 0);
+        assert_eq!(tablebases.verdict(&position), Some(format!("Loss (DTZ {})", -dtz)));
+    }
+
+    #[test]
+    #[ignore]
+    fn krvk() {
+        let tablebases = test_tables();
+
+        // Black's king attacks the rook, which has to get away to keep the win.
+        let position = parse_fen("8/8/8/8/8/8/k7/1R2K3 w - - 0 1").unwrap();
+        assert_eq!(tablebases.probe(&position).unwrap().0, Wdl::Win);
+        let variant = VariantKind::Standard.rules();
+        let (from, to) = tablebases.best_move(variant, &position).unwrap();
+        assert_eq!(from, parse_square("b1").unwrap());
+        let mut next = position.clone();
+        variant.make_move(&mut next, from, to);
+        assert_eq!(tablebases.probe(&next).unwrap().0, Wdl::Loss);
+    }
+}
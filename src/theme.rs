@@ -0,0 +1,201 @@
+use crate::{board::MoveTextInput, game::AppState};
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::fs;
+
+// Directory holding one RON file per theme.
+const THEMES_DIR: &str = "assets/themes";
+
+pub struct ThemePlugin;
+impl Plugin for ThemePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Themes>()
+            .add_system(cycle_theme.system());
+    }
+}
+
+// An RGB colour as written in theme files.
+pub type Rgb = (f32, f32, f32);
+
+// Material used for one side's pieces. Bevy's StandardMaterial only has an
+// albedo, so that's all a theme can set; a theme giving anything else (such
+// as metallic or roughness) is rejected rather than silently drawn without
+// it.
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PieceMaterial {
+    pub albedo: Rgb,
+    #[serde(default)]
+    pub texture: Option<String>,
+}
+
+// Colours and materials for the board and pieces.
+#[derive(Clone, Deserialize)]
+pub struct Theme {
+    pub name: String,
+    pub white_square: Rgb,
+    pub black_square: Rgb,
+    #[serde(default)]
+    pub white_square_texture: Option<String>,
+    #[serde(default)]
+    pub black_square_texture: Option<String>,
+    // Square under the mouse.
+    pub hover: Rgb,
+    // Square holding the selected piece.
+    pub selected: Rgb,
+    // Square under the keyboard cursor.
+    pub cursor: Rgb,
+    // Squares the last move came from and went to.
+    #[serde(default = "default_last_move")]
+    pub last_move: Rgb,
+    // Square of a king in check.
+    #[serde(default = "default_check")]
+    pub check: Rgb,
+    pub white_piece: PieceMaterial,
+    pub black_piece: PieceMaterial,
+    // Mixed into the piece colour of fairy pieces drawn with a stand-in mesh.
+    #[serde(default = "default_fairy_tint")]
+    pub fairy_tint: Rgb,
+}
+
+fn default_fairy_tint() -> Rgb {
+    (0.6, 0.2, 0.8)
+}
+
+fn default_last_move() -> Rgb {
+    (0.85, 0.75, 0.2)
+}
+
+fn default_check() -> Rgb {
+    (1., 0., 0.)
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            name: "Classic".to_string(),
+            white_square: (1., 0.9, 0.9),
+            black_square: (0., 0.1, 0.1),
+            white_square_texture: None,
+            black_square_texture: None,
+            hover: (0.8, 0.3, 0.3),
+            selected: (0.9, 0.1, 0.1),
+            cursor: (0.3, 0.5, 0.8),
+            last_move: default_last_move(),
+            check: default_check(),
+            white_piece: PieceMaterial {
+                albedo: (1., 0.8, 0.8),
+                texture: None,
+            },
+            black_piece: PieceMaterial {
+                albedo: (0., 0.2, 0.2),
+                texture: None,
+            },
+            fairy_tint: default_fairy_tint(),
+        }
+    }
+}
+
+pub fn rgb(color: Rgb) -> Color {
+    Color::rgb(color.0, color.1, color.2)
+}
+
+// A colour pulled 40% of the way towards `tint`.
+pub fn tinted(color: Rgb, tint: Rgb) -> Color {
+    rgb(mix(color, tint, 0.4))
+}
+
+// A colour pulled `amount` of the way (0 to 1) towards `other`.
+pub fn mix(color: Rgb, other: Rgb, amount: f32) -> Rgb {
+    let lerp = |a: f32, b: f32| a * (1. - amount) + b * amount;
+    (lerp(color.0, other.0), lerp(color.1, other.1), lerp(color.2, other.2))
+}
+
+// All themes found in the themes directory, and which one is in use.
+pub struct Themes {
+    pub themes: Vec<Theme>,
+    pub current: usize,
+    // Theme files that couldn't be read, and why, for the settings menu.
+    pub skipped: Vec<String>,
+}
+
+impl Themes {
+    pub fn current(&self) -> &Theme {
+        &self.themes[self.current]
+    }
+}
+
+impl Default for Themes {
+    fn default() -> Self {
+        let mut paths: Vec<_> = match fs::read_dir(THEMES_DIR) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "ron"))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        paths.sort();
+
+        let (mut themes, mut skipped) = (Vec::new(), Vec::new());
+        for path in paths {
+            let parsed = fs::read_to_string(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|contents| ron::de::from_str(&contents).map_err(|err| err.to_string()));
+            match parsed {
+                Ok(theme) => themes.push(theme),
+                Err(err) => skipped.push(format!("Skipped {}: {}", path.display(), err)),
+            }
+        }
+
+        // Always have something to draw with.
+        if themes.is_empty() {
+            themes.push(Theme::default());
+        }
+
+        Self { themes, current: 0, skipped }
+    }
+}
+
+// Loads a theme texture from a path relative to the assets directory.
+pub fn load_texture(asset_server: &AssetServer, path: &Option<String>) -> Option<Handle<Texture>> {
+    path.as_ref().map(|path| asset_server.load(path.as_str()))
+}
+
+// Pressing T switches to the next theme.
+fn cycle_theme(
+    keyboard_input: Res<Input<KeyCode>>,
+    move_text: Res<MoveTextInput>,
+    state: Res<State<AppState>>,
+    mut themes: ResMut<Themes>,
+) {
+    // Letters are typed into the move box or the menu's FEN field instead.
+    if move_text.focused || *state.current() == AppState::Menu {
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::T) {
+        themes.current = (themes.current + 1) % themes.themes.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_themes() {
+        for contents in &[
+            include_str!("../assets/themes/classic.ron"),
+            include_str!("../assets/themes/walnut.ron"),
+        ] {
+            assert!(ron::de::from_str::<Theme>(contents).is_ok());
+        }
+    }
+
+    #[test]
+    fn unsupported_material_settings() {
+        let material = "(albedo: (1.0, 0.8, 0.8), metallic: 0.5)";
+        assert!(ron::de::from_str::<PieceMaterial>(material).is_err());
+        let material = "(albedo: (1.0, 0.8, 0.8), texture: Some(\"textures/wood.png\"))";
+        assert!(ron::de::from_str::<PieceMaterial>(material).is_ok());
+    }
+}
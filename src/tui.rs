@@ -0,0 +1,262 @@
+use crate::{
+    ai::computer_move,
+    book::OpeningBook,
+    game::*,
+    history::MoveHistory,
+    moves::play_move,
+    notation::{parse_fen, parse_move, parse_promotion, square_name, to_san_promoting},
+    pieces::*,
+    position::{opponent, Position},
+    tablebase::Tablebases,
+    variant::VariantKind,
+};
+use std::{
+    fmt::Write as _,
+    io::{self, BufRead, Write},
+    time::Instant,
+};
+
+const USAGE: &str = "usage: bevy-chess --tui [--white human|computer] [--black human|computer] \
+                     [--variant NAME] [--time MINUTES+INCREMENT] [--fen FEN]";
+
+// Background colours of the squares, from the terminal's 256 colours.
+const LIGHT_SQUARE: u8 = 180;
+const DARK_SQUARE: u8 = 137;
+const LAST_MOVE_SQUARE: u8 = 143;
+
+// Plies of the game listed under the board.
+const MOVES_SHOWN: usize = 12;
+
+// Plays a game in the terminal: the board is drawn with chess glyphs after
+// every move, and moves are typed in as SAN or UCI. The rules, clocks and
+// computer player are the same as the windowed game's.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let setup = parse_setup(args)?;
+    let variant = setup.variant.rules();
+    let book = OpeningBook::default();
+    let tablebases = Tablebases::default();
+    let mut position = setup.start();
+    let mut clocks = Clocks::start(&setup);
+    let mut history = MoveHistory::new(position.clone());
+    // The human's side is at the bottom, or white's if both sides are human
+    // or both are the computer.
+    let bottom = match (setup.player(PieceColor::White), setup.player(PieceColor::Black)) {
+        (PlayerType::Computer, PlayerType::Human) => PieceColor::Black,
+        _ => PieceColor::White,
+    };
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut message = String::new();
+
+    let result = loop {
+        print!("{}", draw(&position, &history, &clocks, bottom));
+        if !message.is_empty() {
+            println!("{}", message);
+            message.clear();
+        }
+
+        let color = position.side_to_move;
+        let started = Instant::now();
+        let (mv, promotion) = if setup.player(color) == PlayerType::Computer {
+            println!("{} is thinking...", color.name());
+            match computer_move(setup.variant, &book, &tablebases, &position) {
+                Some((mv, promotion)) => (mv, promotion),
+                // Stalemated, or out of moves in a variant where that decides
+                // the game.
+                None => {
+                    break variant.outcome(&position).unwrap_or_else(|| GameResult {
+                        winner: None,
+                        reason: "no legal moves".to_string(),
+                    })
+                }
+            }
+        } else {
+            print!("{} to move (or \"resign\", \"quit\"): ", color.name());
+            io::stdout().flush().map_err(|err| err.to_string())?;
+            let line = match lines.next() {
+                Some(line) => line.map_err(|err| err.to_string())?,
+                None => return Ok(()),
+            };
+            let text = line.trim();
+            match text {
+                "quit" => return Ok(()),
+                "resign" => {
+                    break GameResult {
+                        winner: Some(opponent(color)),
+                        reason: "resignation".to_string(),
+                    }
+                }
+                _ => {}
+            }
+            match parse_move(text, &position) {
+                Some(mv) => (mv, parse_promotion(text)),
+                None => {
+                    message = format!("Couldn't read the move \"{}\"", text);
+                    continue;
+                }
+            }
+        };
+
+        // Time spent on a move counts against the clock, even a move that
+        // turns out to be illegal.
+        if let Some(result) = clocks.run_down(color, started.elapsed().as_secs_f32()) {
+            break result;
+        }
+        let before = position.clone();
+        let effects = match play_move(variant, &mut position, mv.0, mv.1, promotion) {
+            Some(effects) => effects,
+            None => {
+                message = format!("Illegal move: {}{}", square_name(mv.0), square_name(mv.1));
+                continue;
+            }
+        };
+        clocks.moved(color);
+        let san = to_san_promoting(&before, mv, effects.promotion);
+        history.record(&before, mv, san, position.clone());
+
+        if let Some(result) = variant.outcome(&position) {
+            break result;
+        }
+    };
+
+    print!("{}", draw(&position, &history, &clocks, bottom));
+    println!("{}\n", result.describe());
+    print!("{}", history.to_pgn(&setup, &result, &[]));
+    Ok(())
+}
+
+// The setup for a game from the command line; white is a human and black
+// the computer unless told otherwise.
+fn parse_setup(args: &[String]) -> Result<GameSetup, String> {
+    let mut setup = GameSetup { black: PlayerType::Computer, ..Default::default() };
+    let mut flags = args.iter();
+    while let Some(flag) = flags.next() {
+        let value = flags.next().ok_or_else(|| format!("{} needs a value\n{}", flag, USAGE))?;
+        let player = || match value.as_str() {
+            "human" => Ok(PlayerType::Human),
+            "computer" => Ok(PlayerType::Computer),
+            _ => Err(format!("{} is human or computer", flag)),
+        };
+        match flag.as_str() {
+            "--white" => setup.white = player()?,
+            "--black" => setup.black = player()?,
+            "--variant" => {
+                // Names are matched without spaces, e.g. "kingofthehill".
+                let squashed = |name: &str| name.replace(' ', "").to_lowercase();
+                setup.variant = *VariantKind::ALL
+                    .iter()
+                    .find(|kind| squashed(kind.rules().name()) == squashed(value))
+                    .ok_or_else(|| format!("no variant named {}", value))?;
+            }
+            "--time" => {
+                let mut parts = value.splitn(2, '+').map(|part| part.parse().ok());
+                let minutes = parts.next().flatten();
+                let parsed = minutes.zip(parts.next().unwrap_or(Some(0)));
+                let time_control = parsed.ok_or_else(|| format!("invalid time: {}", value))?;
+                setup.time_control = TimeControl(Some(time_control));
+            }
+            "--fen" => {
+                parse_fen(value).ok_or_else(|| format!("invalid FEN: {}", value))?;
+                setup.start_position = StartPosition::Fen(value.clone());
+            }
+            _ => return Err(format!("unknown option {}\n{}", flag, USAGE)),
+        }
+    }
+    Ok(setup)
+}
+
+// Glyph for a piece; the fairy pieces have none, so they're drawn as their
+// FEN letter.
+fn glyph(piece: &Piece) -> char {
+    match piece.piece_type {
+        PieceType::King => '♚',
+        PieceType::Queen => '♛',
+        PieceType::Rook => '♜',
+        PieceType::Bishop => '♝',
+        PieceType::Knight => '♞',
+        PieceType::Pawn => '♟',
+        PieceType::Archbishop => 'A',
+        PieceType::Chancellor => 'C',
+        PieceType::Amazon => 'M',
+        PieceType::Camel => 'L',
+    }
+}
+
+fn clock_text(seconds: f32) -> String {
+    let seconds = seconds.ceil() as u32;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+// The screen after a move: the clocks, the board with `bottom`'s side
+// nearest, and the last few moves.
+fn draw(position: &Position, history: &MoveHistory, clocks: &Clocks, bottom: PieceColor) -> String {
+    let size = position.state.size;
+    let last_move = history.moves.last().map(|played| played.mv);
+    // Clear the screen and start from the top.
+    let mut screen = "\x1b[2J\x1b[H".to_string();
+    // Untimed games have nothing on their clocks.
+    if clocks.white > 0. || clocks.black > 0. {
+        let _ = writeln!(
+            screen,
+            "White {}   Black {}\n",
+            clock_text(clocks.white),
+            clock_text(clocks.black)
+        );
+    }
+
+    let ranks: Vec<u8> = match bottom {
+        PieceColor::White => (0..size.ranks).rev().collect(),
+        PieceColor::Black => (0..size.ranks).collect(),
+    };
+    let files: Vec<u8> = match bottom {
+        PieceColor::White => (0..size.files).collect(),
+        PieceColor::Black => (0..size.files).rev().collect(),
+    };
+    for x in &ranks {
+        let _ = write!(screen, "{:>2} ", x + 1);
+        for y in &files {
+            let square = (*x, *y);
+            let background = match last_move {
+                Some((from, to)) if square == from || square == to => LAST_MOVE_SQUARE,
+                // a1 is dark.
+                _ if (x + y) % 2 == 0 => DARK_SQUARE,
+                _ => LIGHT_SQUARE,
+            };
+            let piece = position.pieces.iter().find(|piece| (piece.x, piece.y) == square);
+            let (foreground, text) = match piece {
+                Some(piece) if piece.color == PieceColor::White => (97, glyph(piece)),
+                Some(piece) => (30, glyph(piece)),
+                None => (30, ' '),
+            };
+            let _ = write!(screen, "\x1b[48;5;{};{}m {} ", background, foreground, text);
+        }
+        screen.push_str("\x1b[0m\n");
+    }
+    screen.push_str("   ");
+    for y in &files {
+        let _ = write!(screen, " {} ", (b'a' + y) as char);
+    }
+    screen.push_str("\n\n");
+
+    let first = history.moves.len().saturating_sub(MOVES_SHOWN);
+    let moves: Vec<String> = history.moves[first..]
+        .iter()
+        .enumerate()
+        .map(|(idx, played)| {
+            let number = history.move_number(first + idx + 1);
+            // Black's moves are numbered only to start the list.
+            if number.ends_with("...") && idx > 0 {
+                played.san.clone()
+            } else {
+                format!("{} {}", number, played.san)
+            }
+        })
+        .collect();
+    if !moves.is_empty() {
+        let _ = writeln!(screen, "{}", moves.join(" "));
+    }
+    if position.in_check(position.side_to_move) {
+        let _ = writeln!(screen, "{} is in check", position.side_to_move.name());
+    }
+    screen
+}
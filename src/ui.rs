@@ -1,15 +1,143 @@
-use crate::{board::*, pieces::*};
-use bevy::prelude::*;
+use crate::{
+    ai::piece_value,
+    board::*,
+    game::*,
+    history::MoveHistory,
+    moves::{Check, PieceCaptured},
+    network::Network,
+    notation::{parse_fen, square_name, to_fen},
+    openings::EcoTable,
+    piece_set::PieceSets,
+    pieces::*,
+    position::{BoardState, Position},
+    puzzle::PuzzleTrainer,
+    sound::{spawn_sound_settings, SoundSettings},
+    tablebase::Tablebases,
+    theme::Themes,
+    variant::VariantKind,
+    MainCamera,
+};
+use bevy::{
+    prelude::*,
+    render::camera::Camera,
+    window::{CursorMoved, ReceivedCharacter},
+};
+use bevy_mod_picking::*;
 
 pub struct UIPlugin;
 impl Plugin for UIPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_startup_system(init_next_move_text.system())
-            .add_system(next_move_text_update.system());
+        app.init_resource::<ButtonMaterials>()
+            .add_startup_system(init_next_move_text.system())
+            .add_startup_system(init_move_text_input.system())
+            .add_startup_system(init_square_tooltip.system())
+            .add_startup_system(init_settings_button.system())
+            .add_system(next_move_text_update.system())
+            .add_system(clock_text_update.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, material_text_update.system())
+            .on_state_exit(STATE_STAGE, AppState::GameOver, clear_material_text.system())
+            .init_resource::<EcoTable>()
+            .on_state_update(STATE_STAGE, AppState::Playing, opening_text_update.system())
+            .on_state_exit(STATE_STAGE, AppState::GameOver, clear_opening_text.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, tablebase_text_update.system())
+            .on_state_exit(STATE_STAGE, AppState::GameOver, clear_tablebase_text.system())
+            .on_state_enter(STATE_STAGE, AppState::Editor, init_board_labels.system())
+            .on_state_exit(STATE_STAGE, AppState::Editor, despawn_screen::<BoardLabel>.system())
+            .on_state_enter(STATE_STAGE, AppState::Playing, init_board_labels.system())
+            .on_state_exit(STATE_STAGE, AppState::GameOver, despawn_screen::<BoardLabel>.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, move_text_focus.system())
+            .on_state_update(STATE_STAGE, AppState::Playing, move_text_typing.system())
+            .add_system(move_text_update.system())
+            .add_system(board_labels_update.system())
+            .add_system(square_tooltip_update.system())
+            .add_system(button_colors.system())
+            .add_system(settings_button.system())
+            .add_system(theme_buttons.system())
+            .add_system(piece_set_buttons.system())
+            .init_resource::<MenuMessage>()
+            .on_state_update(STATE_STAGE, AppState::Menu, menu_buttons.system())
+            .on_state_update(STATE_STAGE, AppState::Menu, menu_fen_typing.system())
+            .on_state_update(STATE_STAGE, AppState::Menu, update_main_menu.system())
+            .on_state_exit(STATE_STAGE, AppState::Menu, despawn_screen::<MainMenu>.system())
+            .on_state_enter(STATE_STAGE, AppState::GameOver, spawn_game_over.system())
+            .on_state_update(STATE_STAGE, AppState::GameOver, game_over_buttons.system())
+            .on_state_exit(STATE_STAGE, AppState::GameOver, despawn_screen::<GameOverScreen>.system());
     }
 }
 
 struct NextMoveText;
+struct ClockText;
+// Material won so far, in pawns, white's captures counting up and black's
+// down.
+#[derive(Default)]
+struct MaterialText {
+    balance: i32,
+}
+struct OpeningText;
+// Tablebase verdict on the position; `fen` is the position it was worked
+// out for, so the tables are only probed when it changes.
+#[derive(Default)]
+struct TablebaseText {
+    fen: String,
+}
+struct MoveInputText;
+struct SquareTooltip;
+struct SettingsButton;
+// Root node of the settings menu, present only while it is open.
+struct SettingsMenu;
+// Selects the theme at this index in `Themes`.
+struct ThemeButton(usize);
+// Selects the piece set at this index in `PieceSets`.
+struct PieceSetButton(usize);
+
+// Root node of the main menu.
+struct MainMenu;
+// Root node of the game-over screen.
+struct GameOverScreen;
+
+// Buttons in the main menu; each option cycles through its values.
+#[derive(Clone, Copy)]
+enum MenuButton {
+    Mode,
+    Variant,
+    TimeControl,
+    StartPosition,
+    White,
+    Black,
+    HumanSide,
+    Editor,
+    Play,
+}
+
+// Buttons on the game-over screen.
+struct BackToMenuButton;
+struct NextPuzzleButton;
+
+// Shown in the main menu when the game can't be started (e.g. a bad FEN).
+#[derive(Default)]
+struct MenuMessage(Option<String>);
+
+pub struct ButtonMaterials {
+    pub normal: Handle<ColorMaterial>,
+    pub hovered: Handle<ColorMaterial>,
+    pub pressed: Handle<ColorMaterial>,
+}
+
+impl FromResources for ButtonMaterials {
+    fn from_resources(resources: &Resources) -> Self {
+        let mut materials = resources.get_mut::<Assets<ColorMaterial>>().unwrap();
+        ButtonMaterials {
+            normal: materials.add(Color::rgb(0.15, 0.15, 0.15).into()),
+            hovered: materials.add(Color::rgb(0.25, 0.25, 0.25).into()),
+            pressed: materials.add(Color::rgb(0.35, 0.55, 0.35).into()),
+        }
+    }
+}
+
+// A file or rank label, anchored to a point in the 3D scene.
+struct BoardLabel {
+    anchor: Vec3,
+}
 
 // Startup system for next-move text.
 fn init_next_move_text(
@@ -26,10 +154,11 @@ fn init_next_move_text(
             style: Style {
                 position_type: PositionType::Absolute,
                 position: Rect {
-                    left Val::Px(10.),
+                    left: Val::Px(10.),
                     top: Val::Px(10.),
                     ..Default::default()
                 },
+                flex_direction: FlexDirection::ColumnReverse,
                 ..Default::default()
             },
             material,
@@ -40,31 +169,1083 @@ fn init_next_move_text(
                 .spawn(TextBundle {
                     text: Text {
                         value: "Next move: White".to_string(),
-                        font,
+                        font: font.clone(),
                         style: TextStyle {
                             font_size: 40.0,
                             color: Color::rgb(0.8, 0.8, 0.8),
+                            ..Default::default()
                         },
                     },
                     ..Default::default()
                 })
                 .with(NextMoveText);
+            parent
+                .spawn(TextBundle {
+                    text: Text {
+                        value: String::new(),
+                        font: font.clone(),
+                        style: TextStyle {
+                            font_size: 28.0,
+                            color: Color::rgb(0.8, 0.8, 0.8),
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                })
+                .with(ClockText);
+            parent
+                .spawn(TextBundle {
+                    text: Text {
+                        value: String::new(),
+                        font: font.clone(),
+                        style: TextStyle {
+                            font_size: 24.0,
+                            color: Color::rgb(0.8, 0.8, 0.8),
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                })
+                .with(MaterialText::default());
+            parent
+                .spawn(TextBundle {
+                    text: Text {
+                        value: String::new(),
+                        font: font.clone(),
+                        style: TextStyle {
+                            font_size: 24.0,
+                            color: Color::rgb(0.8, 0.8, 0.8),
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                })
+                .with(OpeningText);
+            parent
+                .spawn(TextBundle {
+                    text: Text {
+                        value: String::new(),
+                        font,
+                        style: TextStyle {
+                            font_size: 24.0,
+                            color: Color::rgb(0.8, 0.8, 0.8),
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                })
+                .with(TablebaseText::default());
         });
 }
 
-// Updates next-move text to display which color is to move next.
+// Updates next-move text to display which color is to move next, and
+// whether it's in check.
 fn next_move_text_update(
-    // ChangedRes only runs this when PlayerTurn changes.
-    turn: ChangedRes<PlayerTurn>,
+    turn: Res<PlayerTurn>,
+    state: Res<State<AppState>>,
+    mut check_reader: Local<EventReader<Check>>,
+    check_events: Res<Events<Check>>,
+    mut shown: Local<Option<(PieceColor, bool)>>,
     mut query: Query<(&mut Text, &NextMoveText)>,
+) {
+    // A check is announced with the move giving it and lasts until the side
+    // in check has moved, which hands the turn over.
+    let checked = check_reader.iter(&check_events).any(|check| check.color == turn.0);
+    let still_checked = *shown == Some((turn.0, true)) && *state.current() == AppState::Playing;
+    let next = (turn.0, checked || still_checked);
+    if *shown == Some(next) {
+        return;
+    }
+    *shown = Some(next);
+
+    for (mut text, _tag) in query.iter_mut() {
+        text.value = format!("Next move: {}", turn.0.name());
+        if next.1 {
+            text.value.push_str(", in check");
+        }
+    }
+}
+
+// Adds up the material each capture wins, e.g. "Material: White +3".
+fn material_text_update(
+    mut captured_reader: Local<EventReader<PieceCaptured>>,
+    captured_events: Res<Events<PieceCaptured>>,
+    mut query: Query<(&mut Text, &mut MaterialText)>,
+) {
+    let mut won = 0;
+    for captured in captured_reader.iter(&captured_events) {
+        // Taking the king ends the game rather than winning material.
+        if captured.piece.piece_type == PieceType::King {
+            continue;
+        }
+        let value = (piece_value(captured.piece.piece_type) + 50) / 100;
+        won += match captured.piece.color {
+            PieceColor::White => -value,
+            PieceColor::Black => value,
+        };
+    }
+    if won == 0 {
+        return;
+    }
+
+    for (mut text, mut material_text) in query.iter_mut() {
+        material_text.balance += won;
+        text.value = match material_text.balance {
+            0 => "Material: even".to_string(),
+            balance if balance > 0 => format!("Material: White +{}", balance),
+            balance => format!("Material: Black +{}", -balance),
+        };
+    }
+}
+
+fn clear_material_text(mut query: Query<(&mut Text, &mut MaterialText)>) {
+    for (mut text, mut material_text) in query.iter_mut() {
+        text.value.clear();
+        material_text.balance = 0;
+    }
+}
+
+// Shows both clocks as minutes:seconds while a timed game is on, and
+// whether the game is paused.
+fn clock_text_update(
+    clocks: Res<Clocks>,
+    paused: Res<Paused>,
+    state: Res<State<AppState>>,
+    mut query: Query<(&mut Text, &ClockText)>,
+) {
+    let format_clock = |seconds: f32| {
+        let seconds = seconds.ceil() as u32;
+        format!("{:02}:{:02}", seconds / 60, seconds % 60)
+    };
+
+    for (mut text, _tag) in query.iter_mut() {
+        text.value = if clocks.white > 0. || clocks.black > 0. {
+            format!(
+                "White {}  Black {}",
+                format_clock(clocks.remaining(PieceColor::White)),
+                format_clock(clocks.remaining(PieceColor::Black)),
+            )
+        } else {
+            String::new()
+        };
+        if paused.0 && *state.current() == AppState::Playing {
+            if !text.value.is_empty() {
+                text.value.push_str("  ");
+            }
+            text.value.push_str("Paused (P to resume)");
+        }
+    }
+}
+
+// Names the opening being played, e.g. "C50 Italian Game". Once the game
+// leaves the table the last opening it reached stays up.
+fn opening_text_update(
+    setup: Res<GameSetup>,
+    eco: Res<EcoTable>,
+    turn: Res<PlayerTurn>,
+    board_state: Res<BoardState>,
+    history: Res<MoveHistory>,
+    pieces_query: Query<&Piece>,
+    mut query: Query<&mut Text, With<OpeningText>>,
+) {
+    // The table only covers standard chess.
+    if setup.variant != VariantKind::Standard {
+        return;
+    }
+    let position = history.shown_position().cloned().unwrap_or_else(|| Position {
+        pieces: pieces_query.iter().copied().collect(),
+        side_to_move: turn.0,
+        state: board_state.clone(),
+    });
+    if let Some(opening) = eco.opening(&position) {
+        let name = opening.describe();
+        for mut text in query.iter_mut() {
+            if text.value != name {
+                text.value = name.clone();
+            }
+        }
+    }
+}
+
+fn clear_opening_text(mut query: Query<&mut Text, With<OpeningText>>) {
+    for mut text in query.iter_mut() {
+        text.value.clear();
+    }
+}
+
+// Shows the tablebases' verdict once few enough pieces are left, e.g.
+// "Tablebase: Win (DTZ 23)" for the side to move.
+fn tablebase_text_update(
+    setup: Res<GameSetup>,
+    tablebases: Res<Tablebases>,
+    turn: Res<PlayerTurn>,
+    board_state: Res<BoardState>,
+    history: Res<MoveHistory>,
+    pieces_query: Query<&Piece>,
+    mut query: Query<(&mut Text, &mut TablebaseText)>,
+) {
+    if setup.variant != VariantKind::Standard {
+        return;
+    }
+    let position = history.shown_position().cloned().unwrap_or_else(|| Position {
+        pieces: pieces_query.iter().copied().collect(),
+        side_to_move: turn.0,
+        state: board_state.clone(),
+    });
+    let fen = to_fen(&position, false);
+
+    for (mut text, mut tablebase_text) in query.iter_mut() {
+        if tablebase_text.fen == fen {
+            continue;
+        }
+        text.value = tablebases
+            .verdict(&position)
+            .map_or(String::new(), |verdict| format!("Tablebase: {}", verdict));
+        tablebase_text.fen = fen.clone();
+    }
+}
+
+fn clear_tablebase_text(mut query: Query<(&mut Text, &mut TablebaseText)>) {
+    for (mut text, mut tablebase_text) in query.iter_mut() {
+        text.value.clear();
+        tablebase_text.fen.clear();
+    }
+}
+
+// Startup system for the typed-move text box.
+fn init_move_text_input(
+    commands: &mut Commands,
+    asset_server: ResMut<AssetServer>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let material = color_materials.add(Color::rgba(0., 0., 0., 0.5).into());
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.),
+                    bottom: Val::Px(10.),
+                    ..Default::default()
+                },
+                padding: Rect::all(Val::Px(5.)),
+                ..Default::default()
+            },
+            material,
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn(TextBundle {
+                    text: Text {
+                        value: "Press Tab to type a move".to_string(),
+                        font,
+                        style: TextStyle {
+                            font_size: 24.0,
+                            color: Color::rgb(0.8, 0.8, 0.8),
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                })
+                .with(MoveInputText);
+        });
+}
+
+// Tab toggles focus on the move text box; Escape leaves it.
+fn move_text_focus(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut move_text: ResMut<MoveTextInput>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Tab) {
+        move_text.focused = !move_text.focused;
+        move_text.notice = None;
+    } else if keyboard_input.just_pressed(KeyCode::Escape) {
+        move_text.focused = false;
+    }
+}
+
+// Collects typed characters into the move text box while it has focus.
+fn move_text_typing(
+    mut char_reader: Local<EventReader<ReceivedCharacter>>,
+    char_events: Res<Events<ReceivedCharacter>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut move_text: ResMut<MoveTextInput>,
+) {
+    // Drain events even when unfocused so stale input isn't picked up later.
+    let typed: Vec<char> = char_reader.iter(&char_events).map(|event| event.char).collect();
+    if !move_text.focused {
+        return;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        move_text.text.pop();
+    }
+    for c in typed {
+        // Only characters that can appear in SAN or UCI moves.
+        if c.is_ascii_alphanumeric() || "=+#-".contains(c) {
+            move_text.text.push(c);
+            move_text.error = None;
+        }
+    }
+}
+
+// Displays the move being typed, why the last one was rejected, or a notice
+// such as an exported FEN.
+fn move_text_update(
+    move_text: ChangedRes<MoveTextInput>,
+    mut query: Query<(&mut Text, &MoveInputText)>,
 ) {
     for (mut text, _tag) in query.iter_mut() {
-        text.value = format!(
-            "Next move: {}",
-            match turn.0 {
+        text.value = if let Some(error) = &move_text.error {
+            error.clone()
+        } else if move_text.focused {
+            format!("Move: {}_", move_text.text)
+        } else if let Some(notice) = &move_text.notice {
+            notice.clone()
+        } else {
+            "Press Tab to type a move".to_string()
+        };
+    }
+}
+
+// Adds the file (a-h) and rank (1-8) labels around the board edge when a game
+// starts, as many as the board has.
+fn init_board_labels(
+    commands: &mut Commands,
+    asset_server: ResMut<AssetServer>,
+    setup: Res<GameSetup>,
+) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let size = setup.board_size();
+    let (top, right) = (size.ranks as f32 - 0.1, size.files as f32 - 0.1);
+
+    // Squares are unit planes centred on their coordinates, so the board's
+    // edges lie half a square outside the outer squares; labels sit just
+    // beyond them.
+    let mut labels: Vec<(String, Vec3)> = Vec::new();
+    for i in 0..size.files {
+        let file = square_name((0, i))[..1].to_string();
+        labels.push((file.clone(), Vec3::new(-0.9, 0., i as f32)));
+        labels.push((file, Vec3::new(top, 0., i as f32)));
+    }
+    for i in 0..size.ranks {
+        let rank = square_name((i, 0))[1..].to_string();
+        labels.push((rank.clone(), Vec3::new(i as f32, 0., -0.9)));
+        labels.push((rank, Vec3::new(i as f32, 0., right)));
+    }
+
+    for (value, anchor) in labels {
+        commands
+            .spawn(TextBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    ..Default::default()
+                },
+                text: Text {
+                    value,
+                    font: font.clone(),
+                    style: TextStyle {
+                        font_size: 20.0,
+                        color: Color::rgb(0.8, 0.8, 0.8),
+                        ..Default::default()
+                    },
+                },
+                ..Default::default()
+            })
+            .with(BoardLabel { anchor });
+    }
+}
+
+// Keeps the board labels over their anchors, so they follow the camera
+// when the view is flipped.
+fn board_labels_update(
+    windows: Res<Windows>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    mut query: Query<(&mut Style, &BoardLabel)>,
+) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+
+    for (camera, camera_transform) in camera_query.iter() {
+        for (mut style, label) in query.iter_mut() {
+            if let Some(pos) = world_to_screen(camera, camera_transform, window, label.anchor) {
+                // Roughly centre the glyph on its anchor.
+                style.position.left = Val::Px(pos.x - 5.);
+                style.position.bottom = Val::Px(pos.y - 10.);
+            }
+        }
+    }
+}
+
+// Projects a point in the 3D scene to window coordinates (origin bottom-left).
+fn world_to_screen(
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+    window: &Window,
+    world: Vec3,
+) -> Option<Vec2> {
+    let clip = camera.projection_matrix
+        * camera_transform.compute_matrix().inverse()
+        * world.extend(1.);
+    if clip.w <= 0. {
+        // Behind the camera.
+        return None;
+    }
+
+    let ndc = clip.truncate() / clip.w;
+    Some(Vec2::new(
+        (ndc.x + 1.) / 2. * window.width(),
+        (ndc.y + 1.) / 2. * window.height(),
+    ))
+}
+
+// Startup system for the tooltip naming the square under the mouse.
+fn init_square_tooltip(
+    commands: &mut Commands,
+    asset_server: ResMut<AssetServer>,
+) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+
+    commands
+        .spawn(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                ..Default::default()
+            },
+            text: Text {
+                value: String::new(),
+                font,
+                style: TextStyle {
+                    font_size: 24.0,
+                    color: Color::rgb(1., 1., 0.6),
+                    ..Default::default()
+                },
+            },
+            ..Default::default()
+        })
+        .with(SquareTooltip);
+}
+
+// Shows the name of the hovered square (e.g. "e4") next to the mouse.
+fn square_tooltip_update(
+    pick_state: Res<PickState>,
+    cursor_events: Res<Events<CursorMoved>>,
+    mut cursor_reader: Local<EventReader<CursorMoved>>,
+    mut cursor_position: Local<Vec2>,
+    squares_query: Query<&Square>,
+    mut query: Query<(&mut Text, &mut Style, &SquareTooltip)>,
+) {
+    if let Some(event) = cursor_reader.iter(&cursor_events).last() {
+        *cursor_position = event.position;
+    }
+
+    let hovered = pick_state
+        .top(Group::default())
+        .and_then(|(entity, _intersection)| squares_query.get(*entity).ok());
+
+    for (mut text, mut style, _tag) in query.iter_mut() {
+        match hovered {
+            Some(square) => {
+                text.value = square.name();
+                style.position.left = Val::Px(cursor_position.x + 16.);
+                style.position.bottom = Val::Px(cursor_position.y - 8.);
+            }
+            None => text.value.clear(),
+        }
+    }
+}
+
+// Spawns a button with a text label; the caller adds its marker component.
+pub fn spawn_button<'a, 'b>(
+    parent: &'a mut ChildBuilder<'b>,
+    label: &str,
+    font: Handle<Font>,
+    button_materials: &ButtonMaterials,
+) -> &'a mut ChildBuilder<'b> {
+    parent
+        .spawn(ButtonBundle {
+            style: Style {
+                size: Size::new(Val::Px(180.), Val::Px(32.)),
+                margin: Rect::all(Val::Px(3.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: button_materials.normal.clone(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text {
+                    value: label.to_string(),
+                    font,
+                    style: TextStyle {
+                        font_size: 20.0,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                        ..Default::default()
+                    },
+                },
+                ..Default::default()
+            });
+        })
+}
+
+// Startup system for the button opening the settings menu.
+fn init_settings_button(
+    commands: &mut Commands,
+    asset_server: ResMut<AssetServer>,
+    button_materials: Res<ButtonMaterials>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let material = color_materials.add(Color::NONE.into());
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(10.),
+                    top: Val::Px(10.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            material,
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            spawn_button(parent, "Settings", font, &button_materials).with(SettingsButton);
+        });
+}
+
+// Highlights buttons as they are hovered and clicked.
+fn button_colors(
+    button_materials: Res<ButtonMaterials>,
+    mut query: Query<(&Interaction, &mut Handle<ColorMaterial>), (Mutated<Interaction>, With<Button>)>,
+) {
+    for (interaction, mut material) in query.iter_mut() {
+        *material = match *interaction {
+            Interaction::Clicked => button_materials.pressed.clone(),
+            Interaction::Hovered => button_materials.hovered.clone(),
+            Interaction::None => button_materials.normal.clone(),
+        };
+    }
+}
+
+// Opens and closes the settings menu.
+fn settings_button(
+    commands: &mut Commands,
+    asset_server: ResMut<AssetServer>,
+    themes: Res<Themes>,
+    piece_sets: Res<PieceSets>,
+    sound_settings: Res<SoundSettings>,
+    button_materials: Res<ButtonMaterials>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+    button_query: Query<&Interaction, (Mutated<Interaction>, With<SettingsButton>)>,
+    menu_query: Query<Entity, With<SettingsMenu>>,
+) {
+    if !button_query.iter().any(|interaction| *interaction == Interaction::Clicked) {
+        return;
+    }
+
+    // Close the menu if it's already open.
+    let mut closed = false;
+    for menu in menu_query.iter() {
+        commands.despawn_recursive(menu);
+        closed = true;
+    }
+    if closed {
+        return;
+    }
+
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let material = color_materials.add(Color::rgba(0., 0., 0., 0.7).into());
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(10.),
+                    top: Val::Px(50.),
+                    ..Default::default()
+                },
+                // UI is laid out bottom-up, so reverse the column to list top-down.
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: Rect::all(Val::Px(5.)),
+                ..Default::default()
+            },
+            material,
+            ..Default::default()
+        })
+        .with(SettingsMenu)
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text {
+                    value: "Theme (T)".to_string(),
+                    font: font.clone(),
+                    style: TextStyle {
+                        font_size: 20.0,
+                        color: Color::rgb(0.8, 0.8, 0.8),
+                        ..Default::default()
+                    },
+                },
+                ..Default::default()
+            });
+            for (idx, theme) in themes.themes.iter().enumerate() {
+                spawn_button(parent, &theme.name, font.clone(), &button_materials)
+                    .with(ThemeButton(idx));
+            }
+            spawn_skipped(parent, &themes.skipped, font.clone());
+            parent.spawn(TextBundle {
+                text: Text {
+                    value: "Pieces".to_string(),
+                    font: font.clone(),
+                    style: TextStyle {
+                        font_size: 20.0,
+                        color: Color::rgb(0.8, 0.8, 0.8),
+                        ..Default::default()
+                    },
+                },
+                ..Default::default()
+            });
+            for (idx, piece_set) in piece_sets.sets.iter().enumerate() {
+                spawn_button(parent, &piece_set.name, font.clone(), &button_materials)
+                    .with(PieceSetButton(idx));
+            }
+            spawn_skipped(parent, &piece_sets.skipped, font.clone());
+            spawn_sound_settings(
+                parent,
+                font.clone(),
+                &sound_settings,
+                &button_materials,
+                &mut color_materials,
+            );
+        });
+}
+
+// Lists files the settings menu's themes or piece sets were meant to come
+// from but couldn't be read, with the reason.
+fn spawn_skipped(parent: &mut ChildBuilder, skipped: &[String], font: Handle<Font>) {
+    for message in skipped {
+        parent.spawn(TextBundle {
+            text: Text {
+                value: message.clone(),
+                font: font.clone(),
+                style: TextStyle {
+                    font_size: 14.0,
+                    color: Color::rgb(0.9, 0.5, 0.4),
+                    ..Default::default()
+                },
+            },
+            ..Default::default()
+        });
+    }
+}
+
+// Switches theme when one is picked in the settings menu.
+fn theme_buttons(
+    mut themes: ResMut<Themes>,
+    query: Query<(&Interaction, &ThemeButton), Mutated<Interaction>>,
+) {
+    for (interaction, theme_button) in query.iter() {
+        if *interaction == Interaction::Clicked && themes.current != theme_button.0 {
+            themes.current = theme_button.0;
+        }
+    }
+}
+
+// Switches piece set when one is picked in the settings menu.
+fn piece_set_buttons(
+    mut piece_sets: ResMut<PieceSets>,
+    query: Query<(&Interaction, &PieceSetButton), Mutated<Interaction>>,
+) {
+    for (interaction, piece_set_button) in query.iter() {
+        if *interaction == Interaction::Clicked && piece_sets.current != piece_set_button.0 {
+            piece_sets.current = piece_set_button.0;
+        }
+    }
+}
+
+// Despawns the screen rooted at the entity marked with `T`.
+pub fn despawn_screen<T: Send + Sync + 'static>(
+    commands: &mut Commands,
+    query: Query<Entity, With<T>>,
+) {
+    for entity in query.iter() {
+        commands.despawn_recursive(entity);
+    }
+}
+
+// Full-window node centring its children in a column.
+fn screen_node(material: Handle<ColorMaterial>) -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+            position_type: PositionType::Absolute,
+            flex_direction: FlexDirection::ColumnReverse,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        material,
+        ..Default::default()
+    }
+}
+
+// Labels of the main menu's buttons and the text lines below them, given
+// the options chosen so far.
+fn main_menu_contents(
+    setup: &GameSetup,
+    trainer: &PuzzleTrainer,
+    network: &Network,
+    message: &MenuMessage,
+) -> (Vec<(MenuButton, String)>, Vec<String>) {
+    // Puzzles bring their own position and side; there's nothing to set up.
+    if setup.mode == GameMode::Puzzles {
+        let options = vec![
+            (MenuButton::Mode, format!("Mode: {}", setup.mode.name())),
+            (MenuButton::Play, "Play".to_string()),
+        ];
+        let mut lines = vec![trainer.describe_stats()];
+        lines.extend(message.0.clone());
+        return (options, lines);
+    }
+    // Nor is there when joining a network game: the host sets it up.
+    if setup.mode == GameMode::Join {
+        let options = vec![
+            (MenuButton::Mode, format!("Mode: {}", setup.mode.name())),
+            (MenuButton::Play, "Join".to_string()),
+        ];
+        let mut lines = vec![format!("Host address (type to edit): {}_", setup.host_address)];
+        lines.extend(network.status.clone());
+        return (options, lines);
+    }
+
+    let mut options = vec![
+        (MenuButton::Mode, format!("Mode: {}", setup.mode.name())),
+        (MenuButton::Variant, format!("Variant: {}", setup.variant.rules().name())),
+        (MenuButton::TimeControl, format!("Time: {}", setup.time_control.name())),
+        (MenuButton::StartPosition, format!("Start: {}", setup.start_position.name())),
+    ];
+    match setup.mode {
+        GameMode::Local => {
+            options.push((MenuButton::White, format!("White: {}", setup.white.name())));
+            options.push((MenuButton::Black, format!("Black: {}", setup.black.name())));
+        }
+        GameMode::VsComputer | GameMode::Host => {
+            let side = match setup.human_side {
                 PieceColor::White => "White",
                 PieceColor::Black => "Black",
+            };
+            options.push((MenuButton::HumanSide, format!("You play: {}", side)));
+        }
+        GameMode::Analysis | GameMode::Puzzles | GameMode::Join => {}
+    }
+    options.push((MenuButton::Editor, "Position editor".to_string()));
+    options.push((MenuButton::Play, "Play".to_string()));
+
+    // The FEN being typed, and why the game couldn't start.
+    let mut lines = Vec::new();
+    match &setup.start_position {
+        StartPosition::Fen(fen) => lines.push(format!("FEN (type to edit): {}_", fen)),
+        StartPosition::Chess960(Some(index)) => {
+            lines.push(format!("Position number (type to edit): {}_", index))
+        }
+        StartPosition::Chess960(None) => {
+            lines.push("Position number (type to edit): random".to_string())
+        }
+        StartPosition::Standard => {}
+    }
+    if let Some(message) = &message.0 {
+        lines.push(message.clone());
+    }
+    lines.extend(network.status.clone());
+
+    (options, lines)
+}
+
+// Spawns the main menu, and rebuilds it whenever its contents change.
+fn update_main_menu(
+    commands: &mut Commands,
+    asset_server: ResMut<AssetServer>,
+    setup: Res<GameSetup>,
+    trainer: Res<PuzzleTrainer>,
+    network: Res<Network>,
+    message: Res<MenuMessage>,
+    button_materials: Res<ButtonMaterials>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+    mut shown: Local<Vec<String>>,
+    query: Query<Entity, With<MainMenu>>,
+) {
+    let (options, lines) = main_menu_contents(&setup, &trainer, &network, &message);
+    let contents: Vec<String> = options
+        .iter()
+        .map(|(_, label)| label.clone())
+        .chain(lines.iter().cloned())
+        .collect();
+
+    let spawned = query.iter().next().is_some();
+    if spawned && *shown == contents {
+        return;
+    }
+    for entity in query.iter() {
+        commands.despawn_recursive(entity);
+    }
+    *shown = contents;
+
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let material = color_materials.add(Color::rgba(0., 0., 0., 0.8).into());
+
+    commands
+        .spawn(screen_node(material))
+        .with(MainMenu)
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text {
+                    value: "Chess!".to_string(),
+                    font: font.clone(),
+                    style: TextStyle {
+                        font_size: 60.0,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                        ..Default::default()
+                    },
+                },
+                ..Default::default()
+            });
+            for (button, label) in options {
+                spawn_button(parent, &label, font.clone(), &button_materials).with(button);
             }
-        );
+            for line in lines {
+                parent.spawn(TextBundle {
+                    text: Text {
+                        value: line,
+                        font: font.clone(),
+                        style: TextStyle {
+                            font_size: 18.0,
+                            color: Color::rgb(0.8, 0.8, 0.8),
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                });
+            }
+        });
+}
+
+// Cycles a value through `all`, starting over after the last one.
+fn next_of<T: Copy + PartialEq>(all: &[T], current: T) -> T {
+    let idx = all.iter().position(|item| *item == current).unwrap_or(0);
+    all[(idx + 1) % all.len()]
+}
+
+// Sets the next game up as the trainer's next puzzle. Returns false if there
+// are no puzzles to play.
+fn start_puzzle(setup: &mut GameSetup, trainer: &mut PuzzleTrainer) -> bool {
+    match trainer.next_puzzle() {
+        Some(fen) => {
+            setup.variant = VariantKind::Standard;
+            setup.start_position = StartPosition::Fen(fen);
+            setup.human_side = trainer.solver();
+            true
+        }
+        None => false,
+    }
+}
+
+fn menu_buttons(
+    mut setup: ResMut<GameSetup>,
+    mut trainer: ResMut<PuzzleTrainer>,
+    mut network: ResMut<Network>,
+    mut message: ResMut<MenuMessage>,
+    mut state: ResMut<State<AppState>>,
+    query: Query<(&Interaction, &MenuButton), Mutated<Interaction>>,
+) {
+    let toggle_player = |player: PlayerType| match player {
+        PlayerType::Human => PlayerType::Computer,
+        PlayerType::Computer | PlayerType::Remote => PlayerType::Human,
+    };
+
+    for (interaction, button) in query.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+        message.0 = None;
+
+        match button {
+            MenuButton::Mode => setup.mode = next_of(&GameMode::ALL, setup.mode),
+            MenuButton::Variant => setup.variant = next_of(&VariantKind::ALL, setup.variant),
+            MenuButton::TimeControl => {
+                setup.time_control = next_of(&TimeControl::ALL, setup.time_control)
+            }
+            MenuButton::StartPosition => {
+                setup.start_position = match setup.start_position {
+                    StartPosition::Standard => StartPosition::Fen(String::new()),
+                    StartPosition::Fen(_) => StartPosition::Chess960(None),
+                    StartPosition::Chess960(_) => StartPosition::Standard,
+                }
+            }
+            MenuButton::White => setup.white = toggle_player(setup.white),
+            MenuButton::Black => setup.black = toggle_player(setup.black),
+            MenuButton::HumanSide => {
+                setup.human_side = match setup.human_side {
+                    PieceColor::White => PieceColor::Black,
+                    PieceColor::Black => PieceColor::White,
+                }
+            }
+            MenuButton::Play if setup.mode == GameMode::Puzzles => {
+                if start_puzzle(&mut setup, &mut trainer) {
+                    state.set_next(AppState::Playing).unwrap();
+                } else {
+                    message.0 = Some("No puzzles found in assets/puzzles".to_string());
+                }
+            }
+            // The game starts once the other player is there; see `await_opponent`.
+            MenuButton::Play if setup.mode == GameMode::Join => network.join(&setup.host_address),
+            MenuButton::Editor | MenuButton::Play => {
+                if let StartPosition::Fen(fen) = &setup.start_position {
+                    if parse_fen(fen).is_none() {
+                        message.0 = Some("Invalid FEN".to_string());
+                        continue;
+                    }
+                }
+                match button {
+                    MenuButton::Editor => state.set_next(AppState::Editor).unwrap(),
+                    _ if setup.mode == GameMode::Host => network.host(),
+                    _ => state.set_next(AppState::Playing).unwrap(),
+                }
+            }
+        }
+    }
+}
+
+// Types into the main menu's FEN field, its Chess960 position number or the
+// address of the network game to join.
+fn menu_fen_typing(
+    mut char_reader: Local<EventReader<ReceivedCharacter>>,
+    char_events: Res<Events<ReceivedCharacter>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut setup: ResMut<GameSetup>,
+) {
+    let typed: Vec<char> = char_reader.iter(&char_events).map(|event| event.char).collect();
+    let backspace = keyboard_input.just_pressed(KeyCode::Back);
+    if typed.is_empty() && !backspace {
+        return;
+    }
+
+    if setup.mode == GameMode::Join {
+        if backspace {
+            setup.host_address.pop();
+        }
+        setup.host_address.extend(typed.into_iter().filter(|c| c.is_ascii_graphic()));
+        return;
+    }
+    match &mut setup.start_position {
+        StartPosition::Fen(fen) => {
+            if backspace {
+                fen.pop();
+            }
+            for c in typed {
+                if c.is_ascii_graphic() || c == ' ' {
+                    fen.push(c);
+                }
+            }
+        }
+        StartPosition::Chess960(index) => {
+            let mut digits = index.map_or(String::new(), |index| index.to_string());
+            if backspace {
+                digits.pop();
+            }
+            digits.extend(typed.into_iter().filter(|c| c.is_ascii_digit()));
+            // Leaving it empty picks a random position.
+            *index = digits.parse::<u16>().ok().map(|index| index.min(959));
+        }
+        StartPosition::Standard => {}
+    }
+}
+
+fn spawn_game_over(
+    commands: &mut Commands,
+    asset_server: ResMut<AssetServer>,
+    setup: Res<GameSetup>,
+    trainer: Res<PuzzleTrainer>,
+    result: Res<GameResult>,
+    button_materials: Res<ButtonMaterials>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let material = color_materials.add(Color::rgba(0., 0., 0., 0.5).into());
+
+    // A puzzle's result, the rating change and the streak it leaves.
+    let puzzle_result = match trainer.last_result {
+        Some((solved, change)) if setup.mode == GameMode::Puzzles => Some((
+            if solved { "Puzzle solved!" } else { "Wrong move" },
+            format!(
+                "Rating {:.0} ({:+.0}), streak {}",
+                trainer.stats.rating, change, trainer.stats.streak
+            ),
+        )),
+        _ => None,
+    };
+    let title = match &puzzle_result {
+        Some((title, _)) => title.to_string(),
+        None => result.describe(),
+    };
+
+    commands
+        .spawn(screen_node(material))
+        .with(GameOverScreen)
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text {
+                    value: title,
+                    font: font.clone(),
+                    style: TextStyle {
+                        font_size: 50.0,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                        ..Default::default()
+                    },
+                },
+                ..Default::default()
+            });
+            if let Some((_, stats)) = puzzle_result {
+                parent.spawn(TextBundle {
+                    text: Text {
+                        value: stats,
+                        font: font.clone(),
+                        style: TextStyle {
+                            font_size: 24.0,
+                            color: Color::rgb(0.8, 0.8, 0.8),
+                            ..Default::default()
+                        },
+                    },
+                    ..Default::default()
+                });
+                spawn_button(parent, "Next puzzle", font.clone(), &button_materials)
+                    .with(NextPuzzleButton);
+            }
+            spawn_button(parent, "Main menu", font, &button_materials).with(BackToMenuButton);
+        });
+}
+
+fn game_over_buttons(
+    mut state: ResMut<State<AppState>>,
+    mut setup: ResMut<GameSetup>,
+    mut trainer: ResMut<PuzzleTrainer>,
+    menu_query: Query<&Interaction, (Mutated<Interaction>, With<BackToMenuButton>)>,
+    next_query: Query<&Interaction, (Mutated<Interaction>, With<NextPuzzleButton>)>,
+) {
+    if menu_query.iter().any(|interaction| *interaction == Interaction::Clicked) {
+        state.set_next(AppState::Menu).unwrap();
+    } else if next_query.iter().any(|interaction| *interaction == Interaction::Clicked)
+        && start_puzzle(&mut setup, &mut trainer)
+    {
+        state.set_next(AppState::Playing).unwrap();
     }
 }
@@ -0,0 +1,448 @@
+use crate::{
+    ai::{evaluate, Move},
+    game::GameResult,
+    notation::{parse_fen, STANDARD_FEN},
+    pieces::*,
+    position::*,
+};
+
+// The rules of a chess variant. Every method has the standard chess
+// behaviour by default, so a variant only overrides what it changes.
+pub trait Variant: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    // Starting position when not starting from a FEN.
+    fn setup(&self) -> Position {
+        parse_fen(STANDARD_FEN).unwrap()
+    }
+
+    // Piece types that can appear in this variant.
+    fn piece_types(&self) -> Vec<PieceType> {
+        vec![
+            PieceType::King,
+            PieceType::Queen,
+            PieceType::Rook,
+            PieceType::Bishop,
+            PieceType::Knight,
+            PieceType::Pawn,
+        ]
+    }
+
+    // Whether the piece on `from` may move to `to`.
+    fn is_move_valid(&self, position: &Position, from: (u8, u8), to: (u8, u8)) -> bool {
+        position.is_move_valid(from, to)
+    }
+
+    // All moves the side to move may make.
+    fn legal_moves(&self, position: &Position) -> Vec<Move> {
+        let mut moves = Vec::new();
+        for piece in position.pieces.iter().filter(|piece| piece.color == position.side_to_move) {
+            for to in position.state.size.squares() {
+                if self.is_move_valid(position, (piece.x, piece.y), to) {
+                    moves.push(((piece.x, piece.y), to));
+                }
+            }
+        }
+        moves
+    }
+
+    // Plays a move, assumed valid.
+    fn make_move(&self, position: &mut Position, from: (u8, u8), to: (u8, u8)) -> MoveEffects {
+        position.make_move(from, to)
+    }
+
+    // How the game ended, if it has, with `position` reached.
+    fn outcome(&self, position: &Position) -> Option<GameResult> {
+        for color in &[PieceColor::White, PieceColor::Black] {
+            if position.king(*color).is_none() {
+                return Some(win(opponent(*color), "capturing the king"));
+            }
+        }
+        None
+    }
+
+    // Score of `position` from `color`'s point of view, for the computer.
+    fn evaluate(&self, position: &Position, color: PieceColor) -> i32 {
+        evaluate(position, color)
+    }
+}
+
+fn win(winner: PieceColor, reason: &str) -> GameResult {
+    GameResult {
+        winner: Some(winner),
+        reason: reason.to_string(),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum VariantKind {
+    Standard,
+    KingOfTheHill,
+    ThreeCheck,
+    Atomic,
+    Antichess,
+    Horde,
+    Capablanca,
+    Gardner,
+    LosAlamos,
+}
+
+impl VariantKind {
+    pub const ALL: [VariantKind; 9] = [
+        VariantKind::Standard,
+        VariantKind::KingOfTheHill,
+        VariantKind::ThreeCheck,
+        VariantKind::Atomic,
+        VariantKind::Antichess,
+        VariantKind::Horde,
+        VariantKind::Capablanca,
+        VariantKind::Gardner,
+        VariantKind::LosAlamos,
+    ];
+
+    pub fn rules(&self) -> &'static dyn Variant {
+        match self {
+            VariantKind::Standard => &Standard,
+            VariantKind::KingOfTheHill => &KingOfTheHill,
+            VariantKind::ThreeCheck => &ThreeCheck,
+            VariantKind::Atomic => &Atomic,
+            VariantKind::Antichess => &Antichess,
+            VariantKind::Horde => &Horde,
+            VariantKind::Capablanca => &Capablanca,
+            VariantKind::Gardner => &Gardner,
+            VariantKind::LosAlamos => &LosAlamos,
+        }
+    }
+}
+
+pub struct Standard;
+impl Variant for Standard {
+    fn name(&self) -> &'static str {
+        "Standard"
+    }
+}
+
+// Getting your king to one of the centre squares also wins: the middle two
+// ranks and files, or the middle one where there's an odd number.
+pub struct KingOfTheHill;
+impl Variant for KingOfTheHill {
+    fn name(&self) -> &'static str {
+        "King of the Hill"
+    }
+
+    fn outcome(&self, position: &Position) -> Option<GameResult> {
+        let size = position.state.size;
+        let ranks = (size.ranks - 1) / 2..=size.ranks / 2;
+        let files = (size.files - 1) / 2..=size.files / 2;
+        for color in &[PieceColor::White, PieceColor::Black] {
+            if let Some(king) = position.king(*color) {
+                if ranks.contains(&king.x) && files.contains(&king.y) {
+                    return Some(win(*color, "reaching the hill"));
+                }
+            }
+        }
+        Standard.outcome(position)
+    }
+}
+
+// Giving check three times also wins.
+pub struct ThreeCheck;
+impl Variant for ThreeCheck {
+    fn name(&self) -> &'static str {
+        "Three-check"
+    }
+
+    fn make_move(&self, position: &mut Position, from: (u8, u8), to: (u8, u8)) -> MoveEffects {
+        let effects = position.make_move(from, to);
+        // The side that just moved is the one giving check.
+        match position.side_to_move {
+            PieceColor::White if position.in_check(PieceColor::White) => {
+                position.state.checks_given.1 += 1
+            }
+            PieceColor::Black if position.in_check(PieceColor::Black) => {
+                position.state.checks_given.0 += 1
+            }
+            _ => {}
+        }
+        effects
+    }
+
+    fn outcome(&self, position: &Position) -> Option<GameResult> {
+        if position.state.checks_given.0 >= 3 {
+            return Some(win(PieceColor::White, "three checks"));
+        }
+        if position.state.checks_given.1 >= 3 {
+            return Some(win(PieceColor::Black, "three checks"));
+        }
+        Standard.outcome(position)
+    }
+}
+
+// Captures explode, taking the capturing piece and every piece other than
+// a pawn next to the destination with them. Kings can't capture, and no
+// capture may blow up the capturing side's own king.
+pub struct Atomic;
+impl Variant for Atomic {
+    fn name(&self) -> &'static str {
+        "Atomic"
+    }
+
+    fn is_move_valid(&self, position: &Position, from: (u8, u8), to: (u8, u8)) -> bool {
+        let piece = match position.piece_at(from) {
+            Some(piece) => *piece,
+            None => return false,
+        };
+        let capture = position.is_capture(from, to);
+        if piece.piece_type == PieceType::King && capture {
+            return false;
+        }
+        if !position.is_move_valid(from, to) {
+            return false;
+        }
+        if capture {
+            let mut next = position.clone();
+            self.make_move(&mut next, from, to);
+            return next.king(piece.color).is_some();
+        }
+        true
+    }
+
+    fn make_move(&self, position: &mut Position, from: (u8, u8), to: (u8, u8)) -> MoveEffects {
+        let before = position.pieces.clone();
+        let mut effects = position.make_move(from, to);
+        if effects.captured.is_empty() {
+            return effects;
+        }
+
+        // The capturing piece goes too; it was on `from` before the move.
+        effects.captured.push(from);
+        for piece in &before {
+            let pos = (piece.x, piece.y);
+            let adjacent = (piece.x as i8 - to.0 as i8).abs() <= 1
+                && (piece.y as i8 - to.1 as i8).abs() <= 1;
+            if adjacent && piece.piece_type != PieceType::Pawn && pos != from && !effects.captured.contains(&pos) {
+                effects.captured.push(pos);
+            }
+        }
+
+        let blast: Vec<(u8, u8)> = effects
+            .captured
+            .iter()
+            .filter(|pos| **pos != from)
+            .copied()
+            .chain(std::iter::once(effects.destination))
+            .collect();
+        position.pieces.retain(|piece| !blast.contains(&(piece.x, piece.y)));
+
+        effects
+    }
+}
+
+// Whoever loses all their pieces (or has no move) wins. Captures are
+// compulsory, the king is an ordinary piece and there's no castling.
+pub struct Antichess;
+impl Variant for Antichess {
+    fn name(&self) -> &'static str {
+        "Antichess"
+    }
+
+    fn is_move_valid(&self, position: &Position, from: (u8, u8), to: (u8, u8)) -> bool {
+        if position.castling_side(from, to).is_some() || !position.is_move_valid(from, to) {
+            return false;
+        }
+        if position.is_capture(from, to) {
+            return true;
+        }
+
+        // A quiet move is only allowed when no capture is, en passant
+        // included.
+        let color = match position.piece_at(from) {
+            Some(piece) => piece.color,
+            None => return false,
+        };
+        let targets: Vec<(u8, u8)> = position
+            .pieces
+            .iter()
+            .filter(|target| target.color != color)
+            .map(|target| (target.x, target.y))
+            .chain(position.state.en_passant)
+            .collect();
+        !position.pieces.iter().filter(|piece| piece.color == color).any(|piece| {
+            let from = (piece.x, piece.y);
+            targets
+                .iter()
+                .any(|to| position.is_capture(from, *to) && position.is_move_valid(from, *to))
+        })
+    }
+
+    // Whether any capture exists is worked out once for the whole position,
+    // rather than again for every quiet move as `is_move_valid` must.
+    fn legal_moves(&self, position: &Position) -> Vec<Move> {
+        let mut moves = Vec::new();
+        for piece in position.pieces.iter().filter(|piece| piece.color == position.side_to_move) {
+            let from = (piece.x, piece.y);
+            for to in position.state.size.squares() {
+                if position.castling_side(from, to).is_none() && position.is_move_valid(from, to) {
+                    moves.push((from, to));
+                }
+            }
+        }
+        if moves.iter().any(|(from, to)| position.is_capture(*from, *to)) {
+            moves.retain(|(from, to)| position.is_capture(*from, *to));
+        }
+        moves
+    }
+
+    fn outcome(&self, position: &Position) -> Option<GameResult> {
+        for color in &[PieceColor::White, PieceColor::Black] {
+            if !position.pieces.iter().any(|piece| piece.color == *color) {
+                return Some(win(*color, "losing every piece"));
+            }
+        }
+        if self.legal_moves(position).is_empty() {
+            return Some(win(position.side_to_move, "having no moves"));
+        }
+        None
+    }
+
+    fn evaluate(&self, position: &Position, color: PieceColor) -> i32 {
+        -evaluate(position, color)
+    }
+}
+
+// White has 36 pawns and no king against black's usual army. White wins by
+// capturing the king, black by capturing every pawn.
+pub struct Horde;
+impl Variant for Horde {
+    fn name(&self) -> &'static str {
+        "Horde"
+    }
+
+    fn setup(&self) -> Position {
+        parse_fen("rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1").unwrap()
+    }
+
+    fn is_move_valid(&self, position: &Position, from: (u8, u8), to: (u8, u8)) -> bool {
+        // Pawns on white's first rank may also advance two squares.
+        if let Some(piece) = position.piece_at(from) {
+            if piece.piece_type == PieceType::Pawn
+                && piece.color == PieceColor::White
+                && from.0 == 0
+                && to == (2, from.1)
+            {
+                return position.piece_at((1, from.1)).is_none() && position.piece_at(to).is_none();
+            }
+        }
+        position.is_move_valid(from, to)
+    }
+
+    fn outcome(&self, position: &Position) -> Option<GameResult> {
+        if !position.pieces.iter().any(|piece| piece.color == PieceColor::White) {
+            return Some(win(PieceColor::Black, "destroying the horde"));
+        }
+        if position.king(PieceColor::Black).is_none() {
+            return Some(win(PieceColor::White, "capturing the king"));
+        }
+        None
+    }
+}
+
+// Played on a 10x8 board with an archbishop and a chancellor added to each
+// side. The king castles two files towards either rook, as in chess.
+pub struct Capablanca;
+impl Variant for Capablanca {
+    fn name(&self) -> &'static str {
+        "Capablanca"
+    }
+
+    fn setup(&self) -> Position {
+        parse_fen("rnabqkbcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNABQKBCNR w KQkq - 0 1").unwrap()
+    }
+
+    fn piece_types(&self) -> Vec<PieceType> {
+        let mut piece_types = Standard.piece_types();
+        piece_types.extend(&[PieceType::Archbishop, PieceType::Chancellor]);
+        piece_types
+    }
+}
+
+// Gardner's minichess, on 5x5: one of everything, no pawn double step and
+// no castling.
+pub struct Gardner;
+impl Variant for Gardner {
+    fn name(&self) -> &'static str {
+        "Minichess 5x5"
+    }
+
+    fn setup(&self) -> Position {
+        parse_fen("rnbqk/ppppp/5/PPPPP/RNBQK w - - 0 1").unwrap()
+    }
+}
+
+// Los Alamos chess, on 6x6 without bishops, pawn double step or castling.
+pub struct LosAlamos;
+impl Variant for LosAlamos {
+    fn name(&self) -> &'static str {
+        "Los Alamos 6x6"
+    }
+
+    fn setup(&self) -> Position {
+        parse_fen("rnqknr/pppppp/6/6/PPPPPP/RNQKNR w - - 0 1").unwrap()
+    }
+
+    fn piece_types(&self) -> Vec<PieceType> {
+        Standard
+            .piece_types()
+            .into_iter()
+            .filter(|piece_type| *piece_type != PieceType::Bishop)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notation::parse_uci;
+
+    #[test]
+    fn atomic_captures_spare_your_own_king() {
+        let position = parse_fen("4k3/8/8/7p/8/8/3p4/3QK3 w - - 0 1").unwrap();
+        let legal = |uci: &str| {
+            let (from, to) = parse_uci(uci).unwrap();
+            Atomic.is_move_valid(&position, from, to)
+        };
+        // Taking on d2 would blow up the king on e1 along with the pawn,
+        // and the king can't capture at all.
+        assert!(!legal("d1d2"));
+        assert!(!legal("e1d2"));
+        assert!(!Atomic.legal_moves(&position).contains(&parse_uci("d1d2").unwrap()));
+        // Captures away from the king are fine.
+        assert!(legal("d1h5"));
+        assert!(legal("d1a4"));
+    }
+
+    #[test]
+    fn atomic_en_passant_near_the_king() {
+        // Black's pawn has just come to d5; taking it on d6 blows up the
+        // pieces around d6, which would include white's own king on e7.
+        let position = parse_fen("8/4K3/8/2Pp4/8/8/8/k7 w - d6 0 1").unwrap();
+        let (from, to) = parse_uci("c5d6").unwrap();
+        assert!(!Atomic.is_move_valid(&position, from, to));
+        assert!(Standard.is_move_valid(&position, from, to));
+    }
+
+    #[test]
+    fn antichess_en_passant_is_compulsory() {
+        // Black's pawn has just come to d5, and taking it en passant is the
+        // only capture white has.
+        let position = parse_fen("7k/8/8/2Pp4/8/8/8/R7 w - d6 0 1").unwrap();
+        let legal = |uci: &str| {
+            let (from, to) = parse_uci(uci).unwrap();
+            Antichess.is_move_valid(&position, from, to)
+        };
+        assert!(legal("c5d6"));
+        assert!(!legal("a1a2"));
+        assert!(!legal("c5c6"));
+        assert!(Antichess.legal_moves(&position) == vec![parse_uci("c5d6").unwrap()]);
+    }
+}